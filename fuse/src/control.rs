@@ -0,0 +1,379 @@
+//! Control namespace at the mount root.
+//!
+//! Names starting with '.uberallfs.' at the root of a mount are reserved. They are virtual and
+//! never backed by objects:
+//!
+//!  * .uberallfs.status:: read-only file with version, root identifier and object counts
+//!  * .uberallfs.control:: symlink to the control socket of the mount
+//!
+//! The control socket lives in a private directory (mode 0700) below $XDG_RUNTIME_DIR or the
+//! temporary directory. It takes one command per line. Replies are zero or more lines of output
+//! followed by 'ok' or by a single 'error ERRNO MESSAGE' line, ERRNO is 0 unless the failure
+//! was an OS error:
+//!
+//!  * status:: same as the status file
+//!  * id PATH:: the identifier of PATH, relative to the mount root
+//!  * url PATH:: the URL other nodes fetch PATH from
+//!  * chtype TYPE PATH:: converts the object at PATH to TYPE, replies with its new identifier
+//!    (admin)
+//!  * chacl CHANGE KEY SIGNER PATH:: proposes the acl change of PATH, SIGNER must be an admin
+//!    of the object, replies with the message to sign
+//!  * approve KEY SIGNATURE PATH:: signs the pending change of PATH and finalizes it when the
+//!    quorum is reached
+//!  * gc:: runs garbage collection, replies with its report (admin)
+//!  * pin PATH:: keeps PATH alive in garbage collection by adding it to the default pin set
+//!    (admin)
+//!  * challenge:: replies with the message the connecting user has to sign to authenticate
+//!  * auth KEY SIGNATURE:: authenticates KEY for the connecting user with the signature over
+//!    the last challenge, the key stays authenticated until the daemon exits
+//!  * shutdown [lazy]:: unmounts the filesystem, the daemon then flushes its state and exits
+//!    (admin)
+//!
+//! Admin commands are only accepted from the user the daemon runs as, the peer is identified
+//! by its socket credentials.
+use std::ffi::{OsStr, OsString};
+use std::fs::{DirBuilder, Permissions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use objectstore::{
+    DEFAULT_PIN_SET, GcConfig, Identifier, KeyExpirePolicy, ObjectStore, ObjectStoreError,
+    PermissionController, SubObject, UserId, parse_kind,
+};
+use uberall::libc;
+
+use crate::prelude::*;
+
+/// Name of the status file
+pub(crate) const STATUS_FILE: &str = ".uberallfs.status";
+
+/// Name of the symlink to the control socket
+pub(crate) const CONTROL_SOCKET: &str = ".uberallfs.control";
+
+/// Inode numbers of the control entries
+pub(crate) const STATUS_INO: u64 = (1 << 62) | 1;
+pub(crate) const CONTROL_SOCKET_INO: u64 = (1 << 62) | 2;
+
+/// Serves the control socket of a mount, the socket and its directory are removed on drop.
+#[derive(Debug)]
+pub(crate) struct ControlServer {
+    dir:  PathBuf,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Binds the control socket and serves it from a background thread. The socket lives
+    /// outside of the mount since the mountpoint may shadow the objectstore.
+    pub(crate) fn start(
+        objectstore: Arc<ObjectStore>,
+        controller: Arc<PermissionController>,
+        root: Identifier,
+        mountpoint: &Path,
+    ) -> Result<ControlServer> {
+        let mountpoint = mountpoint.to_path_buf();
+        let dir = private_dir(
+            &std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(format!("uberallfs-{}", std::process::id())),
+        )?;
+        let path = dir.join("control");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+        info!("control socket: {:?}", path);
+
+        std::thread::Builder::new()
+            .name(String::from("control"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(err) =
+                                serve(&objectstore, &controller, &root, &mountpoint, stream)
+                            {
+                                warn!("control connection: {}", err);
+                            }
+                        }
+                        Err(err) => warn!("control socket: {}", err),
+                    }
+                }
+            })?;
+
+        Ok(ControlServer { dir, path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+/// Creates the directory 'dir' accessible only by the daemon user. A leftover from an earlier
+/// daemon with the same pid is reused when it is still private to us, anything else is refused.
+fn private_dir(dir: &Path) -> io::Result<PathBuf> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(dir.to_path_buf()),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            let metadata = std::fs::symlink_metadata(dir)?;
+            if metadata.is_dir()
+                && metadata.uid() == unsafe { libc::geteuid() }
+                && metadata.mode() & 0o077 == 0
+            {
+                Ok(dir.to_path_buf())
+            } else {
+                error!("{:?} exists and is not private", dir);
+                Err(io::Error::from_raw_os_error(libc::EEXIST))
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Client side of the control socket, used by the porcelain commands
+pub(crate) struct ControlClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl ControlClient {
+    /// Connects to the control socket of the uberallfs mounted on 'mountpoint'.
+    pub(crate) fn connect(mountpoint: &Path) -> Result<ControlClient> {
+        let writer = UnixStream::connect(mountpoint.join(CONTROL_SOCKET))?;
+        Ok(ControlClient {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    /// Waits until the daemon closes the connection, that is when it exits. Gives up after
+    /// 'timeout'.
+    pub(crate) fn wait_closed(&mut self, timeout: std::time::Duration) -> io::Result<()> {
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Ok(()),
+                Ok(_) => trace!("control: unexpected {:?}", line),
+                Err(err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    warn!("daemon did not exit within {:?}", timeout);
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sends a command, returns its output.
+    pub(crate) fn command(&mut self, command: &str) -> Result<String> {
+        writeln!(self.writer, "{}", command)?;
+
+        let mut output = String::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            match line.trim_end() {
+                "ok" => return Ok(output),
+                reply if reply.starts_with("error ") => {
+                    let mut words = reply[6..].splitn(2, ' ');
+                    return Err(match (words.next().map(str::parse::<i32>), words.next()) {
+                        (Some(Ok(errno)), _) if errno != 0 => io::Error::from_raw_os_error(errno),
+                        (_, Some(message)) => io::Error::new(io::ErrorKind::Other, message),
+                        _ => io::Error::new(io::ErrorKind::Other, &reply[6..]),
+                    }
+                    .into());
+                }
+                _ => output.push_str(&line),
+            }
+        }
+    }
+}
+
+/// Returns the uid of the process on the other side of 'stream'.
+fn peer_uid(stream: &UnixStream) -> io::Result<UserId> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    if unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+fn serve(
+    objectstore: &ObjectStore,
+    controller: &PermissionController,
+    root: &Identifier,
+    mountpoint: &Path,
+    stream: UnixStream,
+) -> io::Result<()> {
+    let uid = peer_uid(&stream)?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        trace!("control command from uid {}: {:?}", uid, line);
+        match command(objectstore, controller, uid, root, mountpoint, line.trim()) {
+            Ok(output) => writeln!(writer, "{}ok", output)?,
+            Err(err) => writeln!(
+                writer,
+                "error {} {}",
+                err.downcast_ref::<io::Error>()
+                    .and_then(io::Error::raw_os_error)
+                    .unwrap_or(0),
+                err
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// Commands only the user running the daemon may send
+const ADMIN_COMMANDS: [&str; 4] = ["gc", "pin", "chtype", "shutdown"];
+
+fn command(
+    objectstore: &ObjectStore,
+    controller: &PermissionController,
+    uid: UserId,
+    root: &Identifier,
+    mountpoint: &Path,
+    line: &str,
+) -> Result<String> {
+    let mut words = line.splitn(2, ' ');
+    let (name, args) = (words.next().unwrap_or_default(), words.next());
+    if ADMIN_COMMANDS.contains(&name) && uid != unsafe { libc::geteuid() } {
+        warn!("control: uid {} is not allowed to {}", uid, name);
+        return Err(io::Error::from_raw_os_error(libc::EPERM).into());
+    }
+    match (name, args) {
+        ("status", None) => Ok(objectstore.status()?.to_string()),
+        ("id", Some(path)) => Ok(format!("{}\n", path_identifier(objectstore, root, path)?)),
+        ("url", Some(path)) => Ok(format!(
+            "{}\n",
+            objectstore.object_url(&path_identifier(objectstore, root, path)?)?
+        )),
+        ("chtype", Some(args)) => match args.splitn(2, ' ').collect::<Vec<_>>()[..] {
+            [kind, path] => {
+                let (parent, name) = path_entry(objectstore, root, path)?;
+                let identifier =
+                    objectstore.change_type(&SubObject(&parent, &name), parse_kind(kind)?)?;
+                Ok(format!("{}\n", identifier))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "usage: chtype TYPE PATH").into()),
+        },
+        ("chacl", Some(args)) => match args.splitn(4, ' ').collect::<Vec<_>>()[..] {
+            [change, key, signer, path] => {
+                let identifier = path_identifier(objectstore, root, path)?;
+                let pending = objectstore.propose_acl_change(&identifier, change, key, signer)?;
+                Ok(pending.message(&identifier))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "usage: chacl CHANGE KEY SIGNER PATH",
+            )
+            .into()),
+        },
+        ("approve", Some(args)) => match args.splitn(3, ' ').collect::<Vec<_>>()[..] {
+            [key, signature, path] => {
+                let identifier = path_identifier(objectstore, root, path)?;
+                let (have, want) = objectstore.approve_perm_change(&identifier, key, signature)?;
+                Ok(format!("signed: {} of {}\n", have, want))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "usage: approve KEY SIGNATURE PATH",
+            )
+            .into()),
+        },
+        ("gc", None) => {
+            let config = GcConfig {
+                concurrent: true,
+                ..GcConfig::default()
+            };
+            let roots = objectstore.gc_roots()?;
+            Ok(objectstore.gc_with(&roots, false, &config)?.to_string())
+        }
+        ("pin", Some(path)) => {
+            objectstore.pin(DEFAULT_PIN_SET, &path_identifier(objectstore, root, path)?)?;
+            Ok(String::new())
+        }
+        ("challenge", None) => Ok(format!(
+            "{}\n",
+            PermissionController::challenge_message(uid, &controller.challenge(uid))
+        )),
+        ("auth", Some(args)) => match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [key, signature] => {
+                controller.authenticate(uid, key, signature, KeyExpirePolicy::Never)?;
+                Ok(String::new())
+            }
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "usage: auth KEY SIGNATURE").into())
+            }
+        },
+        ("shutdown", None) => {
+            crate::mount::unmount(mountpoint, false)?;
+            Ok(String::new())
+        }
+        ("shutdown", Some("lazy")) => {
+            crate::mount::unmount(mountpoint, true)?;
+            Ok(String::new())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown command {:?}", line),
+        )
+        .into()),
+    }
+}
+
+/// Makes 'path' relative to the mount root an objectstore path.
+fn store_path(root: &Identifier, path: &str) -> String {
+    format!("{}//{}", root, path.trim_start_matches('/'))
+}
+
+/// Resolves 'path' relative to the mount root.
+fn path_identifier(objectstore: &ObjectStore, root: &Identifier, path: &str) -> Result<Identifier> {
+    let (identifier, remaining) =
+        objectstore.path_lookup(Path::new(&store_path(root, path)), None)?;
+    if remaining.as_os_str().is_empty() {
+        Ok(identifier)
+    } else {
+        Err(ObjectStoreError::ObjectNotFound(path.into()).into())
+    }
+}
+
+/// Resolves the directory entry 'path' relative to the mount root.
+fn path_entry(
+    objectstore: &ObjectStore,
+    root: &Identifier,
+    path: &str,
+) -> Result<(Identifier, OsString)> {
+    objectstore.existing_entry(OsStr::new(&store_path(root, path)))
+}
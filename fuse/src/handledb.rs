@@ -61,6 +61,22 @@ impl HandleDb {
         }
     }
 
+    /// Syncs all open files and drops all handles, used on shutdown.
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        let mut handles = self.handles.lock();
+        for entry in handles.iter() {
+            if let Valid(handle) = entry {
+                if let Handle::File(file) = &*handle.lock() {
+                    file.sync_all()?;
+                }
+            }
+        }
+        handles.truncate(1);
+        handles[0] = Invalid(0);
+        self.free_idx = 0;
+        Ok(())
+    }
+
     /// Drops a Handle from the database
     pub fn drop(&mut self, fh: u64) -> io::Result<()> {
         let fh = fh as usize;
@@ -1,21 +1,69 @@
 use std::collections::hash_map::HashMap;
+use std::ffi::{OsStr, OsString};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use uberall::parking_lot::Mutex;
-use objectstore::Identifier;
+use objectstore::{Identifier, ObjectStore};
 
 use crate::prelude::*;
+use crate::porcelain::unescape_mountpoint;
+
+/// What an inode refers to
+#[derive(Debug)]
+enum Kind {
+    Object,
+    /// The virtual directory listing the versions of the object
+    Versions,
+    /// A user symlink or special file with this name in the directory object
+    Entry(OsString),
+}
 
 // PLANNED: may become a disk backed implementation since this can become big
 #[derive(Debug)]
 pub(crate) struct Entry {
     identifier: Identifier,
+    kind:       Kind,
+    /// The directory entry an object was last looked up through
+    link:       Option<(Identifier, OsString)>,
+    /// The path from the mounted root an object was last looked up through, unknown for
+    /// entries loaded from an earlier mount
+    path:       Option<PathBuf>,
 }
 
 impl Entry {
     pub(crate) fn as_identifier(&self) -> &Identifier {
         &self.identifier
     }
+
+    /// True for the virtual directory listing the versions of 'identifier'
+    pub(crate) fn is_versions(&self) -> bool {
+        matches!(self.kind, Kind::Versions)
+    }
+
+    /// The directory and name an object was last looked up through, directory rules apply
+    /// to it there.
+    pub(crate) fn link(&self) -> Option<(&Identifier, &OsStr)> {
+        self.link
+            .as_ref()
+            .map(|(parent, name)| (parent, name.as_os_str()))
+    }
+
+    /// The path from the mounted root an object was last looked up through, '..' is resolved
+    /// along it.
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The name of a user symlink or special file in the directory 'identifier'
+    pub(crate) fn entry_name(&self) -> Option<&OsStr> {
+        match &self.kind {
+            Kind::Entry(name) => Some(name),
+            _ => None,
+        }
+    }
 }
 
 /// Relate local inode numbers to uberallfs identifiers
@@ -33,14 +81,161 @@ impl InodeDb {
     }
 
     pub fn store(&mut self, inode: u64, identifier: Identifier) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier,
+            kind: Kind::Object,
+            link: None,
+            path: None,
+        })
+    }
+
+    /// Stores the mounted root, the paths of the objects looked up below start here.
+    pub fn store_root(&mut self, inode: u64, identifier: Identifier) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier,
+            kind: Kind::Object,
+            link: None,
+            path: Some(PathBuf::new()),
+        })
+    }
+
+    /// Stores an object which was looked up as 'name' in the directory 'parent'.
+    pub fn store_linked(
+        &mut self,
+        inode: u64,
+        identifier: Identifier,
+        parent: &Entry,
+        name: &OsStr,
+    ) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier,
+            kind: Kind::Object,
+            link: Some((parent.identifier.clone(), name.into())),
+            path: parent.path().map(|path| path.join(name)),
+        })
+    }
+
+    /// Stores a directory which was reached through 'path' from the mounted root, 'parent' is
+    /// the directory it is linked in there.
+    pub fn store_path(
+        &mut self,
+        inode: u64,
+        identifier: Identifier,
+        parent: &Identifier,
+        path: &Path,
+    ) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier,
+            kind: Kind::Object,
+            link: path.file_name().map(|name| (parent.clone(), name.into())),
+            path: Some(path.into()),
+        })
+    }
+
+    /// Stores the virtual versions directory of a versioned object.
+    pub fn store_versions(&mut self, inode: u64, identifier: Identifier) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier,
+            kind: Kind::Versions,
+            link: None,
+            path: None,
+        })
+    }
+
+    /// Stores the user symlink or special file 'name' in the directory 'parent'.
+    pub fn store_entry(&mut self, inode: u64, parent: Identifier, name: &OsStr) -> Arc<Entry> {
+        self.insert(inode, Entry {
+            identifier: parent,
+            kind:       Kind::Entry(name.into()),
+            link:       None,
+            path:       None,
+        })
+    }
+
+    fn insert(&mut self, inode: u64, entry: Entry) -> Arc<Entry> {
         let mut inode_to_identifier = self.inode_to_identifier.lock();
 
-        let entry = Arc::new(Entry { identifier });
+        let entry = Arc::new(entry);
 
         inode_to_identifier.insert(inode, Arc::clone(&entry));
         entry
     }
 
+    /// Writes all entries as 'objects/inodes' to the objectstore, one per line as 'INODE
+    /// IDENTIFIER' followed by 'versions' or 'entry NAME' for virtual entries. Names are
+    /// escaped, see 'escape()'.
+    pub fn persist(&self, objectstore: &ObjectStore) -> Result<()> {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        for (inode, entry) in self.inode_to_identifier.lock().iter() {
+            match &entry.kind {
+                Kind::Object => writeln!(text, "{} {}", inode, entry.identifier),
+                Kind::Versions => writeln!(text, "{} {} versions", inode, entry.identifier),
+                Kind::Entry(name) => {
+                    writeln!(
+                        text,
+                        "{} {} entry {}",
+                        inode,
+                        entry.identifier,
+                        escape(name)
+                    )
+                }
+            }?;
+        }
+        objectstore.write_state("inodes", text.as_bytes())
+    }
+
+    /// Loads the entries persisted by the last mount. Entries of objects which are gone
+    /// meanwhile are dropped, the directory links are looked up anew.
+    pub fn load(&mut self, objectstore: &ObjectStore) -> Result<()> {
+        let data = match objectstore.read_state("inodes")? {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+
+        let mut loaded = 0;
+        for line in data
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let mut words = line.split(|byte| *byte == b' ');
+            let inode = words
+                .next()
+                .and_then(|inode| std::str::from_utf8(inode).ok())
+                .and_then(|inode| inode.parse::<u64>().ok());
+            let identifier = words
+                .next()
+                .map(|id| objectstore.object_lookup(OsStr::from_bytes(id)));
+            let (inode, identifier) = match (inode, identifier) {
+                (Some(inode), Some(Ok(identifier))) => (inode, identifier),
+                (_, Some(Err(err))) => {
+                    trace!("dropping inode entry {:?}: {}", line, err);
+                    continue;
+                }
+                _ => {
+                    warn!("malformed inode entry: {:?}", OsStr::from_bytes(line));
+                    continue;
+                }
+            };
+            match (words.next(), words.next()) {
+                (None, _) => self.store(inode, identifier),
+                (Some(b"versions"), None) => self.store_versions(inode, identifier),
+                (Some(b"entry"), Some(name)) => {
+                    let name = unescape_mountpoint(name).into_os_string();
+                    self.store_entry(inode, identifier, &name)
+                }
+                _ => {
+                    warn!("malformed inode entry: {:?}", OsStr::from_bytes(line));
+                    continue;
+                }
+            };
+            loaded += 1;
+        }
+        debug!("loaded {} inodes", loaded);
+        Ok(())
+    }
+
     pub fn get(&mut self, inode: u64) -> Option<Arc<Entry>> {
         let inode_to_identifier = self.inode_to_identifier.lock();
         // PLANNED: touch/refresh self.inodedb caches
@@ -49,3 +244,18 @@ impl InodeDb {
             .map(|entry| Arc::clone(entry))
     }
 }
+
+/// Escapes space, tab, newline and backslash as octal like the mount table, non ASCII bytes
+/// as well since names need not be UTF-8. 'unescape_mountpoint()' reverses it.
+fn escape(name: &OsStr) -> String {
+    let mut escaped = String::new();
+    for &byte in name.as_bytes() {
+        match byte {
+            b' ' | b'\t' | b'\n' | b'\\' | 0x80..=0xff => {
+                escaped.push_str(&format!("\\{:03o}", byte))
+            }
+            _ => escaped.push(byte as char),
+        }
+    }
+    escaped
+}
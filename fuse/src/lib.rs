@@ -6,11 +6,16 @@ use crate::prelude::*;
 mod optargs;
 pub use self::optargs::optargs;
 
+mod control;
 mod handledb;
 mod inodedb;
 mod mount;
+mod options;
+mod porcelain;
 mod uberallfs;
 
+pub use options::MountOptions;
+pub use porcelain::{PORCELAIN, porcelain, porcelain_optargs};
 use handledb::HandleDb;
 use inodedb::InodeDb;
 
@@ -23,6 +28,7 @@ pub fn cmd(matches: &ArgMatches) -> Result<()> {
 
     match matches.subcommand() {
         ("mount", Some(sub_m)) => mount::opt_mount(mountpoint, sub_m),
+        ("umount", Some(sub_m)) => mount::opt_umount(mountpoint, sub_m),
         (name, _) => {
             unimplemented!("subcommand '{}'", name)
         }
@@ -1,12 +1,18 @@
-use std::ffi::{OsStr, OsString};
+use std::ffi::{CStr, CString, OsStr, OsString};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 
 use uberall::{
     addy::{self, Signal::*},
     clap::{self, ArgMatches},
+    libc,
 };
 use fuser::MountOption;
 
 use crate::prelude::*;
+use crate::control::ControlClient;
+use crate::options::MountOptions;
 use crate::uberallfs::UberallFS;
 
 pub(crate) fn opt_mount(mountpoint: &OsStr, matches: &ArgMatches) -> Result<()> {
@@ -19,15 +25,36 @@ pub(crate) fn opt_mount(mountpoint: &OsStr, matches: &ArgMatches) -> Result<()>
 
     trace!("objectstore: {:?}", objectstore_dir);
 
+    let mut options = MountOptions::default();
+    if let Some(values) = matches.values_of("options") {
+        options.parse(values)?;
+    }
+    trace!("options: {:?}", options);
+
+    mount(
+        mountpoint,
+        objectstore_dir,
+        matches.is_present("offline"),
+        matches.value_of_os("root").unwrap_or_default(),
+        &options,
+    )
+}
+
+/// Mounts the objectstore at 'objectstore_dir' on 'mountpoint', daemonizes when allowed.
+pub fn mount(
+    mountpoint: &OsStr,
+    objectstore_dir: &Path,
+    offline: bool,
+    root: &OsStr,
+    options: &MountOptions,
+) -> Result<()> {
     uberall::daemon::maybe_daemonize(|tx| {
         let umountpoint = OsString::from(mountpoint);
         addy::mediate(SIGINT)
             .register("unmount", move |_signal| {
-                std::process::Command::new("fusermount")
-                    .arg("-u")
-                    .arg(&umountpoint)
-                    .status()
-                    .expect("umount");
+                if let Err(err) = unmount(Path::new(&umountpoint), false) {
+                    error!("unmount: {}", err);
+                }
             })?
             .enable()?;
 
@@ -39,11 +66,84 @@ pub(crate) fn opt_mount(mountpoint: &OsStr, matches: &ArgMatches) -> Result<()>
                 },
                 tx,
             )
-            .mount(
-                mountpoint.as_ref(),
-                matches.is_present("offline"),
-                matches.value_of_os("root").unwrap_or_default(),
-                None,
-            )
+            .mount(mountpoint.as_ref(), offline, root, options)
     })
 }
+
+pub(crate) fn opt_umount(mountpoint: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let mountpoint = Path::new(mountpoint);
+    let lazy = matches.is_present("lazy");
+
+    // a running daemon unmounts itself and shuts down cleanly, the state is persisted when
+    // it closes the control connection
+    match ControlClient::connect(mountpoint) {
+        Ok(mut control) => {
+            control
+                .command(if lazy { "shutdown lazy" } else { "shutdown" })
+                .map_err(|err| {
+                    if err
+                        .downcast_ref::<io::Error>()
+                        .and_then(io::Error::raw_os_error)
+                        == Some(libc::EBUSY)
+                    {
+                        error!("{:?} is busy, try --lazy", mountpoint);
+                    }
+                    err
+                })?;
+            // lazily unmounted filesystems stay alive while in use
+            if !lazy {
+                control.wait_closed(std::time::Duration::from_secs(60))?;
+            }
+        }
+        Err(err) => {
+            warn!("no control socket, unmounting directly: {}", err);
+            unmount(mountpoint, lazy)?;
+        }
+    }
+    Ok(())
+}
+
+/// Unmounts the filesystem on 'mountpoint', 'lazy' detaches it even when it is still in use.
+/// Privileged callers unmount directly, others through the setuid 'fusermount'.
+pub(crate) fn unmount(mountpoint: &Path, lazy: bool) -> io::Result<()> {
+    let path = CString::new(mountpoint.as_os_str().as_bytes())?;
+    let flags = if lazy { libc::MNT_DETACH } else { 0 };
+    if unsafe { libc::umount2(path.as_ptr(), flags) } == 0 {
+        info!("unmounted {:?}", mountpoint);
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EPERM) => trace!("umount2 not permitted, trying fusermount"),
+        Some(libc::EBUSY) => {
+            error!("{:?} is busy, try --lazy", mountpoint);
+            return Err(err);
+        }
+        _ => return Err(err),
+    }
+
+    let mut command = std::process::Command::new("fusermount");
+    command.arg("-u");
+    if lazy {
+        command.arg("-z");
+    }
+    let output = command.arg(mountpoint).output()?;
+
+    if output.status.success() {
+        info!("unmounted {:?}", mountpoint);
+        Ok(())
+    } else {
+        // fusermount only reports the error message, it runs in our locale
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let busy = unsafe { CStr::from_ptr(libc::strerror(libc::EBUSY)) }.to_string_lossy();
+        if stderr.contains(&*busy) {
+            error!("{:?} is busy, try --lazy", mountpoint);
+            Err(io::Error::from_raw_os_error(libc::EBUSY))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                stderr.trim().to_string(),
+            ))
+        }
+    }
+}
@@ -23,12 +23,21 @@ fn mount_optargs() -> App<'static, 'static> {
                 .long("offline")
                 .help("Start without the network node"),
         )
+        .arg(
+            Arg::with_name("options")
+                .short("o")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("OPTIONS")
+                .help("Comma separated mount options: ro, rw, allow_other, allow_root, default_permissions, auto_unmount, noauto_unmount, attr_timeout=SECS, entry_timeout=SECS, offline_errno=ERRNO"),
+        )
         .arg(
             Arg::with_name("root")
                 .short("r")
                 .long("root")
                 .takes_value(true)
-                .help("Path to an alternative root directory"),
+                .help("Named root or path to an alternative root directory"),
         )
 }
 
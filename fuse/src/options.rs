@@ -0,0 +1,115 @@
+//! Parsing of the '-o' mount options.
+use std::time::Duration;
+
+use fuser::MountOption;
+use objectstore::ObjectStoreError;
+use uberall::libc;
+
+use crate::prelude::*;
+
+/// Options for mounting an uberallfs
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountOptions {
+    /// Options passed on to fuse
+    pub fuse:          Vec<MountOption>,
+    /// How long the kernel may cache attributes
+    pub attr_timeout:  Duration,
+    /// How long the kernel may cache directory entries
+    pub entry_timeout: Duration,
+    /// Error returned for operations that need remote data while offline
+    pub offline_errno: libc::c_int,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        MountOptions {
+            fuse:          vec![
+                MountOption::FSName(String::from("uberallfs")),
+                MountOption::RO,
+                MountOption::AutoUnmount,
+            ],
+            attr_timeout:  Duration::from_secs(600),
+            entry_timeout: Duration::from_secs(600),
+            offline_errno: libc::EHOSTUNREACH,
+        }
+    }
+}
+
+impl MountOptions {
+    /// Parses comma separated options as given to '-o':
+    ///
+    ///  * ro, rw:: mount read-only (default) or read-write
+    ///  * allow_other, allow_root:: allow access by other users or root
+    ///  * default_permissions:: let the kernel check permissions
+    ///  * auto_unmount, noauto_unmount:: unmount when the daemon exits (default)
+    ///  * attr_timeout=SECS, entry_timeout=SECS:: kernel cache timeouts (default 600)
+    ///  * offline_errno=ERRNO:: error for remote operations while offline, a number or one of
+    ///    EHOSTUNREACH (default), ENETUNREACH, ENOTCONN, EAGAIN, EIO, ENOENT
+    pub fn parse<'a>(&mut self, options: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        for option in options
+            .into_iter()
+            .flat_map(|options| options.split(','))
+            .filter(|option| !option.is_empty())
+        {
+            let invalid =
+                || ObjectStoreError::OptArgError(format!("invalid mount option: {:?}", option));
+
+            match option.split_once('=') {
+                None => match option {
+                    "ro" => self.set_access(MountOption::RO),
+                    "rw" => self.set_access(MountOption::RW),
+                    "allow_other" => self.add(MountOption::AllowOther),
+                    "allow_root" => self.add(MountOption::AllowRoot),
+                    "default_permissions" => self.add(MountOption::DefaultPermissions),
+                    "auto_unmount" => self.add(MountOption::AutoUnmount),
+                    "noauto_unmount" => self.fuse.retain(|o| *o != MountOption::AutoUnmount),
+                    _ => return Err(invalid().into()),
+                },
+                Some(("attr_timeout", secs)) => {
+                    self.attr_timeout = Duration::from_secs(secs.parse().map_err(|_| invalid())?)
+                }
+                Some(("entry_timeout", secs)) => {
+                    self.entry_timeout = Duration::from_secs(secs.parse().map_err(|_| invalid())?)
+                }
+                Some(("offline_errno", errno)) => {
+                    self.offline_errno = parse_errno(errno).ok_or_else(invalid)?
+                }
+                Some(_) => return Err(invalid().into()),
+            }
+        }
+
+        if self.fuse.contains(&MountOption::AllowOther)
+            && self.fuse.contains(&MountOption::AllowRoot)
+        {
+            return Err(ObjectStoreError::OptArgError(String::from(
+                "allow_other and allow_root are mutually exclusive",
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    fn set_access(&mut self, access: MountOption) {
+        self.fuse
+            .retain(|o| *o != MountOption::RO && *o != MountOption::RW);
+        self.fuse.push(access);
+    }
+
+    fn add(&mut self, option: MountOption) {
+        if !self.fuse.contains(&option) {
+            self.fuse.push(option);
+        }
+    }
+}
+
+fn parse_errno(errno: &str) -> Option<libc::c_int> {
+    match errno {
+        "EHOSTUNREACH" => Some(libc::EHOSTUNREACH),
+        "ENETUNREACH" => Some(libc::ENETUNREACH),
+        "ENOTCONN" => Some(libc::ENOTCONN),
+        "EAGAIN" => Some(libc::EAGAIN),
+        "EIO" => Some(libc::EIO),
+        "ENOENT" => Some(libc::ENOENT),
+        _ => errno.parse().ok().filter(|errno| *errno > 0),
+    }
+}
@@ -0,0 +1,300 @@
+//! Porcelain commands.
+//!
+//! These work on host paths within a mounted uberallfs. The governing mount is looked up in
+//! the mount table, then the running daemon is asked through its control socket. Thus users
+//! never need to know where the objectstore is hidden behind the mount.
+//!
+//! 'chacl' and 'auth' sign with the secret key from a key file locally, the daemon only ever
+//! sees the signatures.
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use objectstore::{
+    CHTYPE_KINDS, LockingMethod::*, ObjectStore, ObjectStoreError, SharingPolicy, key_id, load_key,
+    sign,
+};
+use uberall::clap::{App, Arg, ArgMatches, SubCommand};
+
+use crate::prelude::*;
+use crate::control::ControlClient;
+use crate::options::MountOptions;
+
+/// Names of the porcelain subcommands
+pub const PORCELAIN: [&str; 8] = [
+    "insta", "show-id", "show-url", "status", "gc", "auth", "chtype", "chacl",
+];
+
+pub fn porcelain_optargs() -> Vec<App<'static, 'static>> {
+    vec![
+        SubCommand::with_name("insta")
+            .about("Initialize or reuse an objectstore and mount it on the same directory")
+            .arg(
+                Arg::with_name("DIR")
+                    .required(true)
+                    .help("The objectstore directory, becomes the mountpoint"),
+            )
+            .arg(
+                Arg::with_name("URL")
+                    .long("from")
+                    .takes_value(true)
+                    .conflicts_with("shared")
+                    .help("Use the root directory from an uberallfs:// URL, the old root is kept"),
+            )
+            .arg(
+                Arg::with_name("shared")
+                    .long("shared")
+                    .requires("KEYFILE")
+                    .help("Create a shared instead a private root directory"),
+            )
+            .arg(
+                Arg::with_name("KEYFILE")
+                    .long("key")
+                    .takes_value(true)
+                    .help("File holding the secret key of the creator of a shared root"),
+            )
+            .arg(
+                Arg::with_name("nomount")
+                    .long("no-mount")
+                    .help("Only prepare the objectstore, do not mount it"),
+            ),
+        SubCommand::with_name("show-id")
+            .about("Show the identifier of an object on a mounted filesystem")
+            .arg(Arg::with_name("PATH").required(true).help("The object")),
+        SubCommand::with_name("show-url")
+            .about("Show the URL other nodes fetch an object on a mounted filesystem from")
+            .arg(Arg::with_name("PATH").required(true).help("The object")),
+        SubCommand::with_name("status")
+            .about("Show the status of a mounted filesystem")
+            .arg(
+                Arg::with_name("PATH")
+                    .required(true)
+                    .help("Any path on the mounted filesystem"),
+            ),
+        SubCommand::with_name("gc")
+            .about("Run garbage collection on a mounted filesystem")
+            .arg(
+                Arg::with_name("PATH")
+                    .required(true)
+                    .help("Any path on the mounted filesystem"),
+            ),
+        SubCommand::with_name("auth")
+            .about("Authenticate a key for the calling user on a mounted filesystem")
+            .arg(
+                Arg::with_name("KEYFILE")
+                    .long("key")
+                    .takes_value(true)
+                    .required(true)
+                    .help("File holding the secret key"),
+            )
+            .arg(
+                Arg::with_name("PATH")
+                    .required(true)
+                    .help("Any path on the mounted filesystem"),
+            ),
+        SubCommand::with_name("chtype")
+            .about("Convert an object on a mounted filesystem to another type")
+            .arg(
+                Arg::with_name("TYPE")
+                    .required(true)
+                    .possible_values(&CHTYPE_KINDS)
+                    .help("The new kind of the object"),
+            )
+            .arg(Arg::with_name("PATH").required(true).help("The object")),
+        SubCommand::with_name("chacl")
+            .about("Grant or withdraw a permission on an object on a mounted filesystem")
+            .arg(
+                Arg::with_name("CHANGE")
+                    .required(true)
+                    .allow_hyphen_values(true)
+                    .help("+PERMISSION or -PERMISSION, PERMISSION may be 'admin'"),
+            )
+            .arg(
+                Arg::with_name("KEY")
+                    .required(true)
+                    .help("The key to change"),
+            )
+            .arg(
+                Arg::with_name("KEYFILE")
+                    .long("sign-with")
+                    .takes_value(true)
+                    .required(true)
+                    .help("File holding the secret key of an admin of the object"),
+            )
+            .arg(Arg::with_name("PATH").required(true).help("The object")),
+    ]
+}
+
+pub fn porcelain(name: &str, matches: &ArgMatches) -> Result<()> {
+    if name == "insta" {
+        return insta(matches);
+    }
+
+    let path = Path::new(matches.value_of_os("PATH").unwrap());
+    let (mountpoint, relative) = find_mount(path)?;
+    trace!("{:?} is {:?} on {:?}", path, relative, mountpoint);
+    let mut control = ControlClient::connect(&mountpoint)?;
+    // the control protocol is line based text
+    let control_path = || -> Result<String> {
+        Ok(format!(
+            "/{}",
+            relative.to_str().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8")
+            })?
+        ))
+    };
+
+    let reply = match name {
+        "show-id" => control.command(&format!("id {}", control_path()?))?,
+        "show-url" => control.command(&format!("url {}", control_path()?))?,
+        "chtype" => control.command(&format!(
+            "chtype {} {}",
+            matches.value_of("TYPE").unwrap(),
+            control_path()?
+        ))?,
+        "chacl" => {
+            let keypair = load_key(Path::new(matches.value_of_os("KEYFILE").unwrap()))?;
+            let signer = key_id(&keypair);
+            let path = control_path()?;
+            let message = control.command(&format!(
+                "chacl {} {} {} {}",
+                matches.value_of("CHANGE").unwrap(),
+                matches.value_of("KEY").unwrap(),
+                signer,
+                path
+            ))?;
+            control.command(&format!(
+                "approve {} {} {}",
+                signer,
+                sign(&keypair, message.as_bytes()),
+                path
+            ))?
+        }
+        "status" => control.command("status")?,
+        "gc" => control.command("gc")?,
+        "auth" => {
+            let keypair = load_key(Path::new(matches.value_of_os("KEYFILE").unwrap()))?;
+            let challenge = control.command("challenge")?;
+            let key = key_id(&keypair);
+            control.command(&format!(
+                "auth {} {}",
+                key,
+                sign(&keypair, challenge.trim_end().as_bytes())
+            ))?;
+            format!("authenticated {}\n", key)
+        }
+        _ => unimplemented!("porcelain '{}'", name),
+    };
+    print!("{}", reply);
+    Ok(())
+}
+
+/// Initializes the objectstore unless it exists, starts the services and mounts the
+/// filesystem over the objectstore directory. A shared root gets the owner of '--key' as its
+/// creator. '--from' makes the object of the URL the root, the old root is kept as named root.
+/// Until the node can fetch objects that object must already be present.
+fn insta(matches: &ArgMatches) -> Result<()> {
+    let dir = matches.value_of_os("DIR").unwrap();
+
+    let objectstore = if let Some(url) = matches.value_of("URL") {
+        // PLANNED: fetch the root through the node
+        let node_missing = |err: Box<dyn std::error::Error>| {
+            ObjectStoreError::OptArgError(format!(
+                "--from {}: {}, fetching needs the node which is not available yet",
+                url, err
+            ))
+        };
+        let objectstore = ObjectStore::open(Path::new(dir), TryLock).map_err(node_missing)?;
+        let root = objectstore.url_lookup(url).map_err(node_missing)?;
+        if let Some(kept) = objectstore.replace_root(&root, None)? {
+            info!("kept the old root as {}", kept);
+        }
+        objectstore
+    } else if matches.is_present("shared") {
+        let objectstore = ObjectStore::open_or_init(Path::new(dir), SharingPolicy::PublicAcl)?;
+        let (root, _) = objectstore.path_lookup(Path::new("/"), None)?;
+        if root.sharing_policy() != SharingPolicy::PublicAcl {
+            return Err(ObjectStoreError::OptArgError(format!(
+                "{:?} has a private root, --shared does not convert it",
+                dir
+            ))
+            .into());
+        }
+        if objectstore.perm_manifest(&root)?.is_none() {
+            let keypair = load_key(Path::new(matches.value_of_os("KEYFILE").unwrap()))?;
+            objectstore.init_creator_perm(&root, &keypair)?;
+        }
+        objectstore
+    } else {
+        ObjectStore::open_or_init(Path::new(dir), SharingPolicy::Private)?
+    };
+
+    // release the lock before mounting
+    drop(objectstore);
+    if matches.is_present("nomount") {
+        return Ok(());
+    }
+
+    // PLANNED: start the node
+    crate::mount::mount(
+        dir,
+        Path::new(dir),
+        true,
+        OsStr::new(""),
+        &MountOptions::default(),
+    )
+}
+
+/// Finds the uberallfs mount governing 'path'. Returns the mountpoint and 'path' relative to
+/// it.
+fn find_mount(path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let path = path.canonicalize()?;
+
+    let mounts = std::fs::read("/proc/self/mounts")?;
+    let mountpoint = mounts
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.split(|byte| *byte == b' ');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(b"uberallfs"), Some(mountpoint), Some(fstype))
+                    if fstype.starts_with(b"fuse") =>
+                {
+                    Some(unescape_mountpoint(mountpoint))
+                }
+                _ => None,
+            }
+        })
+        .filter(|mountpoint| path.starts_with(mountpoint))
+        .max_by_key(|mountpoint| mountpoint.as_os_str().len())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} is not on a mounted uberallfs", path),
+            )
+        })?;
+
+    let relative = path.strip_prefix(&mountpoint)?.to_path_buf();
+    Ok((mountpoint, relative))
+}
+
+/// The mount table escapes space, tab, newline and backslash as octal.
+pub(crate) fn unescape_mountpoint(escaped: &[u8]) -> PathBuf {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        match escaped.get(i + 1..i + 4) {
+            Some(octal)
+                if escaped[i] == b'\\' && octal.iter().all(|c| (b'0'..=b'7').contains(c)) =>
+            {
+                bytes.push(octal.iter().fold(0u8, |n, c| (n << 3) | (c - b'0')));
+                i += 4;
+            }
+            _ => {
+                bytes.push(escaped[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsStr::from_bytes(&bytes))
+}
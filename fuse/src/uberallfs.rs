@@ -1,24 +1,53 @@
-use std::ffi::OsStr;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::io::{Seek, SeekFrom};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use uberall::libc;
 use uberall::daemon;
-use objectstore::{Identifier, ObjectType, VirtualFileSystem};
+use objectstore::{
+    Handle, Identifier, Mutability, ObjectStoreError, ObjectType, SpecialFile, VirtualFileSystem,
+};
 use fuser::{
-    FileAttr, FileType, Filesystem, KernelConfig, MountOption, ReplyAttr, ReplyData,
-    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, Request,
+    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, ReplyXattr, Request,
 };
 
 use crate::prelude::*;
-use crate::{HandleDb, InodeDb};
+use crate::control::{CONTROL_SOCKET, CONTROL_SOCKET_INO, ControlServer, STATUS_FILE, STATUS_INO};
+use crate::inodedb::Entry;
+use crate::{HandleDb, InodeDb, MountOptions};
+
+/// Name of the virtual directory in versioned directories which lists their snapshots. The
+/// snapshots of a versioned file 'NAME' are listed in '.uberallfs.versions.NAME' next to it.
+const VERSIONS_DIR: &str = ".uberallfs.versions";
+
+/// Flag for the inode numbers of virtual versions directories
+const VERSIONS_INO: u64 = 1 << 63;
 
 pub struct UberallFS {
     vfs:      VirtualFileSystem,
     inodedb:  InodeDb,
     handledb: HandleDb,
     callback: daemon::Callback,
+    control:  Option<ControlServer>,
+    /// Directory entries of the files opened for writing, by file handle
+    written:  HashMap<u64, (Identifier, OsString)>,
+    /// Files created in drop-boxes, they are added as anonymous objects when closed, by file
+    /// handle
+    staged:   HashMap<u64, (Identifier, OsString)>,
+    /// Content of the status file, refreshed on every lookup
+    status:   String,
+    /// Cache timeout for entries and attributes
+    ttl:      Duration,
 }
 
 impl fmt::Debug for UberallFS {
@@ -28,6 +57,7 @@ impl fmt::Debug for UberallFS {
             .field("inodedb", &self.inodedb)
             .field("handledb", &self.handledb)
             .field("callback.is_some()", &self.callback.is_some())
+            .field("control", &self.control)
             .finish()
     }
 }
@@ -39,6 +69,11 @@ impl UberallFS {
             inodedb:  InodeDb::new()?,
             handledb: HandleDb::with_capacity(1024)?,
             callback: daemon::Callback::default(),
+            control:  None,
+            written:  HashMap::new(),
+            staged:   HashMap::new(),
+            status:   String::new(),
+            ttl:      Duration::from_secs(600),
         })
     }
 
@@ -57,26 +92,309 @@ impl UberallFS {
     pub fn mount(
         mut self,
         mountpoint: &Path,
-        _offline_todo: bool,
+        offline: bool,
         root: &OsStr,
-        _options_planned: Option<Vec<String>>,
+        options: &MountOptions,
     ) -> Result<()> {
-        let mut options = vec![
-            MountOption::RO,
-            MountOption::FSName("uberallfs".to_string()),
-        ];
-        options.push(MountOption::AutoUnmount); //TODO: optarg?
+        // fuse replies carry a single timeout for entries and their attributes
+        self.ttl = options.entry_timeout.min(options.attr_timeout);
+        if offline {
+            self.vfs.set_offline(Some(options.offline_errno));
+        }
 
-        let identifier = self.vfs.path_lookup(0, Path::new(root))?;
+        let identifier = self.vfs.mount_root(0, root)?;
+        identifier.ensure_root()?;
 
-        self.inodedb.store(1, identifier);
+        self.control = Some(ControlServer::start(
+            self.vfs.objectstore(),
+            self.vfs.permission_controller(),
+            identifier.clone(),
+            mountpoint,
+        )?);
+        // stale entries only cost memory, a broken database must not prevent mounting
+        if let Err(err) = self.inodedb.load(&self.vfs.objectstore()) {
+            warn!("loading inodes: {}", err);
+        }
+        self.inodedb.store_root(1, identifier);
         // FIXME: for the real metadata/ino, make '1' a special case UberallFS::root_ino
-        fuser::mount2(&mut self, mountpoint, &options).map_err(|err| {
+        fuser::mount2(&mut self, mountpoint, &options.fuse).map_err(|err| {
             error!("mounting filesystem: {:?}", err);
             self.callback_once(daemon::CallbackMessage::from_io_error(&err));
             err.into()
         })
     }
+
+    /// Returns the object 'ino' refers to for extended attribute requests.
+    fn xattr_object(
+        &mut self,
+        ino: u64,
+        name: Option<&OsStr>,
+    ) -> std::result::Result<Identifier, libc::c_int> {
+        if name.map_or(false, |name| name.to_str().is_none()) {
+            return Err(libc::EOPNOTSUPP);
+        }
+        match self.inodedb.get(ino) {
+            Some(entry) if entry.is_versions() || entry.entry_name().is_some() => {
+                Err(libc::EOPNOTSUPP)
+            }
+            Some(entry) => Ok(entry.as_identifier().clone()),
+            None => Err(libc::ENOENT),
+        }
+    }
+
+    /// Calls 'f' with the file behind the file handle 'fh'.
+    fn with_file<T>(
+        &mut self,
+        fh: u64,
+        f: impl FnOnce(&std::fs::File) -> io::Result<T>,
+    ) -> std::result::Result<T, libc::c_int> {
+        let handle = self.handledb.get(fh).ok_or(libc::EBADF)?;
+        let handle = handle.lock();
+        match &*handle {
+            Handle::File(file) => f(file).map_err(|err| err.raw_os_error().unwrap_or(libc::EIO)),
+            _ => Err(libc::EISDIR),
+        }
+    }
+
+    /// Creates the file 'name' in the drop-box 'parent'. It is written to an unnamed staging
+    /// file, 'add_anonymous()' adds it when closed.
+    fn create_anonymous(
+        &mut self,
+        req: &Request<'_>,
+        parent: &Identifier,
+        name: &OsStr,
+        reply: ReplyCreate,
+    ) {
+        let file = match self.vfs.create_anonymous(req.uid(), parent, name) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!("create {:?}: {}", name, err);
+                return reply.error(error_to_errno(&*err));
+            }
+        };
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(file.as_raw_fd(), &mut stat) } == -1 {
+            return reply.error(
+                io::Error::last_os_error()
+                    .raw_os_error()
+                    .unwrap_or(libc::EIO),
+            );
+        }
+
+        let fh = self.handledb.store(Handle::File(file));
+        self.staged.insert(fh, (parent.clone(), name.into()));
+        reply.created(
+            &self.ttl,
+            &stat_to_fileattr(&stat, FileType::RegularFile),
+            0, // TODO: generation
+            fh,
+            0,
+        )
+    }
+
+    /// Adds the staging file of a drop-box file as anonymous object, see
+    /// 'create_anonymous()'. Writes after this are not added anymore.
+    fn add_anonymous(
+        &mut self,
+        req: &Request<'_>,
+        fh: u64,
+        parent: &Identifier,
+        name: &OsStr,
+        reply: ReplyEmpty,
+    ) {
+        let source = match self.with_file(fh, |file| {
+            let mut source = file.try_clone()?;
+            source.seek(SeekFrom::Start(0))?;
+            Ok(source)
+        }) {
+            Ok(source) => source,
+            Err(errno) => return reply.error(errno),
+        };
+        match self
+            .vfs
+            .add_anonymous(Some(req.uid()), parent, name, source)
+        {
+            Ok(identifier) => {
+                debug!("added {:?} as {}", name, identifier);
+                reply.ok()
+            }
+            Err(err) => {
+                warn!("add {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    /// Looks up the reserved names at the root of the mount, these are never objects.
+    fn lookup_control(&mut self, req: &Request<'_>, name: &OsStr, reply: ReplyEntry) {
+        let attr = if name == STATUS_FILE {
+            self.status = match self.vfs.objectstore().status() {
+                Ok(status) => status.to_string(),
+                Err(err) => {
+                    warn!("status: {}", err);
+                    return reply.error(error_to_errno(&*err));
+                }
+            };
+            virtual_attr(
+                req,
+                STATUS_INO,
+                FileType::RegularFile,
+                0o444,
+                self.status.len() as u64,
+            )
+        } else if name == CONTROL_SOCKET {
+            match &self.control {
+                Some(control) => virtual_attr(
+                    req,
+                    CONTROL_SOCKET_INO,
+                    FileType::Symlink,
+                    0o777,
+                    control.path().as_os_str().len() as u64,
+                ),
+                None => return reply.error(libc::ENOENT),
+            }
+        } else {
+            return reply.error(libc::ENOENT);
+        };
+        reply.entry(&Duration::from_secs(1), &attr, 0)
+    }
+
+    /// Traverses the path 'entry' was looked up through again from the mounted root. Returns
+    /// the directories leading to it, starting with the mounted root.
+    fn entry_parents(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        entry: &Entry,
+    ) -> std::result::Result<Vec<Identifier>, libc::c_int> {
+        if ino == 1 {
+            return Ok(Vec::new());
+        }
+        let root = self.inodedb.get(1).ok_or(libc::ENOENT)?;
+        let path = entry.path().ok_or_else(|| {
+            trace!("parents of {}: path unknown", ino);
+            libc::ENOENT
+        })?;
+
+        let mut lookup_path = OsString::from(format!("{}//", root.as_identifier()));
+        lookup_path.push(path);
+        match self
+            .vfs
+            .path_lookup_with_parents(req.uid(), Path::new(&lookup_path))
+        {
+            Ok((identifier, parents)) if identifier == *entry.as_identifier() => Ok(parents),
+            Ok(_) => {
+                trace!("parents of {}: {:?} was replaced", ino, path);
+                Err(libc::ENOENT)
+            }
+            Err(err) => Err(error_to_errno(&*err)),
+        }
+    }
+
+    /// Looks up '..' of the directory 'entry'. Directories have no implicit parent, it is the
+    /// one 'entry' was looked up through, the last directory of the chain leading there.
+    fn lookup_parent(&mut self, req: &Request<'_>, ino: u64, entry: &Entry, reply: ReplyEntry) {
+        let root = match self.inodedb.get(1) {
+            Some(root) => root,
+            None => return reply.error(libc::ENOENT),
+        };
+        let parents = match self.entry_parents(req, ino, entry) {
+            Ok(parents) => parents,
+            Err(errno) => return reply.error(errno),
+        };
+
+        // the chain starts with the mounted root, nothing above it is reachable
+        let (parent, grandparent, path) = match (parents.as_slice(), entry.path()) {
+            ([.., grandparent, parent], Some(path)) => (parent, grandparent, path),
+            _ => return self.lookup_root(req, root.as_identifier(), reply),
+        };
+        let parent_path = path.parent().unwrap_or(path);
+        match self.vfs.metadata(req.uid(), parent) {
+            Ok(metadata) => {
+                self.inodedb.store_path(
+                    metadata.stat().st_ino,
+                    parent.clone(),
+                    grandparent,
+                    parent_path,
+                );
+                reply.entry(
+                    &self.ttl,
+                    &stat_to_fileattr(metadata.stat(), identifier_to_filetype(parent)),
+                    0, // TODO: generation
+                )
+            }
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Replies with the mounted root as inode 1.
+    fn lookup_root(&mut self, req: &Request<'_>, identifier: &Identifier, reply: ReplyEntry) {
+        match self.vfs.metadata(req.uid(), identifier) {
+            Ok(metadata) => {
+                let mut attr =
+                    stat_to_fileattr(metadata.stat(), identifier_to_filetype(identifier));
+                attr.ino = 1;
+                reply.entry(&self.ttl, &attr, 0)
+            }
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Replies with the virtual versions directory of 'identifier', its inode number is
+    /// derived from 'ino'.
+    fn lookup_versions(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        identifier: Identifier,
+        reply: ReplyEntry,
+    ) {
+        match self.vfs.metadata(req.uid(), &identifier) {
+            Ok(metadata) => {
+                let ino = ino | VERSIONS_INO;
+                let mut attr = stat_to_fileattr(metadata.stat(), FileType::Directory);
+                attr.ino = ino;
+                attr.perm = 0o555;
+                self.inodedb.store_versions(ino, identifier);
+                reply.entry(&self.ttl, &attr, 0)
+            }
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Looks up a snapshot by its number in the versions directory of 'identifier'.
+    fn lookup_version(
+        &mut self,
+        req: &Request<'_>,
+        identifier: &Identifier,
+        name: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let number = match name.to_str().and_then(|name| name.parse().ok()) {
+            Some(number) => number,
+            None => return reply.error(libc::ENOENT),
+        };
+
+        match self
+            .vfs
+            .version(req.uid(), identifier, number)
+            .and_then(|sub_id| Ok((self.vfs.metadata(req.uid(), &sub_id)?, sub_id)))
+        {
+            Ok((metadata, sub_id)) => {
+                let entry = self.inodedb.store(metadata.stat().st_ino, sub_id);
+                let sub_id = entry.as_identifier();
+                reply.entry(
+                    &self.ttl,
+                    &stat_to_fileattr(metadata.stat(), identifier_to_filetype(sub_id)),
+                    0, // TODO: generation
+                )
+            }
+            Err(err) => {
+                trace!("version {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
 }
 
 impl Filesystem for &mut UberallFS {
@@ -92,6 +410,17 @@ impl Filesystem for &mut UberallFS {
         Ok(())
     }
 
+    fn destroy(&mut self) {
+        trace!("destroy filesystem");
+        if let Err(err) = self.handledb.flush_all() {
+            error!("flushing handles: {}", err);
+        }
+        if let Err(err) = self.inodedb.persist(&self.vfs.objectstore()) {
+            error!("persisting inodes: {}", err);
+        }
+        self.control = None;
+    }
+
     fn access(&mut self, req: &Request<'_>, ino: u64, mode: i32, reply: ReplyEmpty) {
         if let Some(entry) = self.inodedb.get(ino) {
             if let Ok(()) = self.vfs.access(req.uid(), entry.as_identifier(), mode) {
@@ -108,22 +437,592 @@ impl Filesystem for &mut UberallFS {
 
     fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
         if let Some(entry) = self.inodedb.get(parent) {
-            if let Ok(sub_id) = self.vfs.sub_lookup(req.uid(), entry.as_identifier(), name) {
-                trace!("sub_id: {:?}", sub_id);
-                if let Ok(metadata) = self.vfs.metadata(req.uid(), &sub_id) {
-                    let entry = self.inodedb.store(metadata.stat().st_ino, sub_id);
+            if entry.is_versions() {
+                return self.lookup_version(req, entry.as_identifier(), name, reply);
+            }
+            if name == ".." {
+                return self.lookup_parent(req, parent, &entry, reply);
+            }
+            if parent == 1
+                && !name.as_bytes().starts_with(VERSIONS_DIR.as_bytes())
+                && name.as_bytes().starts_with(&objectstore::RESERVED_PREFIX)
+            {
+                return self.lookup_control(req, name, reply);
+            }
+            if name == VERSIONS_DIR
+                && entry.as_identifier().mutability() == Mutability::Versioned
+                && entry.as_identifier().object_type().is_directory()
+            {
+                return self.lookup_versions(req, parent, entry.as_identifier().clone(), reply);
+            }
+            if let Some(file_name) = name
+                .as_bytes()
+                .strip_prefix(VERSIONS_DIR.as_bytes())
+                .and_then(|rest| rest.strip_prefix(b"."))
+            {
+                return match self.vfs.sub_lookup(
+                    req.uid(),
+                    entry.as_identifier(),
+                    OsStr::from_bytes(file_name),
+                ) {
+                    Ok(sub_id) if sub_id.mutability() == Mutability::Versioned => {
+                        match self.vfs.metadata(req.uid(), &sub_id) {
+                            Ok(metadata) => {
+                                self.lookup_versions(req, metadata.stat().st_ino, sub_id, reply)
+                            }
+                            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+                        }
+                    }
+                    _ => reply.error(libc::ENOENT),
+                };
+            }
+            match self.vfs.sub_lookup(req.uid(), entry.as_identifier(), name) {
+                Ok(sub_id) => {
+                    trace!("sub_id: {:?}", sub_id);
+                    if let Ok(metadata) = self.vfs.metadata(req.uid(), &sub_id) {
+                        let entry =
+                            self.inodedb
+                                .store_linked(metadata.stat().st_ino, sub_id, &entry, name);
+                        let sub_id = entry.as_identifier();
+                        return reply.entry(
+                            &self.ttl,
+                            &stat_to_fileattr(metadata.stat(), identifier_to_filetype(sub_id)),
+                            0, // TODO: generation
+                        );
+                    }
+                }
+                Err(err)
+                    if matches!(
+                        err.downcast_ref(),
+                        Some(ObjectStoreError::IsSymlink(_) | ObjectStoreError::SpecialFile(_))
+                    ) =>
+                {
+                    if let Ok(metadata) =
+                        self.vfs
+                            .entry_metadata(req.uid(), entry.as_identifier(), name)
+                    {
+                        self.inodedb.store_entry(
+                            metadata.stat().st_ino,
+                            entry.as_identifier().clone(),
+                            name,
+                        );
+                        return reply.entry(
+                            &self.ttl,
+                            &stat_to_fileattr(metadata.stat(), mode_to_filetype(metadata.stat())),
+                            0, // TODO: generation
+                        );
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+        reply.error(libc::ENOENT);
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        if let Some(entry) = self.inodedb.get(parent) {
+            match self
+                .vfs
+                .mkdir(req.uid(), entry.as_identifier(), name)
+                .and_then(|sub_id| Ok((self.vfs.metadata(req.uid(), &sub_id)?, sub_id)))
+            {
+                Ok((metadata, sub_id)) => {
+                    let entry =
+                        self.inodedb
+                            .store_linked(metadata.stat().st_ino, sub_id, &entry, name);
                     let sub_id = entry.as_identifier();
                     return reply.entry(
-                        &Duration::from_secs(600),
+                        &self.ttl,
                         &stat_to_fileattr(metadata.stat(), identifier_to_filetype(sub_id)),
                         0, // TODO: generation
                     );
                 }
+                Err(err) => {
+                    warn!("mkdir {:?}: {}", name, err);
+                    return reply.error(error_to_errno(&*err));
+                }
+            }
+        }
+        reply.error(libc::ENOENT);
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+        if ino == CONTROL_SOCKET_INO {
+            return match &self.control {
+                Some(control) => reply.data(control.path().as_os_str().as_bytes()),
+                None => reply.error(libc::ENOENT),
+            };
+        }
+        if let Some(entry) = self.inodedb.get(ino) {
+            if let Some(name) = entry.entry_name() {
+                return match self.vfs.readlink(req.uid(), entry.as_identifier(), name) {
+                    Ok(target) => reply.data(target.as_os_str().as_bytes()),
+                    Err(err) => {
+                        warn!("readlink {:?}: {}", name, err);
+                        reply.error(error_to_errno(&*err))
+                    }
+                };
+            }
+            return reply.error(libc::EINVAL);
+        }
+        reply.error(libc::ENOENT);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        if ino == STATUS_INO {
+            let data = self.status.as_bytes();
+            let start = (offset.max(0) as usize).min(data.len());
+            let end = (start + size as usize).min(data.len());
+            return reply.data(&data[start..end]);
+        }
+        let mut data = vec![0u8; size as usize];
+        match self.with_file(fh, |file| file.read_at(&mut data, offset as u64)) {
+            Ok(len) => reply.data(&data[..len]),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let entry = match self.inodedb.get(ino) {
+            Some(entry) if !entry.is_versions() && entry.entry_name().is_none() => entry,
+            Some(_) => return reply.error(libc::EISDIR),
+            None => return reply.error(libc::ENOENT),
+        };
+        match self.vfs.open(req.uid(), entry.as_identifier(), flags) {
+            Ok(handle) => {
+                let fh = self.handledb.store(handle);
+                if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                    if let Some((parent, name)) = entry.link() {
+                        self.written.insert(fh, (parent.clone(), name.into()));
+                    }
+                }
+                reply.opened(fh, 0)
+            }
+            Err(err) => {
+                warn!("open {}: {}", ino, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let entry = match self.inodedb.get(parent) {
+            Some(entry) => entry,
+            None => return reply.error(libc::ENOENT),
+        };
+        if self.vfs.is_dropbox(entry.as_identifier()) {
+            return self.create_anonymous(req, entry.as_identifier(), name, reply);
+        }
+        match self
+            .vfs
+            .create(req.uid(), entry.as_identifier(), name)
+            .and_then(|sub_id| {
+                let handle = self.vfs.open(req.uid(), &sub_id, flags & !libc::O_TRUNC)?;
+                Ok((self.vfs.metadata(req.uid(), &sub_id)?, sub_id, handle))
+            }) {
+            Ok((metadata, sub_id, handle)) => {
+                let fh = self.handledb.store(handle);
+                self.written
+                    .insert(fh, (entry.as_identifier().clone(), name.into()));
+                let sub_entry =
+                    self.inodedb
+                        .store_linked(metadata.stat().st_ino, sub_id, &entry, name);
+                reply.created(
+                    &self.ttl,
+                    &stat_to_fileattr(
+                        metadata.stat(),
+                        identifier_to_filetype(sub_entry.as_identifier()),
+                    ),
+                    0, // TODO: generation
+                    fh,
+                    0,
+                )
+            }
+            Err(err) => {
+                warn!("create {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        match self.with_file(fh, |file| file.write_at(data, offset as u64)) {
+            Ok(len) => reply.written(len as u32),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    /// Closing a written file checks its size against the rules of its directory, the error
+    /// is returned by close().
+    fn flush(
+        &mut self,
+        req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        if let Some((parent, name)) = self.staged.remove(&fh) {
+            return self.add_anonymous(req, fh, &parent, &name, reply);
+        }
+        let (parent, name) = match self.written.get(&fh) {
+            Some(entry) => entry.clone(),
+            None => return reply.ok(),
+        };
+        let size = match self.with_file(fh, |file| file.metadata()) {
+            Ok(metadata) => metadata.len(),
+            Err(errno) => return reply.error(errno),
+        };
+        match self.vfs.close_check(req.uid(), &parent, &name, size) {
+            Ok(()) => reply.ok(),
+            Err(err) => {
+                warn!("close {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.written.remove(&fh);
+        self.staged.remove(&fh);
+        match self.handledb.drop(fh) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EBADF)),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        // exchanging entries is not supported
+        if flags & !(libc::RENAME_NOREPLACE as u32) != 0 {
+            return reply.error(libc::EINVAL);
+        }
+        let (entry, new_entry) = match (self.inodedb.get(parent), self.inodedb.get(newparent)) {
+            (Some(entry), Some(new_entry)) => (entry, new_entry),
+            _ => return reply.error(libc::ENOENT),
+        };
+        // moving a directory below itself is refused along this chain
+        let new_parents = if parent == newparent {
+            Vec::new()
+        } else {
+            match self.entry_parents(req, newparent, &new_entry) {
+                Ok(parents) => parents,
+                Err(errno) => return reply.error(errno),
+            }
+        };
+        match self.vfs.rename(
+            req.uid(),
+            entry.as_identifier(),
+            name,
+            new_entry.as_identifier(),
+            &new_parents,
+            newname,
+            flags & libc::RENAME_NOREPLACE as u32 == 0,
+        ) {
+            Ok(()) => reply.ok(),
+            Err(err) => {
+                warn!("rename {:?} -> {:?}: {}", name, newname, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        link: &Path,
+        reply: ReplyEntry,
+    ) {
+        if let Some(entry) = self.inodedb.get(parent) {
+            match self
+                .vfs
+                .symlink(req.uid(), entry.as_identifier(), name, link)
+                .and_then(|()| {
+                    Ok(self
+                        .vfs
+                        .entry_metadata(req.uid(), entry.as_identifier(), name)?)
+                }) {
+                Ok(metadata) => {
+                    self.inodedb.store_entry(
+                        metadata.stat().st_ino,
+                        entry.as_identifier().clone(),
+                        name,
+                    );
+                    return reply.entry(
+                        &self.ttl,
+                        &stat_to_fileattr(metadata.stat(), FileType::Symlink),
+                        0, // TODO: generation
+                    );
+                }
+                Err(err) => {
+                    warn!("symlink {:?}: {}", name, err);
+                    return reply.error(error_to_errno(&*err));
+                }
+            }
+        }
+        reply.error(libc::ENOENT);
+    }
+
+    fn mknod(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        let special = match SpecialFile::from_mode(mode, rdev as libc::dev_t) {
+            Some(special) => special,
+            None => return reply.error(libc::EPERM),
+        };
+        if let Some(entry) = self.inodedb.get(parent) {
+            match self
+                .vfs
+                .mknod(
+                    req.uid(),
+                    entry.as_identifier(),
+                    name,
+                    special,
+                    mode & !umask,
+                )
+                .and_then(|()| {
+                    Ok(self
+                        .vfs
+                        .entry_metadata(req.uid(), entry.as_identifier(), name)?)
+                }) {
+                Ok(metadata) => {
+                    self.inodedb.store_entry(
+                        metadata.stat().st_ino,
+                        entry.as_identifier().clone(),
+                        name,
+                    );
+                    return reply.entry(
+                        &self.ttl,
+                        &stat_to_fileattr(metadata.stat(), mode_to_filetype(metadata.stat())),
+                        0, // TODO: generation
+                    );
+                }
+                Err(err) => {
+                    warn!("mknod {:?}: {}", name, err);
+                    return reply.error(error_to_errno(&*err));
+                }
             }
         }
         reply.error(libc::ENOENT);
     }
 
+    fn setxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let identifier = match self.xattr_object(ino, Some(name)) {
+            Ok(identifier) => identifier,
+            Err(errno) => return reply.error(errno),
+        };
+        let name = name.to_str().unwrap();
+
+        if flags & (libc::XATTR_CREATE | libc::XATTR_REPLACE) != 0 {
+            let exists = self.vfs.getxattr(req.uid(), &identifier, name).is_ok();
+            if flags & libc::XATTR_CREATE != 0 && exists {
+                return reply.error(libc::EEXIST);
+            }
+            if flags & libc::XATTR_REPLACE != 0 && !exists {
+                return reply.error(libc::ENODATA);
+            }
+        }
+
+        match self.vfs.setxattr(req.uid(), &identifier, name, value) {
+            Ok(()) => reply.ok(),
+            Err(err) => {
+                warn!("setxattr {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn getxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let identifier = match self.xattr_object(ino, Some(name)) {
+            Ok(identifier) => identifier,
+            Err(errno) => return reply.error(errno),
+        };
+
+        match self
+            .vfs
+            .getxattr(req.uid(), &identifier, name.to_str().unwrap())
+        {
+            Ok(value) => reply_xattr(&value, size, reply),
+            Err(err) => {
+                trace!("getxattr {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let identifier = match self.xattr_object(ino, None) {
+            Ok(identifier) => identifier,
+            Err(errno) => return reply.error(errno),
+        };
+
+        match self.vfs.listxattr(req.uid(), &identifier) {
+            Ok(names) => {
+                let mut list = Vec::new();
+                for name in names {
+                    list.extend_from_slice(name.as_bytes());
+                    list.push(0);
+                }
+                reply_xattr(&list, size, reply)
+            }
+            Err(err) => {
+                warn!("listxattr {}: {}", ino, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    fn removexattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let identifier = match self.xattr_object(ino, Some(name)) {
+            Ok(identifier) => identifier,
+            Err(errno) => return reply.error(errno),
+        };
+
+        match self
+            .vfs
+            .removexattr(req.uid(), &identifier, name.to_str().unwrap())
+        {
+            Ok(()) => reply.ok(),
+            Err(err) => {
+                warn!("removexattr {:?}: {}", name, err);
+                reply.error(error_to_errno(&*err))
+            }
+        }
+    }
+
+    /// Only the virtual versions directories can be listed yet, they list the numbers of the
+    /// snapshots.
+    fn readdir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entry = match self.inodedb.get(ino) {
+            Some(entry) if entry.is_versions() => entry,
+            Some(_) => return reply.error(libc::ENOSYS),
+            None => return reply.error(libc::ENOENT),
+        };
+        let versions = match self.vfs.versions(req.uid(), entry.as_identifier()) {
+            Ok(versions) => versions,
+            Err(err) => {
+                warn!("readdir {}: {}", ino, err);
+                return reply.error(error_to_errno(&*err));
+            }
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, OsString::from(".")),
+            (
+                ino & !VERSIONS_INO,
+                FileType::Directory,
+                OsString::from(".."),
+            ),
+        ];
+        for version in versions {
+            let sub_ino = match self.vfs.metadata(req.uid(), &version.identifier) {
+                Ok(metadata) => metadata.stat().st_ino,
+                // expired meanwhile
+                Err(_) => continue,
+            };
+            entries.push((
+                sub_ino,
+                identifier_to_filetype(&version.identifier),
+                OsString::from(version.number.to_string()),
+            ));
+        }
+
+        // offsets count from 1, the offset passed in is the one of the last returned entry
+        for (index, (ino, kind, name)) in entries.iter().enumerate().skip(offset.max(0) as usize) {
+            if reply.add(*ino, index as i64 + 1, *kind, name) {
+                break;
+            }
+        }
+        reply.ok()
+    }
+
     // fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
     //     if let Some(entry) = self.inodedb.get(ino) {
     //         trace!("id: {:?}", entry.as_identifier());
@@ -231,32 +1130,16 @@ impl Filesystem for &mut UberallFS {
 
     // TODO:
     // pub fn init(
-    // pub fn destroy(&mut self, _req: &Request<'_>) { ... }
     // pub fn forget(&mut self, _req: &Request<'_>, _ino: u64, _nlookup: u64) { ... }
     // pub fn getattr(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyAttr) { ... }
     // pub fn setattr(
-    // pub fn readlink(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyData) { ... }
-    // pub fn mknod(
-    // pub fn mkdir(
     // pub fn unlink(
     // pub fn rmdir(
-    // pub fn symlink(
-    // pub fn rename(
     // pub fn link(
-    // pub fn open(
-    // pub fn read(
-    // pub fn write(
-    // pub fn flush(
-    // pub fn release(
     // pub fn fsync(
     // pub fn readdirplus(
     // pub fn fsyncdir(
     // pub fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) { ... }
-    // pub fn setxattr(
-    // pub fn getxattr(
-    // pub fn listxattr(
-    // pub fn removexattr(
-    // pub fn create(
     // pub fn getlk(
     // pub fn setlk(
     // pub fn bmap(
@@ -266,6 +1149,35 @@ impl Filesystem for &mut UberallFS {
     // pub fn copy_file_range(
 }
 
+/// Map errors from the vfs layer to errno values for fuse replies
+fn error_to_errno(err: &(dyn std::error::Error + 'static)) -> libc::c_int {
+    let io_error = match err.downcast_ref::<ObjectStoreError>() {
+        Some(ObjectStoreError::IoError(io_error)) => Some(io_error),
+        Some(ObjectStoreError::ObjectNotFound(_)) => return libc::ENOENT,
+        Some(ObjectStoreError::IllegalFileName(_)) => return libc::EINVAL,
+        Some(ObjectStoreError::SymlinkEscape(_)) => return libc::EPERM,
+        Some(ObjectStoreError::SpecialFile(_)) => return libc::EPERM,
+        Some(ObjectStoreError::NoSuchXattr(_)) => return libc::ENODATA,
+        Some(ObjectStoreError::ReadOnlyXattr(_)) => return libc::EPERM,
+        Some(ObjectStoreError::ObjectExists(_)) => return libc::EEXIST,
+        Some(ObjectStoreError::RetypeUnsupported(_)) => return libc::EPERM,
+        Some(ObjectStoreError::MoveIntoItself(_)) => return libc::EINVAL,
+        Some(ObjectStoreError::UnsupportedXattr(_)) => return libc::EOPNOTSUPP,
+        _ => err.downcast_ref::<io::Error>(),
+    };
+
+    match io_error {
+        Some(io_error) => io_error.raw_os_error().unwrap_or(match io_error.kind() {
+            io::ErrorKind::NotFound => libc::ENOENT,
+            io::ErrorKind::PermissionDenied => libc::EACCES,
+            io::ErrorKind::AlreadyExists => libc::EEXIST,
+            io::ErrorKind::InvalidInput => libc::EINVAL,
+            _ => libc::EIO,
+        }),
+        None => libc::EIO,
+    }
+}
+
 fn unix_to_system_time(sec: libc::time_t, ns: i64) -> SystemTime {
     UNIX_EPOCH + Duration::from_secs(sec as u64) + Duration::from_nanos(ns as u64)
 }
@@ -273,11 +1185,58 @@ fn unix_to_system_time(sec: libc::time_t, ns: i64) -> SystemTime {
 fn identifier_to_filetype(identifier: &Identifier) -> FileType {
     match identifier.object_type() {
         ObjectType::File => FileType::RegularFile,
-        ObjectType::Directory => FileType::Directory,
+        ObjectType::Directory | ObjectType::DirectoryWithParent => FileType::Directory,
         _ => unimplemented!(),
     }
 }
 
+/// Attributes of virtual entries which are not backed by anything on disk
+fn virtual_attr(req: &Request<'_>, ino: u64, kind: FileType, perm: u16, size: u64) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino,
+        size,
+        blocks: 0,
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: UNIX_EPOCH,
+        kind,
+        perm,
+        nlink: 1,
+        uid: req.uid(),
+        gid: req.gid(),
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
+
+/// Replies the size of an extended attribute value when asked with size 0, the value itself
+/// when it fits into 'size'.
+fn reply_xattr(value: &[u8], size: u32, reply: ReplyXattr) {
+    if size == 0 {
+        reply.size(value.len() as u32)
+    } else if value.len() <= size as usize {
+        reply.data(value)
+    } else {
+        reply.error(libc::ERANGE)
+    }
+}
+
+/// File type of plain directory entries which are not objects
+fn mode_to_filetype(stat: &libc::stat) -> FileType {
+    match stat.st_mode & libc::S_IFMT {
+        libc::S_IFLNK => FileType::Symlink,
+        libc::S_IFIFO => FileType::NamedPipe,
+        libc::S_IFSOCK => FileType::Socket,
+        libc::S_IFCHR => FileType::CharDevice,
+        libc::S_IFBLK => FileType::BlockDevice,
+        libc::S_IFDIR => FileType::Directory,
+        _ => FileType::RegularFile,
+    }
+}
+
 fn stat_to_fileattr(stat: &libc::stat, kind: FileType) -> FileAttr {
     FileAttr {
         ino: stat.st_ino,
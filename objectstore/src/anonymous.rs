@@ -0,0 +1,51 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::PathBuf;
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::object::Object;
+use crate::{LockingMethod::*, ObjectStore, SubObject};
+
+pub(crate) fn opt_add_anonymous(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").map(PathBuf::from).unwrap();
+    let (parent, remaining) = objectstore.path_lookup(&path, None)?;
+
+    let mut components = remaining.components();
+    let name = match (components.next(), components.next()) {
+        (Some(name), None) => name.as_os_str(),
+        (None, _) => return Err(io::Error::from(io::ErrorKind::AlreadyExists).into()),
+        (Some(name), Some(_)) => {
+            warn!("Parent dir missing: {:?}", name);
+            return Err(ObjectStoreError::ObjectNotFound(name.as_os_str().into()).into());
+        }
+    };
+    parent.ensure_dir()?;
+
+    let sub_object = SubObject(&parent, name);
+    // check the rules before creating a new object which would become garbage otherwise
+    objectstore.rules_check_create(
+        &sub_object,
+        (
+            ObjectType::File,
+            SharingPolicy::Anonymous,
+            Mutability::Immutable,
+        ),
+    )?;
+
+    let object = Object::build(
+        ObjectType::File,
+        SharingPolicy::Anonymous,
+        Mutability::Immutable,
+    )
+    .source(File::open(matches.value_of_os("SOURCE").unwrap())?)
+    .realize(&objectstore)?;
+
+    objectstore.create_link(&object.identifier, sub_object)?;
+    println!("{}", object.identifier);
+    Ok(())
+}
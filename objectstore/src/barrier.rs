@@ -0,0 +1,89 @@
+//! Write barrier for garbage collection running concurrently with other operations.
+//!
+//! While an online gc runs, every object that is created or linked gets recorded. Marking
+//! walks the recorded objects after its first pass until no new ones show up, the sweep keeps
+//! everything recorded meanwhile. Creating and linking objects hold the gate shared, starting
+//! a gc takes it exclusively, thus no operation straddles the start of an epoch unrecorded.
+//!
+//! An object may be created before the epoch started but only linked later, such objects are
+//! protected by a grace period: objects changed shortly before the epoch began are kept.
+//!
+//! This relies on operations reaching objects only by walking from the roots, resurrecting
+//! unreachable objects by identifier while a gc runs is not supported.
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use uberall::parking_lot::{Mutex, RwLock, RwLockReadGuard};
+
+use crate::prelude::*;
+use crate::{Identifier, IdentifierBin};
+
+#[derive(Default)]
+struct Epoch {
+    /// Objects changed after this (seconds since the unix epoch) are kept
+    changed_after: i64,
+    /// Recorded but not yet marked
+    pending:       Vec<Identifier>,
+    /// Everything recorded in this epoch
+    recorded:      HashSet<IdentifierBin>,
+}
+
+#[derive(Default)]
+pub(crate) struct GcBarrier {
+    gate:   RwLock<()>,
+    active: AtomicBool,
+    epoch:  Mutex<Epoch>,
+}
+
+impl GcBarrier {
+    /// Creating and linking objects hold the returned guard while they run.
+    pub(crate) fn enter(&self) -> RwLockReadGuard<'_, ()> {
+        // operations may nest, a waiting gc must not block the inner ones
+        self.gate.read_recursive()
+    }
+
+    /// Records an object created or linked while a gc is running.
+    pub(crate) fn record(&self, identifier: &Identifier) {
+        if self.active.load(Ordering::Acquire) {
+            let mut epoch = self.epoch.lock();
+            if epoch.recorded.insert(identifier.id_bin()) {
+                trace!("gc barrier: {}", identifier);
+                epoch.pending.push(identifier.clone());
+            }
+        }
+    }
+
+    /// Starts a new epoch, only one gc can run at a time. Objects changed up to 'grace'
+    /// before are protected.
+    pub(crate) fn start(&self, grace: Duration) -> Result<()> {
+        let _gate = self.gate.write();
+        if self.active.swap(true, Ordering::AcqRel) {
+            return Err(ObjectStoreError::GcRunning.into());
+        }
+        *self.epoch.lock() = Epoch {
+            changed_after: (SystemTime::now() - grace)
+                .duration_since(UNIX_EPOCH)?
+                .as_secs() as i64,
+            ..Epoch::default()
+        };
+        Ok(())
+    }
+
+    /// Ends the epoch.
+    pub(crate) fn stop(&self) {
+        self.active.store(false, Ordering::Release);
+        *self.epoch.lock() = Epoch::default();
+    }
+
+    /// Returns the objects recorded since the last call.
+    pub(crate) fn take_pending(&self) -> Vec<Identifier> {
+        std::mem::take(&mut self.epoch.lock().pending)
+    }
+
+    /// Returns true when an unmarked object must be kept anyway, 'changed' is its ctime.
+    pub(crate) fn keeps(&self, identifier: &Identifier, changed: i64) -> bool {
+        let epoch = self.epoch.lock();
+        epoch.recorded.contains(&identifier.id_bin()) || changed >= epoch.changed_after
+    }
+}
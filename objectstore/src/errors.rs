@@ -4,6 +4,8 @@ use uberall::thiserror::{self, Error};
 
 use crate::prelude::*;
 use crate::identifier_kind::*;
+use crate::objectmeta::MetadataStamp;
+use crate::KeyId;
 
 #[derive(Error, Debug)]
 pub enum ObjectStoreError {
@@ -43,6 +45,9 @@ pub enum ObjectStoreError {
     #[error("Could not acquire lock on the objectstore")]
     NoLock,
 
+    #[error("Objectstore is locked by {0}")]
+    LockedBy(String),
+
     #[error("Object {0:?} exists already")]
     ObjectExists(OsString),
 
@@ -52,6 +57,85 @@ pub enum ObjectStoreError {
     #[error("Illegal file name: {0:?}")]
     IllegalFileName(OsString),
 
+    #[error("Rule syntax error: {0}")]
+    RuleSyntax(String),
+
+    #[error("Replayed metadata on {object:?}: got '{got}' but already have '{have}'")]
+    MetadataReplay {
+        object: OsString,
+        have:   MetadataStamp,
+        got:    MetadataStamp,
+    },
+
+    #[error("Revocation of {key:?} by {signer:?} is not authorized")]
+    RevocationUnauthorized { key: KeyId, signer: KeyId },
+
+    #[error("Revocation list is full")]
+    RevocationListFull,
+
+    #[error("Manifest syntax error: {0}")]
+    ManifestSyntax(String),
+
+    #[error("Key {0:?} is not an admin")]
+    NotAnAdmin(KeyId),
+
+    #[error("Key {0:?} is revoked")]
+    KeyRevoked(KeyId),
+
+    #[error("Key {0:?} is malformed")]
+    BadKey(KeyId),
+
+    #[error("Signature by {0:?} does not verify")]
+    BadSignature(KeyId),
+
+    #[error("No pending change on {0:?}")]
+    NoPendingChange(OsString),
+
+    #[error("Pending change on {0:?} is based on an outdated manifest")]
+    StalePendingChange(OsString),
+
+    #[error("{0:?} is a symlink")]
+    IsSymlink(OsString),
+
+    #[error("Symlink target {0:?} escapes the directory")]
+    SymlinkEscape(OsString),
+
+    #[error("{0:?} is a special file, these only exist in private directories")]
+    SpecialFile(OsString),
+
+    #[error("No extended attribute {0:?}")]
+    NoSuchXattr(String),
+
+    #[error("Extended attribute {0:?} is read-only")]
+    ReadOnlyXattr(String),
+
+    #[error("Extended attribute {0:?} is not supported")]
+    UnsupportedXattr(String),
+
+    #[error("Object {0:?} can not be a root")]
+    NotARoot(OsString),
+
+    #[error("Object {object:?} can only be linked under its parent {parent:?}")]
+    ParentMismatch { object: OsString, parent: OsString },
+
+    #[error("Moving {0:?} below itself would make it unreachable")]
+    MoveIntoItself(OsString),
+
+    #[error("Renaming {0:?} would change it into a type it can not be converted to")]
+    RetypeUnsupported(OsString),
+
+    #[error("Object {0:?} is private, no --private given")]
+    PrivateObject(OsString),
+
+    #[error("Object {0:?} is not versioned")]
+    NotVersioned(OsString),
+
+    #[error("Quorum not reached: {have} of {want} admin signatures")]
+    QuorumNotReached { have: usize, want: usize },
+
+    #[error("Garbage collection is already running")]
+    GcRunning,
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
@@ -1,131 +1,692 @@
-use std::ffi::OsStr;
-use std::sync::Arc;
-use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use std::collections::VecDeque;
 
 use uberall::clap::ArgMatches;
+use uberall::daemon::{CallbackMessage, CallbackTx};
 use uberall::parking_lot::Mutex;
 
 use crate::prelude::*;
-use crate::IdentifierBin;
-use crate::Identifier;
+use crate::liveset::{LiveSet, SHARDS, shard_path};
+use crate::{Identifier, Mutability};
 use crate::object::{DeleteMethod, Object};
-use crate::{LockingMethod::*, ObjectStore};
+use crate::{DirEntry, LockingMethod::*, ObjectPath, ObjectStore, SubObject};
+
+/// How often progress is printed
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
 pub(crate) fn opt_gc(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    // gc defaults to foreground, backgrounding must be requested
+    if matches.is_present("background") {
+        uberall::daemon::maybe_daemonize(|tx| run_gc(dir, matches, tx))
+    } else {
+        run_gc(dir, matches, None)
+    }
+}
+
+fn run_gc(dir: &OsStr, matches: &ArgMatches, tx: Option<CallbackTx>) -> Result<()> {
     let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
 
-    // PLANNED: gc may background (defaults to foreground, does this need any change in the dameonizer?)
-    // PLANNED: additional roots
+    // the objectstore is locked now, a waiting parent may return
+    if let Some(tx) = tx {
+        tx.send(CallbackMessage::success()).expect("send message");
+    }
+
+    let mut config = GcConfig::default();
+    if let Some(threads) = matches.value_of("threads") {
+        config.threads = threads.parse()?;
+    }
+    if let Some(memory_limit) = matches.value_of("memory-limit") {
+        config.memory_limit = memory_limit.parse()?;
+    }
+    config.progress = matches.is_present("progress");
+
+    let mut roots = objectstore.gc_roots()?;
+    if let Some(extra) = matches.values_of_os("ROOT") {
+        for root in extra {
+            let identifier = objectstore.object_lookup(root)?;
+            info!("additional root: {:?}", identifier);
+            roots.push(identifier);
+        }
+    }
+
+    print!(
+        "{}",
+        objectstore.gc_with(&roots, matches.is_present("dry-run"), &config)?
+    );
+    Ok(())
+}
+
+// Hidden diagnostic, races directory creation and relinking against concurrent gc runs and
+// checks that nothing reachable got lost.
+pub(crate) fn opt_gc_race(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+    let writers: usize = matches.value_of("writers").unwrap_or("4").parse()?;
+    let rounds: usize = matches.value_of("rounds").unwrap_or("100").parse()?;
 
     let root = objectstore.get_root_id()?;
-    info!("root is: {:?}", root);
+    let config = GcConfig {
+        concurrent: true,
+        // objects older than this are only protected by the barrier
+        grace: Duration::from_secs(1),
+        ..GcConfig::default()
+    };
+
+    let (paths, gc_runs) = thread::scope(|scope| -> Result<(Vec<PathBuf>, usize)> {
+        let writers: Vec<_> = (0..writers)
+            .map(|writer| {
+                let (objectstore, root) = (&objectstore, &root);
+                scope.spawn(move || -> ThreadResult<Vec<PathBuf>> {
+                    objectstore
+                        .race_writer(root, writer, rounds)
+                        .map_err(sendable)
+                })
+            })
+            .collect();
+
+        let mut gc_runs = 0;
+        while gc_runs == 0 || writers.iter().any(|writer| !writer.is_finished()) {
+            objectstore.gc_with(&objectstore.gc_roots()?, false, &config)?;
+            gc_runs += 1;
+        }
 
-    objectstore.gc(&[root], matches.is_present("dry-run"))
+        let mut paths = Vec::new();
+        for writer in writers {
+            paths.extend(join(writer)?);
+        }
+        Ok((paths, gc_runs))
+    })?;
+
+    for path in &paths {
+        let identifier = objectstore.object_lookup(path.as_os_str())?;
+        objectstore.list_directory(&identifier)?;
+    }
+    println!("ok {} directories, {} gc runs", paths.len(), gc_runs);
+    Ok(())
 }
 
 impl ObjectStore {
-    /// Returns an iterator of all objects not reachable from the given roots.
-    pub fn unreachable(
+    /// Builds a chain of directories below '/raceN', every third one is moved up to '/raceN'
+    /// after it got linked. Returns the paths of all directories created.
+    fn race_writer(&self, root: &Identifier, writer: usize, rounds: usize) -> Result<Vec<PathBuf>> {
+        let mkdir = |parent: &Identifier, name: &OsStr| -> Result<Identifier> {
+            let object = Object::build(
+                crate::ObjectType::Directory,
+                crate::SharingPolicy::Private,
+                Mutability::Mutable,
+            )
+            .realize(self)?;
+            self.create_link(&object.identifier, SubObject(parent, name))?;
+            Ok(object.identifier)
+        };
+
+        let base_name = OsString::from(format!("race{}", writer));
+        let base_path = Path::new("/").join(&base_name);
+        let base = mkdir(root, &base_name)?;
+
+        let mut paths = vec![base_path.clone()];
+        let (mut parent, mut parent_path) = (base.clone(), base_path.clone());
+        for round in 0..rounds {
+            let name = OsString::from(format!("d{}", round));
+            let directory = mkdir(&parent, &name)?;
+            let mut path = parent_path.join(&name);
+
+            if round % 3 == 2 {
+                let moved = OsString::from(format!("m{}", round));
+                self.create_link(&directory, SubObject(&base, &moved))?;
+                self.objects
+                    .remove_file(&SubObject(&parent, &name).to_pathbuf())?;
+                path = base_path.join(&moved);
+            }
+
+            paths.push(path.clone());
+            parent = directory;
+            parent_path = path;
+        }
+        Ok(paths)
+    }
+}
+
+/// Tuning of the garbage collector
+#[derive(Debug, Clone)]
+pub struct GcConfig {
+    /// Number of threads marking and sweeping
+    pub threads:      usize,
+    /// Number of live objects held in memory before they are spilled to a temporary file
+    pub memory_limit: usize,
+    /// Print progress to stderr while running
+    pub progress:     bool,
+    /// Run while other operations change the objectstore, objects created or linked
+    /// meanwhile are protected by the gc barrier
+    pub concurrent:   bool,
+    /// When running concurrently, objects changed this long before the start are kept
+    pub grace:        Duration,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            threads:      thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            memory_limit: 1 << 22,
+            progress:     false,
+            concurrent:   false,
+            grace:        Duration::from_secs(60),
+        }
+    }
+}
+
+/// What a garbage collection run did, or would do on a dry run
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Number of objects in the objectstore
+    pub scanned:     usize,
+    /// Objects reachable from the roots
+    pub kept:        usize,
+    /// Objects removed right away
+    pub deleted:     usize,
+    /// Objects moved to 'delete' to be expired later
+    pub expired:     usize,
+    /// Size of the removed objects and their metadata as reported by the host
+    pub bytes_freed: u64,
+    /// The objects a dry run would remove, empty otherwise
+    pub unreachable: Vec<Identifier>,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        writeln!(f, "scanned {}", self.scanned)?;
+        writeln!(f, "kept {}", self.kept)?;
+        writeln!(f, "deleted {}", self.deleted)?;
+        writeln!(f, "expired {}", self.expired)?;
+        writeln!(f, "bytes_freed {}", self.bytes_freed)?;
+        for identifier in &self.unreachable {
+            let object = Object::from(identifier.clone());
+            writeln!(f, "would {}: {}", object.delete_method(), identifier)?;
+        }
+        Ok(())
+    }
+}
+
+impl AddAssign for GcReport {
+    fn add_assign(&mut self, other: GcReport) {
+        self.scanned += other.scanned;
+        self.kept += other.kept;
+        self.deleted += other.deleted;
+        self.expired += other.expired;
+        self.bytes_freed += other.bytes_freed;
+        self.unreachable.extend(other.unreachable);
+    }
+}
+
+/// Counters shared by the gc threads for progress reporting
+#[derive(Default)]
+struct GcProgress {
+    directories: AtomicUsize,
+    marked:      AtomicUsize,
+    swept:       AtomicUsize,
+}
+
+impl fmt::Display for GcProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "gc: {} directories walked, {} objects marked, {}/{} shards swept",
+            self.directories.load(Ordering::Relaxed),
+            self.marked.load(Ordering::Relaxed),
+            self.swept.load(Ordering::Relaxed),
+            SHARDS
+        )
+    }
+}
+
+/// Work stealing queue of directories still to be walked. Each worker pushes and pops at the
+/// back of its own queue and steals from the front of the others when it runs dry.
+struct WorkQueue {
+    queues:  Vec<Mutex<VecDeque<Identifier>>>,
+    /// Directories queued or in progress, marking is complete when this drops to zero
+    pending: AtomicUsize,
+    failed:  AtomicBool,
+}
+
+impl WorkQueue {
+    fn new(workers: usize) -> WorkQueue {
+        WorkQueue {
+            queues:  (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(0),
+            failed:  AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, worker: usize, identifier: Identifier) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.queues[worker].lock().push_back(identifier);
+    }
+
+    /// Returns the next directory to walk, 'None' when marking is complete or failed.
+    fn pop(&self, worker: usize) -> Option<Identifier> {
+        let workers = self.queues.len();
+        loop {
+            if self.failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(identifier) = self.queues[worker].lock().pop_back() {
+                return Some(identifier);
+            }
+            for other in (1..workers).map(|n| (worker + n) % workers) {
+                if let Some(identifier) = self.queues[other].lock().pop_front() {
+                    return Some(identifier);
+                }
+            }
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// A popped directory was walked.
+    fn done(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn fail(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Errors passed back from the gc threads
+type ThreadResult<T> = std::result::Result<T, Box<dyn Error + Send>>;
+
+/// ObjectStoreError may wrap errors which are not 'Send', only io errors are passed as is.
+fn sendable(err: Box<dyn Error>) -> Box<dyn Error + Send> {
+    match err.downcast::<io::Error>() {
+        Ok(err) => err,
+        Err(err) => Box::new(ObjectStoreError::ObjectStoreFatal(err.to_string())),
+    }
+}
+
+/// Objects may vanish while gc runs concurrently, these are skipped.
+fn unless_vanished<T: Default>(result: Result<T>, concurrent: bool) -> Result<T> {
+    match result {
+        Err(err)
+            if concurrent
+                && err
+                    .downcast_ref::<io::Error>()
+                    .map_or(false, |err| err.kind() == io::ErrorKind::NotFound) =>
+        {
+            Ok(T::default())
+        }
+        result => result,
+    }
+}
+
+fn join<T>(handle: thread::ScopedJoinHandle<'_, ThreadResult<T>>) -> Result<T> {
+    handle
+        .join()
+        .expect("gc thread panicked")
+        .map_err(|err| err as Box<dyn Error>)
+}
+
+impl ObjectStore {
+    /// Returns the objects garbage collection starts from: the root, the revocation list and
+    /// all pinned objects.
+    pub fn gc_roots(&self) -> Result<Vec<Identifier>> {
+        let root = self.get_root_id()?;
+        info!("root is: {:?}", root);
+        let mut roots = vec![root];
+
+        if let Some(revocations) = self.revocation_list_id()? {
+            info!("revocation list is: {:?}", revocations);
+            roots.push(revocations);
+        }
+
+        for (name, named) in self.named_roots()? {
+            info!("root {} is: {:?}", name, named);
+            if !roots.contains(&named) {
+                roots.push(named);
+            }
+        }
+
+        for pinned in self.all_pins()? {
+            info!("pinned: {:?}", pinned);
+            roots.push(pinned);
+        }
+        Ok(roots)
+    }
+
+    /// Returns all objects not reachable from the given roots, nothing gets removed.
+    pub fn unreachable(&self, roots: &[Identifier]) -> Result<Vec<Identifier>> {
+        Ok(self.gc(roots, true)?.unreachable)
+    }
+
+    /// Run garbage collection on the objectstore with the default configuration.
+    pub fn gc(&self, roots: &[Identifier], dry_run: bool) -> Result<GcReport> {
+        self.gc_with(roots, dry_run, &GcConfig::default())
+    }
+
+    /// Run garbage collection on the objectstore.  Garbage collection discovers all
+    /// referenced objects starting from the given roots and then removes all objects that are
+    /// not referenced. The 'dry_run' parameter make it only report what would been done
+    /// without changing anything. Returns a report of what was (or would be) done, on a dry
+    /// run it lists the unreachable objects.
+    pub fn gc_with(
+        &self,
+        roots: &[Identifier],
+        dry_run: bool,
+        config: &GcConfig,
+    ) -> Result<GcReport> {
+        let progress = GcProgress::default();
+
+        if config.concurrent {
+            self.barrier.start(config.grace)?;
+        }
+        let result = thread::scope(|scope| {
+            let (stop, stopped) = mpsc::channel::<()>();
+            if config.progress {
+                let progress = &progress;
+                scope.spawn(move || {
+                    while let Err(RecvTimeoutError::Timeout) =
+                        stopped.recv_timeout(PROGRESS_INTERVAL)
+                    {
+                        eprintln!("{}", progress);
+                    }
+                });
+            }
+
+            let result = self.collect(roots, dry_run, config, &progress);
+            drop(stop);
+            result
+        });
+        if config.concurrent {
+            self.barrier.stop();
+        }
+
+        let report = result?;
+        if config.progress {
+            eprintln!("{}", progress);
+        }
+        info!("gc: {:?}", report);
+        Ok(report)
+    }
+
+    fn collect(
+        &self,
+        roots: &[Identifier],
+        dry_run: bool,
+        config: &GcConfig,
+        progress: &GcProgress,
+    ) -> Result<GcReport> {
+        let live = LiveSet::new(config.memory_limit);
+        self.mark(&live, roots, config, progress)?;
+
+        if config.concurrent {
+            // objects created or linked meanwhile are marked until no new ones show up
+            loop {
+                let recorded = self.barrier.take_pending();
+                if recorded.is_empty() {
+                    break;
+                }
+                debug!("gc: marking {} recorded objects", recorded.len());
+                self.mark(&live, &recorded, config, progress)?;
+            }
+        }
+
+        self.sweep(&live, dry_run, config, progress)
+    }
+
+    /// Marks all objects reachable from the given roots as live. Directories are walked by
+    /// 'config.threads' workers in parallel.
+    fn mark(
         &self,
+        live: &LiveSet,
         roots: &[Identifier],
-    ) -> Result<impl Iterator<Item = Identifier> + '_> {
-        // discover referenced objects from all roots
-        let mut in_use = HashSet::<IdentifierBin>::new();
+        config: &GcConfig,
+        progress: &GcProgress,
+    ) -> Result<()> {
+        let threads = config.threads.max(1);
+        let queue = WorkQueue::new(threads);
+
         for root in roots {
-            self.collect_objects_recursive(root, &mut in_use)?;
+            if root.object_type().is_directory() {
+                if live.insert_directory(root) {
+                    queue.push(0, root.clone());
+                }
+            } else {
+                self.mark_object(root, live, config, progress)?;
+            }
         }
 
-        // iterate over all objects, filter referenced objects
-        Ok(self
-            .all_objects()
-            .filter(move |id| !in_use.contains(&id.id_bin())))
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let queue = &queue;
+                    scope.spawn(move || -> ThreadResult<()> {
+                        while let Some(directory) = queue.pop(worker) {
+                            let result = self
+                                .mark_directory(&directory, live, queue, worker, config, progress);
+                            queue.done();
+                            if let Err(err) = result {
+                                queue.fail();
+                                return Err(sendable(err));
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            workers.into_iter().try_for_each(join)
+        })
     }
 
-    /// Run garbage collection on the objectstore.  Garbage collection discovers all
-    /// referenced objects starting from the given roots and then removes all objects that are
-    /// not referenced. The 'dry_run' parameter make it only report what would been done on
-    /// stdout without changing anything.
-    pub fn gc(&self, roots: &[Identifier], dry_run: bool) -> Result<()> {
-        self.unreachable(roots)?.try_for_each(|id| {
+    /// Marks a non-directory object and its retained versions as live.
+    fn mark_object(
+        &self,
+        identifier: &Identifier,
+        live: &LiveSet,
+        config: &GcConfig,
+        progress: &GcProgress,
+    ) -> Result<()> {
+        if identifier.mutability() == Mutability::Versioned {
+            for version in unless_vanished(self.retained_versions(identifier), config.concurrent)? {
+                live.insert(self, &version)?;
+            }
+        }
+        live.insert(self, identifier)?;
+        progress.marked.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Marks the entries of a directory as live and queues the subdirectories.
+    fn mark_directory(
+        &self,
+        directory: &Identifier,
+        live: &LiveSet,
+        queue: &WorkQueue,
+        worker: usize,
+        config: &GcConfig,
+        progress: &GcProgress,
+    ) -> Result<()> {
+        trace!("dir: {:?}", directory);
+        // snapshots of directories are walked like the directories themself
+        if directory.mutability() == Mutability::Versioned {
+            for version in unless_vanished(self.retained_versions(directory), config.concurrent)? {
+                if live.insert_directory(&version) {
+                    queue.push(worker, version);
+                }
+            }
+        }
+
+        let entries = match self.list_directory(directory) {
+            Ok(entries) => entries,
+            Err(err) if config.concurrent && err.kind() == io::ErrorKind::NotFound => {
+                debug!("gc: {} vanished", directory);
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        for DirEntry(name, entry) in entries {
+            trace!("found: {:?}: {:?}", name, entry);
+            match entry.object_type() {
+                crate::ObjectType::File => self.mark_object(&entry, live, config, progress)?,
+                crate::ObjectType::Directory | crate::ObjectType::DirectoryWithParent => {
+                    if live.insert_directory(&entry) {
+                        queue.push(worker, entry);
+                    }
+                }
+                _ => {
+                    return Err(
+                        ObjectStoreError::UnsupportedObjectType(entry.kind().components()).into(),
+                    );
+                }
+            }
+        }
+        progress.directories.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Removes all objects not marked live, the shards are swept by 'config.threads' workers
+    /// in parallel.
+    fn sweep(
+        &self,
+        live: &LiveSet,
+        dry_run: bool,
+        config: &GcConfig,
+        progress: &GcProgress,
+    ) -> Result<GcReport> {
+        let next_shard = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..config.threads.max(1))
+                .map(|_| {
+                    let next_shard = &next_shard;
+                    scope.spawn(move || -> ThreadResult<GcReport> {
+                        let mut report = GcReport::default();
+                        loop {
+                            let shard = next_shard.fetch_add(1, Ordering::Relaxed);
+                            if shard >= SHARDS {
+                                return Ok(report);
+                            }
+                            self.sweep_shard(shard, live, dry_run, config.concurrent, &mut report)
+                                .map_err(sendable)?;
+                            progress.swept.fetch_add(1, Ordering::Relaxed);
+                        }
+                    })
+                })
+                .collect();
+
+            let mut report = GcReport::default();
+            for worker in workers {
+                report += join(worker)?;
+            }
+            Ok(report)
+        })
+    }
+
+    fn sweep_shard(
+        &self,
+        shard: usize,
+        live: &LiveSet,
+        dry_run: bool,
+        concurrent: bool,
+        report: &mut GcReport,
+    ) -> Result<()> {
+        let in_use = live.take_shard(shard)?;
+        let entries = match self.objects.list_dir(&shard_path(shard)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in entries {
+            // metadata files share the directory with the objects
+            let id = match Identifier::from_filename(Path::new(entry?.file_name())) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+
+            report.scanned += 1;
+            if in_use.contains(&id.id_bin())
+                || (concurrent
+                    && self.barrier.keeps(
+                        &id,
+                        self.object_metadata(&id)
+                            .map_or(i64::MAX, |metadata| metadata.stat().st_ctime),
+                    ))
+            {
+                report.kept += 1;
+                continue;
+            }
+
+            let object = Object::from(id);
+            match object.delete_method() {
+                DeleteMethod::Immediate => {
+                    report.deleted += 1;
+                    report.bytes_freed += self.object_size(object.identifier());
+                }
+                DeleteMethod::Expire => report.expired += 1,
+                DeleteMethod::Unknown => {}
+            }
+
             if !dry_run {
-                self.delete(id)
-                // TODO: report expire
+                self.delete(object.identifier)?;
             } else {
-                let object = Object::from(id);
-                println!("Would {}: {}", object.delete_method(), object.identifier());
-                // TODO: expire
-                Ok(())
+                report.unreachable.push(object.identifier);
             }
-        })
+        }
+        Ok(())
+    }
+
+    /// Size of an object and its metadata, only used for reporting thus errors count as
+    /// zero.
+    fn object_size(&self, identifier: &Identifier) -> u64 {
+        let metadata_size = self
+            .present_metadata(identifier)
+            .into_iter()
+            .map(|meta| {
+                let mut path = PathBuf::new();
+                path.push_metadata(identifier, meta);
+                self.objects.metadata(&path).map_or(0, |m| m.len())
+            })
+            .sum::<u64>();
+
+        self.object_metadata(identifier).map_or(0, |m| m.len()) + metadata_size
     }
 
     /// Delete an object from the objectstore. This is the low-level object deletion which
     /// will remove the object data no matter if they are still in use. Distributed objects
-    /// will be put into the 'delete' directory from where they will be expired later.
+    /// will be put into the 'delete' directory from where they will be expired later. The
+    /// metadata files of the object are removed after the object itself, the sweep never
+    /// visits them on their own.
     pub(crate) fn delete(&self, id: Identifier) -> Result<()> {
         let object = Object::from(id);
         let delete_method = object.delete_method();
         trace!("{}: {}", delete_method, object.identifier());
         match delete_method {
-            DeleteMethod::Immediate => Ok(self
+            DeleteMethod::Immediate => self
                 .objects
-                .remove_recursive_atomic(&object.identifier().to_pathbuf(), "tmp")?),
-            DeleteMethod::Expire => Ok(self
+                .remove_recursive_atomic(&object.identifier().to_pathbuf(), "tmp")?,
+            DeleteMethod::Expire => self
                 .objects
-                .local_rename(&object.identifier().to_pathbuf(), "delete")?),
-            DeleteMethod::Unknown => Err(ObjectStoreError::UnsupportedObjectType(
-                object.identifier().components(),
-            )
-            .into()),
-        }
-    }
-
-    /// Starting from a given root, walk all objects and store their identifiers in the given in_use HashSet.
-    /// Can be called multiple times with differnt roots to fill the 'in_use' set incrementally.
-    pub fn collect_objects_recursive(
-        &self,
-        root: &Identifier,
-        in_use: &mut HashSet<IdentifierBin>,
-    ) -> Result<()> {
-        // stores discovered directories not yet progressed
-        let to_do = Arc::new(Mutex::new(VecDeque::<Identifier>::new()));
-        to_do.lock().push_back(root.clone());
-
-        // stores all found identifiers in binary form
-        let in_use = Arc::new(Mutex::new(in_use));
-
-        while let Some(id) = {
-            let v = to_do.lock().pop_front();
-            v
-        } {
-            let id_bin = id.id_bin();
-            let mut in_use1 = in_use.lock();
-            if !in_use1.contains(&id_bin) {
-                trace!("dir: {:?}", id);
-                in_use1.insert(id_bin);
-                drop(in_use1);
-                for (name, entry) in self.list_directory(&id)? {
-                    trace!("found: {:?}: {:?}", name, entry);
-                    match entry.object_type() {
-                        crate::ObjectType::File => {
-                            in_use.lock().insert(entry.id_bin());
-                        }
-                        crate::ObjectType::Directory => {
-                            let contains_not = !in_use.lock().contains(&entry.id_bin());
-                            if contains_not {
-                                to_do.lock().push_back(entry);
-                            }
-                        }
-                        _ => {
-                            return Err(ObjectStoreError::UnsupportedObjectType(
-                                entry.kind().components(),
-                            )
-                            .into());
-                        }
-                    }
-                }
+                .local_rename(&object.identifier().to_pathbuf(), "delete")?,
+            DeleteMethod::Unknown => {
+                return Err(ObjectStoreError::UnsupportedObjectType(
+                    object.identifier().components(),
+                )
+                .into());
             }
         }
+        for meta in self.present_metadata(object.identifier()) {
+            self.remove_metadata(object.identifier(), meta)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,116 @@
+//! Identifier lookups.
+//!
+//! Prints the identifier of an object, optionally abbreviated, or the other way around all
+//! paths from the root under which an object is linked. Directories can be linked in several
+//! places, so there may be more than one.
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::{DirEntry, Identifier, LockingMethod::*, ObjectStore};
+
+/// Abbreviated identifiers are never shorter than this, see 'identifier_lookup()'
+const MIN_ABBREV: usize = 4;
+
+pub(crate) fn opt_getid(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+
+    if let Some(object) = matches.value_of_os("paths") {
+        let identifier = objectstore.object_lookup(object)?;
+        let paths = objectstore.identifier_paths(&identifier)?;
+        if paths.is_empty() {
+            return Err(ObjectStoreError::ObjectNotFound(object.into()).into());
+        }
+        for path in paths {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+
+    if matches.is_present("abbrev") {
+        println!("{}", objectstore.abbreviate(&identifier)?);
+    } else {
+        println!("{}", identifier);
+    }
+    Ok(())
+}
+
+impl ObjectStore {
+    /// Returns the shortest prefix of 'identifier' which 'identifier_lookup()' resolves
+    /// unambiguously.
+    pub fn abbreviate(&self, identifier: &Identifier) -> Result<String> {
+        let name = identifier.id_base64().0;
+        let shard = identifier.to_pathbuf();
+        let shard = shard.parent().unwrap();
+
+        let mut len = MIN_ABBREV;
+        for entry in self.objects.list_dir(shard)? {
+            let entry = entry?;
+            let other = entry.file_name().as_bytes();
+            // metadata files and the object itself don't count
+            if other.len() != name.len() || other == name {
+                continue;
+            }
+            let common = other
+                .iter()
+                .zip(name.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            len = len.max(common + 1);
+        }
+        Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+    }
+
+    /// Returns every path from the root under which 'identifier' is linked. Directories
+    /// already on the way are not entered again, thus cycles are walked only once.
+    pub fn identifier_paths(&self, identifier: &Identifier) -> Result<Vec<PathBuf>> {
+        let root = self.get_root_id()?;
+        let mut paths = Vec::new();
+        if *identifier == root {
+            paths.push(PathBuf::from("/"));
+        }
+        self.find_paths(
+            &mut vec![root],
+            &mut PathBuf::from("/"),
+            identifier,
+            &mut paths,
+        )?;
+        Ok(paths)
+    }
+
+    fn find_paths(
+        &self,
+        ancestors: &mut Vec<Identifier>,
+        path: &mut PathBuf,
+        identifier: &Identifier,
+        paths: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let directory = ancestors.last().unwrap().clone();
+        let mut entries: Vec<_> = self.list_directory(&directory)?.collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for DirEntry(name, sub_object) in entries {
+            path.push(OsStr::from_bytes(name.to_bytes()));
+            if sub_object == *identifier {
+                paths.push(path.clone());
+            }
+            if sub_object.object_type().is_directory() && !ancestors.contains(&sub_object) {
+                ancestors.push(sub_object);
+                self.find_paths(ancestors, path, identifier, paths)?;
+                ancestors.pop();
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+}
@@ -126,8 +126,19 @@ impl TryFrom<&Flipbase64> for IdentifierKind {
 }
 
 impl Identifier {
+    /// Checks that the object can be the root of a filesystem. Directories with parent are
+    /// bound to their parent and never roots.
+    pub fn ensure_root(&self) -> Result<&Self> {
+        if self.object_type() == ObjectType::DirectoryWithParent {
+            warn!("directory with parent can not be a root: {}", self);
+            Err(ObjectStoreError::NotARoot(self.as_os_str().into()).into())
+        } else {
+            self.ensure_dir()
+        }
+    }
+
     pub fn ensure_dir(&self) -> Result<&Self> {
-        if self.object_type() == ObjectType::Directory {
+        if self.object_type().is_directory() {
             Ok(self)
         } else {
             Err(ObjectStoreError::ObjectType {
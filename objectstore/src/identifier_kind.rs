@@ -6,14 +6,24 @@
 #[repr(u8)] // 3 bits
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ObjectType {
-    File                 = 0 << 5,
-    Directory            = 1 << 5,
-    _PartialFile         = 2 << 5,
-    _FecBlock            = 3 << 5,
-    _DirectoryWithParent = 4 << 5,
-    _Reserved3           = 5 << 5,
-    _Reserved4           = 6 << 5,
-    _Reserved5           = 7 << 5,
+    File                = 0 << 5,
+    Directory           = 1 << 5,
+    _PartialFile        = 2 << 5,
+    _FecBlock           = 3 << 5,
+    DirectoryWithParent = 4 << 5,
+    RevocationList      = 5 << 5,
+    _Reserved4          = 6 << 5,
+    _Reserved5          = 7 << 5,
+}
+
+impl ObjectType {
+    /// Returns true for all types that hold directory entries
+    pub fn is_directory(&self) -> bool {
+        matches!(
+            self,
+            ObjectType::Directory | ObjectType::DirectoryWithParent
+        )
+    }
 }
 
 #[repr(u8)] // 3 bits
@@ -32,10 +42,10 @@ pub enum SharingPolicy {
 #[repr(u8)] // 2 bits
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Mutability {
-    Mutable    = 0,
-    Immutable  = 1,
-    _Versioned = 2,
-    _Reserved  = 3,
+    Mutable   = 0,
+    Immutable = 1,
+    Versioned = 2,
+    _Reserved = 3,
 }
 
 // packs the aspects from above into a byte
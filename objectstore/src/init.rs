@@ -12,8 +12,9 @@ use openat_ct as openat;
 use openat::Dir;
 
 use crate::prelude::*;
+use crate::barrier::GcBarrier;
 use crate::identifier_kind::*;
-use crate::{lock_fd, LockingMethod::*, ObjectStore};
+use crate::{LockMode, LockingMethod::*, ObjectStore, lock_fd};
 
 fn valid_objectstore_dir(dir: &Path, force: bool) -> Result<Dir> {
     // PLANNED: can this be integrated in the clap validator?
@@ -93,6 +94,34 @@ pub(crate) fn opt_init(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
 }
 
 impl ObjectStore {
+    /// Initializes a new objectstore with a fresh root directory at 'dir' when it does not
+    /// exist or is empty, otherwise reuses the existing objectstore. Never overwrites data.
+    /// A shared (PublicAcl) root has no perm manifest yet, see 'init_creator_perm()'.
+    pub fn open_or_init(dir: &Path, sharing_policy: SharingPolicy) -> Result<ObjectStore> {
+        let components = (ObjectType::Directory, sharing_policy, Mutability::Mutable);
+
+        match valid_objectstore_dir(dir, false) {
+            Ok(base_dir) => {
+                debug!("Initialize objectstore in {:?}", dir);
+                let objectstore = ObjectStore::create_objectstore(base_dir, false)?;
+                let root = crate::object::Object::build(components.0, components.1, components.2)
+                    .realize(&objectstore)?;
+                objectstore.set_root(&root.identifier)?;
+                Ok(objectstore)
+            }
+            Err(err)
+                if matches!(
+                    err.downcast_ref(),
+                    Some(ObjectStoreError::ObjectStoreExists(_))
+                ) =>
+            {
+                debug!("Reuse objectstore in {:?}", dir);
+                ObjectStore::open(dir, TryLock)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Create and initialize a new objectstore at the given dir.
     pub fn create_objectstore(dir: Dir, reinit: bool) -> Result<ObjectStore> {
         let _ = Dir::create_dir(&dir, "objects", 0o770);
@@ -109,7 +138,7 @@ impl ObjectStore {
         version.write_all(format!("{}\n", crate::VERSION).as_bytes())?;
 
         // initialize objectstore structure
-        for sub in ["tmp", "delete"] {
+        for sub in ["tmp", "delete", "pins", "locks", "roots"] {
             match objects.create_dir(sub, 0o770) {
                 Ok(()) => {
                     trace!("creating dir: objects/{}", sub);
@@ -148,10 +177,15 @@ impl ObjectStore {
                 }
             })?;
 
-        Ok(ObjectStore {
+        let mut objectstore = ObjectStore {
             version: crate::VERSION,
             objects,
             uberall: UberAll::new()?,
-        })
+            barrier: GcBarrier::default(),
+            lock_info: None,
+            daemon_lock: None,
+        };
+        objectstore.register_lock(LockMode::Exclusive)?;
+        Ok(objectstore)
     }
 }
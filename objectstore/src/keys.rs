@@ -0,0 +1,104 @@
+//! Public keys and signatures.
+//!
+//! Keys are ed25519 keys. A 'KeyId' is the public key in url-safe base64 without padding,
+//! signatures are encoded the same way. Until the node manages keys, the plumbing commands
+//! sign with secret keys from key files, these hold the base64 encoded secret key.
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer};
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::{KeyId, LockingMethod::*, ObjectStore};
+
+pub(crate) fn opt_key(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    if let Some(keyfile) = matches.value_of_os("generate") {
+        let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+        println!("{}", objectstore.generate_key(Path::new(keyfile))?);
+    } else {
+        let keypair = load_key(Path::new(matches.value_of_os("KEYFILE").unwrap()))?;
+        println!("{}", key_id(&keypair));
+    }
+    Ok(())
+}
+
+/// Returns the KeyId of a key pair.
+pub fn key_id(keypair: &Keypair) -> KeyId {
+    base64::encode_config(keypair.public.as_bytes(), base64::URL_SAFE_NO_PAD)
+}
+
+/// Signs 'message', returns the encoded signature.
+pub fn sign(keypair: &Keypair, message: &[u8]) -> String {
+    base64::encode_config(
+        keypair.sign(message).to_bytes().as_ref(),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Verifies that 'signature' is the signature of 'key' over 'message'.
+pub fn verify_signature(key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let public = base64::decode_config(key, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| ObjectStoreError::BadKey(key.into()))?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| ObjectStoreError::BadSignature(key.into()))?;
+
+    public.verify_strict(message, &signature).map_err(|_| {
+        warn!("signature by {} does not verify", key);
+        ObjectStoreError::BadSignature(key.into()).into()
+    })
+}
+
+/// Checks that 'key' is a well formed KeyId.
+pub fn check_key(key: &str) -> Result<()> {
+    match base64::decode_config(key, base64::URL_SAFE_NO_PAD) {
+        Ok(bytes) if PublicKey::from_bytes(&bytes).is_ok() => Ok(()),
+        _ => Err(ObjectStoreError::BadKey(key.into()).into()),
+    }
+}
+
+/// Loads the secret key from 'keyfile'.
+pub fn load_key(keyfile: &Path) -> Result<Keypair> {
+    let text = std::fs::read_to_string(keyfile)?;
+    let secret = base64::decode_config(text.trim(), base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| SecretKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| {
+            ObjectStoreError::OptArgError(format!("{:?} holds no secret key", keyfile))
+        })?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
+
+impl ObjectStore {
+    /// Generates a new key and stores its secret in 'keyfile', which must not exist yet.
+    /// Returns the KeyId.
+    pub fn generate_key(&self, keyfile: &Path) -> Result<KeyId> {
+        let secret = SecretKey::from_bytes(&self.uberall.rng_gen::<[u8; 32]>())?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(keyfile)?;
+        writeln!(
+            file,
+            "{}",
+            base64::encode_config(secret.as_bytes(), base64::URL_SAFE_NO_PAD)
+        )?;
+        file.sync_all()?;
+
+        let public = PublicKey::from(&secret);
+        let key = key_id(&Keypair { secret, public });
+        info!("generated key {}", key);
+        Ok(key)
+    }
+}
@@ -9,36 +9,71 @@ use crate::prelude::*;
 mod optargs;
 pub use self::optargs::optargs;
 
+mod barrier;
 mod errors;
 mod handle;
 mod identifier;
 mod identifier_kind;
+mod keys;
+mod liveset;
 mod object;
+mod objectmeta;
 mod objectpath;
 mod objectstore;
+mod perm;
 mod permissions;
+mod pin;
 mod rev_cursor;
+mod revocation;
+mod roots;
+mod rules;
+mod send;
+mod special;
+mod status;
+mod symlink;
+mod versions;
 mod vfs;
+mod xattr;
 
+mod anonymous;
 mod gc;
+mod getid;
 mod init;
+mod link;
 mod lock;
+mod ls;
 mod mkdir;
 mod show;
 
+pub use errors::ObjectStoreError;
+pub use gc::{GcConfig, GcReport};
 pub use handle::Handle;
 pub use identifier::{Flipbase64, Identifier, IdentifierBin};
 pub use identifier_kind::{Mutability, ObjectType, SharingPolicy};
+pub use keys::{check_key, key_id, load_key, sign, verify_signature};
+pub use link::{CHTYPE_KINDS, parse_kind};
 pub use object::Object;
-pub use permissions::{PermissionCheck, PermissionController};
+pub use objectmeta::{MetadataStamp, ObjectMeta};
+pub use perm::{PendingChange, PermManifest};
+pub use permissions::{KeyExpirePolicy, PermissionCheck, PermissionController};
+pub use pin::DEFAULT_PIN_SET;
+pub use revocation::{Admin, Revocation, RevocationList};
 pub use vfs::VirtualFileSystem;
 pub use objectpath::ObjectPath;
-pub use objectstore::{DirectoryPermissions, ObjectStore, SubObject};
-pub use lock::{lock_fd, LockingMethod};
+pub use objectstore::{DirEntry, DirectoryPermissions, Meta, ObjectStore, SubObject};
+pub use roots::PREVIOUS_ROOT;
+pub use rules::Rules;
+pub use show::{ObjectInfo, PermSummary};
+pub use special::SpecialFile;
+pub use status::StoreStatus;
+pub use versions::{Retention, Version};
+pub use xattr::{UBERALLFS_XATTRS, USER_XATTRS, Xattrs};
+pub use lock::{LockMode, LockingMethod, lock_fd, lock_fd_shared, lock_holders};
 
 // PLANNED: mockup types defined and exported that dont have a implementation
 // yet
 pub type UserId = u32; //TODO: u64
+pub type KeyId = String; // base64 of an ed25519 public key, see 'keys'
 
 /// Objectstore version
 pub const VERSION: u32 = 0;
@@ -55,8 +90,30 @@ pub fn cmd(matches: &ArgMatches) -> Result<()> {
         ("init", Some(sub_m)) => init::opt_init(dir, sub_m),
         ("lock", Some(sub_m)) => lock::opt_lock(dir, sub_m),
         ("gc", Some(sub_m)) => gc::opt_gc(dir, sub_m),
+        ("gc-race", Some(sub_m)) => gc::opt_gc_race(dir, sub_m),
+        ("pin", Some(sub_m)) => pin::opt_pin(dir, sub_m),
         ("mkdir", Some(sub_m)) => mkdir::opt_mkdir(dir, sub_m),
         ("show", Some(sub_m)) => show::opt_show(dir, sub_m),
+        ("get-id", Some(sub_m)) => getid::opt_getid(dir, sub_m),
+        ("ls", Some(sub_m)) => ls::opt_ls(dir, sub_m),
+        ("root", Some(sub_m)) => roots::opt_root(dir, sub_m),
+        ("ln", Some(sub_m)) => link::opt_ln(dir, sub_m),
+        ("unlink", Some(sub_m)) => link::opt_unlink(dir, sub_m),
+        ("mv", Some(sub_m)) => link::opt_mv(dir, sub_m),
+        ("chtype", Some(sub_m)) => link::opt_chtype(dir, sub_m),
+        ("rules", Some(sub_m)) => rules::opt_rules(dir, sub_m),
+        ("key", Some(sub_m)) => keys::opt_key(dir, sub_m),
+        ("revoke", Some(sub_m)) => revocation::opt_revoke(dir, sub_m),
+        ("revocations", Some(sub_m)) => revocation::opt_revocations(dir, sub_m),
+        ("perm", Some(sub_m)) => perm::opt_perm(dir, sub_m),
+        ("chacl", Some(sub_m)) => perm::opt_chacl(dir, sub_m),
+        ("add-anonymous", Some(sub_m)) => anonymous::opt_add_anonymous(dir, sub_m),
+        ("versions", Some(sub_m)) => versions::opt_versions(dir, sub_m),
+        ("symlink", Some(sub_m)) => symlink::opt_symlink(dir, sub_m),
+        ("mknod", Some(sub_m)) => special::opt_mknod(dir, sub_m),
+        ("xattr", Some(sub_m)) => xattr::opt_xattr(dir, sub_m),
+        ("status", Some(sub_m)) => status::opt_status(dir, sub_m),
+        ("send", Some(sub_m)) => send::opt_send(dir, sub_m),
         (name, _) => {
             unimplemented!("subcommand '{}'", name)
         }
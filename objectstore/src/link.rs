@@ -0,0 +1,343 @@
+//! Managing directory entries.
+//!
+//! Directory entries are the identifier links in directories. Linking adds another entry for
+//! an existing object, unlinking only removes the entry and leaves the object for the garbage
+//! collector. Renaming within one directory is atomic, moving to another directory links the
+//! object there first and then unlinks the old entry. Renames through the filesystem replace
+//! an existing target atomically as rename(2) does, the 'mv' command refuses to. A rename a
+//! 'retype' rule applies to links a new object of the demanded kind in place of the old one.
+//! 'chtype' does the same explicitly, keeping the name of the entry.
+use std::ffi::{CString, OsStr, OsString};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use uberall::clap::ArgMatches;
+use uberall::libc;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectpath::ObjectPath;
+use crate::objectstore::is_reserved;
+use crate::{Identifier, LockingMethod::*, ObjectStore, SubObject};
+
+/// The kinds 'chtype' converts to, named as SHARING_MUTABILITY
+pub const CHTYPE_KINDS: [&str; 5] = [
+    "private_mutable",
+    "private_immutable",
+    "public_mutable",
+    "public_immutable",
+    "anonymous_immutable",
+];
+
+pub(crate) fn opt_ln(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let identifier = objectstore.object_lookup(matches.value_of_os("OBJECT").unwrap())?;
+    let (parent, name) = objectstore.new_entry(matches.value_of_os("PATH").unwrap(), None)?;
+    objectstore.create_link(&identifier, SubObject(&parent, &name))
+}
+
+pub(crate) fn opt_unlink(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let (parent, name) = objectstore.existing_entry(matches.value_of_os("PATH").unwrap())?;
+    let identifier = objectstore.remove_link(&SubObject(&parent, &name))?;
+    info!("unlinked {:?}, {} is left for gc", name, identifier);
+    Ok(())
+}
+
+pub(crate) fn opt_mv(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let (from_parent, from_name) =
+        objectstore.existing_entry(matches.value_of_os("FROM").unwrap())?;
+    let mut parents = Vec::new();
+    let (to_parent, to_name) =
+        objectstore.new_entry(matches.value_of_os("TO").unwrap(), Some(&mut parents))?;
+
+    objectstore.rename_link(
+        &SubObject(&from_parent, &from_name),
+        &SubObject(&to_parent, &to_name),
+        &parents,
+        false,
+    )
+}
+
+pub(crate) fn opt_chtype(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let kind = parse_kind(matches.value_of("TYPE").unwrap())?;
+    let (parent, name) = objectstore.existing_entry(matches.value_of_os("PATH").unwrap())?;
+    let identifier = objectstore.change_type(&SubObject(&parent, &name), kind)?;
+    println!("{}", identifier);
+    Ok(())
+}
+
+/// Parses one of the 'CHTYPE_KINDS'.
+pub fn parse_kind(kind: &str) -> Result<(SharingPolicy, Mutability)> {
+    match kind {
+        "private_mutable" => Ok((SharingPolicy::Private, Mutability::Mutable)),
+        "private_immutable" => Ok((SharingPolicy::Private, Mutability::Immutable)),
+        "public_mutable" => Ok((SharingPolicy::PublicAcl, Mutability::Mutable)),
+        "public_immutable" => Ok((SharingPolicy::PublicAcl, Mutability::Immutable)),
+        "anonymous_immutable" => Ok((SharingPolicy::Anonymous, Mutability::Immutable)),
+        _ => Err(ObjectStoreError::OptArgError(format!(
+            "unknown type '{}', expected one of {}",
+            kind,
+            CHTYPE_KINDS.join(", ")
+        ))
+        .into()),
+    }
+}
+
+impl ObjectStore {
+    /// Resolves a path to an entry which does not exist yet, only its last component may be
+    /// missing. Returns the directory and the name of the entry, 'parents' as in
+    /// 'path_lookup()'.
+    fn new_entry(
+        &self,
+        path: &OsStr,
+        parents: Option<&mut Vec<Identifier>>,
+    ) -> Result<(Identifier, OsString)> {
+        let (parent, remaining) = self.path_lookup(Path::new(path), parents)?;
+
+        let mut components = remaining.components();
+        match (components.next(), components.next()) {
+            (Some(name), None) => Ok((parent, name.as_os_str().into())),
+            (None, _) => Err(ObjectStoreError::ObjectExists(path.into()).into()),
+            (Some(name), Some(_)) => {
+                warn!("Parent dir missing: {:?}", name);
+                Err(ObjectStoreError::ObjectNotFound(name.as_os_str().into()).into())
+            }
+        }
+    }
+
+    /// Resolves a path to an existing entry. Returns the directory and the name of the entry.
+    pub fn existing_entry(&self, path: &OsStr) -> Result<(Identifier, OsString)> {
+        let bytes = path.as_bytes();
+        let split = match bytes.iter().rposition(|&b| b == b'/') {
+            Some(split) => split + 1,
+            None => return Err(ObjectStoreError::ObjectNotFound(path.into()).into()),
+        };
+        let (parent_path, name) = (&bytes[..split], OsStr::from_bytes(&bytes[split..]));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(ObjectStoreError::IllegalFileName(name.into()).into());
+        }
+
+        let (parent, remaining) =
+            self.path_lookup(Path::new(OsStr::from_bytes(parent_path)), None)?;
+        if !remaining.as_os_str().is_empty() {
+            return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+        }
+        Ok((parent, name.into()))
+    }
+
+    /// Removes the link to an object from a directory and returns the identifier it linked.
+    /// The object itself is left for the garbage collector.
+    pub(crate) fn remove_link(&self, sub_object: &SubObject) -> Result<Identifier> {
+        if is_reserved(sub_object.1) {
+            warn!("unlink: illegal file name: {:?}", sub_object.1);
+            return Err(ObjectStoreError::IllegalFileName(sub_object.1.into()).into());
+        }
+
+        // fails for user symlinks and special files, these are no links to objects
+        let identifier = self.sub_object_id(sub_object)?;
+        let source = sub_object.to_pathbuf();
+        trace!("unlink: {:?} -> {}", source.as_os_str(), identifier);
+        self.objects.remove_file(&source)?;
+        self.snapshot_on_change(sub_object.0)?;
+        Ok(identifier)
+    }
+
+    /// Converts the object linked by 'sub_object' to another sharing policy and mutability.
+    /// The converted copy replaces the entry atomically, the old object is left for the
+    /// garbage collector. Returns the new identifier.
+    pub fn change_type(
+        &self,
+        sub_object: &SubObject,
+        kind: (SharingPolicy, Mutability),
+    ) -> Result<Identifier> {
+        let identifier = self.sub_object_id(sub_object)?;
+        let retyped = self.retype(&identifier, kind)?;
+        self.rules_check_create(sub_object, retyped.components())?;
+
+        self.replace_link(&retyped, sub_object, "chtype")?;
+        info!("chtype: {} -> {}", identifier, retyped);
+        Ok(retyped)
+    }
+
+    /// Renames the link 'from' to 'to'. With 'replace' an existing entry at 'to' is replaced
+    /// atomically as rename(2) does, otherwise renaming onto an existing entry fails with
+    /// 'ObjectExists'. Within one directory this is atomic and checked against the rename
+    /// rules of the directory, a 'retype' rule replaces the object by a converted copy.
+    /// 'to_parents' are the directories leading to 'to.0' as 'path_lookup()' records them,
+    /// moving an object into one of them or 'to.0' itself would leave it reachable only
+    /// through itself and fails with 'MoveIntoItself'.
+    pub(crate) fn rename_link(
+        &self,
+        from: &SubObject,
+        to: &SubObject,
+        to_parents: &[Identifier],
+        replace: bool,
+    ) -> Result<()> {
+        to.0.ensure_dir()?;
+        if is_reserved(to.1) {
+            warn!("rename: illegal file name: {:?}", to.1);
+            return Err(ObjectStoreError::IllegalFileName(to.1.into()).into());
+        }
+
+        let identifier = self.sub_object_id(from)?;
+        let target = to.to_pathbuf();
+        if replace && from.0 == to.0 && from.1 == to.1 {
+            return Ok(());
+        }
+        // the old entry goes away, the object must not only be reachable through itself
+        if from.0 != to.0 && (identifier == *to.0 || to_parents.contains(&identifier)) {
+            warn!("rename: {} would become unreachable", identifier);
+            return Err(ObjectStoreError::MoveIntoItself(from.1.into()).into());
+        }
+
+        // creating the new entry fails atomically when it exists
+        let exists = |err: Box<dyn std::error::Error>| -> Box<dyn std::error::Error> {
+            match err.downcast_ref::<io::Error>() {
+                Some(ioerr) if ioerr.raw_os_error() == Some(libc::EEXIST) => {
+                    ObjectStoreError::ObjectExists(to.1.into()).into()
+                }
+                _ => err,
+            }
+        };
+
+        let retype = if from.0 == to.0 {
+            self.rules_check_rename(from.0, from.1, to.1, &identifier)?
+        } else {
+            None
+        };
+        if from.0 != to.0 || retype.is_some() {
+            // the retyped object is a new one, it replaces the old entry
+            let linked = match retype {
+                Some(retype) => self.retype(&identifier, retype)?,
+                None => identifier,
+            };
+            if replace && self.check_replace(to, &linked)? {
+                self.check_declared_parent(&linked, to.0)?;
+                self.rules_check_create(to, linked.components())?;
+                self.replace_link(&linked, to, "rename")?;
+            } else {
+                self.create_link(&linked, SubObject(to.0, to.1))
+                    .map_err(exists)?;
+            }
+            self.remove_link(from)?;
+            return Ok(());
+        }
+
+        if replace {
+            self.check_replace(to, &identifier)?;
+        }
+        let _gate = self.barrier.enter();
+        self.barrier.record(&identifier);
+        let source = from.to_pathbuf();
+        trace!(
+            "rename: {:?} -> {:?}",
+            source.as_os_str(),
+            target.as_os_str()
+        );
+        let csource = CString::new(source.as_os_str().as_bytes())?;
+        let ctarget = CString::new(target.as_os_str().as_bytes())?;
+        if unsafe {
+            libc::renameat2(
+                self.objects.as_raw_fd(),
+                csource.as_ptr(),
+                self.objects.as_raw_fd(),
+                ctarget.as_ptr(),
+                if replace { 0 } else { libc::RENAME_NOREPLACE },
+            )
+        } == -1
+        {
+            return Err(exists(io::Error::last_os_error().into()));
+        }
+        drop(_gate);
+        self.snapshot_on_change(to.0)
+    }
+
+    /// Returns whether the entry 'sub_object' exists, whatever it is.
+    pub(crate) fn entry_exists(&self, sub_object: &SubObject) -> Result<bool> {
+        match self.objects.metadata(&sub_object.to_pathbuf()) {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Checks whether 'identifier' may replace the entry 'sub_object' as rename(2) allows:
+    /// directories only replace empty directories, other objects only non-directories.
+    /// Returns whether the entry exists.
+    fn check_replace(&self, sub_object: &SubObject, identifier: &Identifier) -> Result<bool> {
+        let is_dir = identifier.object_type().is_directory();
+        let existing_is_dir = match self.sub_object_id(sub_object) {
+            Ok(existing) if existing.object_type().is_directory() => {
+                let has_entries = self.open_directory(&existing)?.list_self()?.any(|entry| {
+                    entry.map_or(true, |entry| {
+                        !is_reserved(OsStr::from_bytes(entry.name.to_bytes()))
+                    })
+                });
+                if is_dir && has_entries {
+                    return Err(io::Error::from_raw_os_error(libc::ENOTEMPTY).into());
+                }
+                true
+            }
+            Ok(_) => false,
+            // user symlinks and special files
+            Err(err)
+                if matches!(
+                    err.downcast_ref(),
+                    Some(ObjectStoreError::IsSymlink(_) | ObjectStoreError::SpecialFile(_))
+                ) =>
+            {
+                false
+            }
+            Err(err) => {
+                return match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+                    Some(io::ErrorKind::NotFound) => Ok(false),
+                    _ => Err(err),
+                };
+            }
+        };
+
+        match (is_dir, existing_is_dir) {
+            (true, false) => Err(io::Error::from_raw_os_error(libc::ENOTDIR).into()),
+            (false, true) => Err(io::Error::from_raw_os_error(libc::EISDIR).into()),
+            _ => Ok(true),
+        }
+    }
+
+    /// Links 'identifier' in place of the existing entry 'sub_object'. The link is created
+    /// under a reserved temporary name and renamed over the entry, thus it is replaced
+    /// atomically.
+    fn replace_link(
+        &self,
+        identifier: &Identifier,
+        sub_object: &SubObject,
+        op: &str,
+    ) -> Result<()> {
+        let _gate = self.barrier.enter();
+        self.barrier.record(identifier);
+        let target = sub_object.to_pathbuf();
+        // reserved names never show up as entries
+        let mut temp_name = OsString::from(OsStr::from_bytes(&crate::RESERVED_PREFIX));
+        temp_name.push(format!("{}.{}", op, identifier));
+        let temp = target.with_file_name(temp_name);
+        let mut dest = PathBuf::new();
+        dest.push_link(identifier);
+
+        trace!("{}: {:?} -> {}", op, target.as_os_str(), identifier);
+        self.objects.symlink(temp.as_os_str(), dest.as_os_str())?;
+        if let Err(err) = self.objects.local_rename(&temp, &target) {
+            let _ = self.objects.remove_file(&temp);
+            return Err(err.into());
+        }
+        drop(_gate);
+        self.snapshot_on_change(sub_object.0)
+    }
+}
@@ -0,0 +1,185 @@
+//! The set of live objects during garbage collection.
+//!
+//! Live objects are kept sharded like the objectstore directories ('objects/XX/'), thus the
+//! marking threads rarely contend and the sweep can work shard by shard. When more than
+//! 'memory_limit' objects are held in memory they are spilled to an unnamed temporary file
+//! in 'objects/tmp'. Sweeping a shard then only reads back the parts of that shard.
+//! Directories are never spilled, marking looks them up to walk each directory only once.
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use uberall::libc;
+use uberall::parking_lot::Mutex;
+
+use crate::prelude::*;
+use crate::objectstore::FileAccess;
+use crate::{Identifier, IdentifierBin, ObjectStore};
+
+/// Number of shards, one per directory 'objects/XX/'
+pub(crate) const SHARDS: usize = 64 * 64;
+
+/// Size of a spilled identifier
+const RECORD_LEN: usize = std::mem::size_of::<IdentifierBin>();
+
+#[derive(Default)]
+struct Shard {
+    directories: HashSet<IdentifierBin>,
+    objects:     HashSet<IdentifierBin>,
+    /// Offset and number of identifiers of the parts spilled from this shard
+    spilled:     Vec<(u64, usize)>,
+}
+
+struct Spill {
+    file: File,
+    len:  u64,
+}
+
+pub(crate) struct LiveSet {
+    shards:       Vec<Mutex<Shard>>,
+    in_memory:    AtomicUsize,
+    memory_limit: usize,
+    spill:        Mutex<Option<Spill>>,
+}
+
+impl LiveSet {
+    pub(crate) fn new(memory_limit: usize) -> LiveSet {
+        LiveSet {
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::default())).collect(),
+            in_memory: AtomicUsize::new(0),
+            memory_limit,
+            spill: Mutex::new(None),
+        }
+    }
+
+    /// Marks a directory as live, returns false when it was already marked.
+    pub(crate) fn insert_directory(&self, identifier: &Identifier) -> bool {
+        self.shards[shard_of(identifier)]
+            .lock()
+            .directories
+            .insert(identifier.id_bin())
+    }
+
+    /// Marks any other object as live. Spills to disk when the memory limit is exceeded.
+    pub(crate) fn insert(&self, objectstore: &ObjectStore, identifier: &Identifier) -> Result<()> {
+        let inserted = self.shards[shard_of(identifier)]
+            .lock()
+            .objects
+            .insert(identifier.id_bin());
+
+        if inserted && self.in_memory.fetch_add(1, Ordering::Relaxed) >= self.memory_limit {
+            self.spill(objectstore)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&self, objectstore: &ObjectStore) -> Result<()> {
+        // one thread spills, the others continue marking meanwhile
+        let mut spill = match self.spill.try_lock() {
+            Some(spill) => spill,
+            None => return Ok(()),
+        };
+        if self.in_memory.load(Ordering::Relaxed) <= self.memory_limit {
+            return Ok(());
+        }
+
+        if spill.is_none() {
+            *spill = Some(Spill {
+                file: objectstore.openat_file(
+                    Path::new("tmp"),
+                    FileAccess::new()
+                        .readwrite()
+                        .extra_flags(libc::O_TMPFILE)
+                        .get(),
+                    0o600,
+                )?,
+                len:  0,
+            });
+        }
+        let spill = spill.as_mut().unwrap();
+
+        let mut spilled = 0;
+        let mut buffer = Vec::new();
+        for shard in &self.shards {
+            let mut shard = shard.lock();
+            if shard.objects.is_empty() {
+                continue;
+            }
+
+            buffer.clear();
+            for identifier in shard.objects.drain() {
+                buffer.extend_from_slice(&identifier.0);
+            }
+            shard.objects.shrink_to_fit();
+
+            spill.file.write_all_at(&buffer, spill.len)?;
+            let count = buffer.len() / RECORD_LEN;
+            shard.spilled.push((spill.len, count));
+            spill.len += buffer.len() as u64;
+            spilled += count;
+        }
+
+        self.in_memory.fetch_sub(spilled, Ordering::Relaxed);
+        info!(
+            "gc: spilled {} live objects, {} bytes total",
+            spilled, spill.len
+        );
+        Ok(())
+    }
+
+    /// Returns all live identifiers of a shard including the spilled ones. Only used for
+    /// sweeping after marking is completed, the shard is left empty.
+    pub(crate) fn take_shard(&self, shard: usize) -> Result<HashSet<IdentifierBin>> {
+        let Shard {
+            directories,
+            mut objects,
+            spilled,
+        } = std::mem::take(&mut *self.shards[shard].lock());
+        objects.extend(directories);
+
+        if !spilled.is_empty() {
+            let file = match &*self.spill.lock() {
+                Some(spill) => spill.file.try_clone()?,
+                None => unreachable!("shard spilled without spill file"),
+            };
+            for (offset, count) in spilled {
+                let mut buffer = vec![0u8; count * RECORD_LEN];
+                file.read_exact_at(&mut buffer, offset)?;
+                objects.extend(
+                    buffer
+                        .chunks_exact(RECORD_LEN)
+                        .map(|record| IdentifierBin(record.try_into().unwrap())),
+                );
+            }
+        }
+        Ok(objects)
+    }
+}
+
+/// Returns the shard an object is stored in.
+pub(crate) fn shard_of(identifier: &Identifier) -> usize {
+    let name = &identifier.id_base64().0;
+    base64_value(name[0]) * 64 + base64_value(name[1])
+}
+
+/// Returns the directory of a shard relative to 'objects/'.
+pub(crate) fn shard_path(shard: usize) -> PathBuf {
+    const URL_SAFE_ENCODE: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let name = [URL_SAFE_ENCODE[shard / 64], URL_SAFE_ENCODE[shard % 64]];
+    PathBuf::from(String::from_utf8_lossy(&name).into_owned())
+}
+
+fn base64_value(c: u8) -> usize {
+    (match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'-' => 62,
+        _ => 63,
+    }) as usize
+}
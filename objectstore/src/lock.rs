@@ -1,5 +1,13 @@
+//! Locking the objectstore.
+//!
+//! The 'objects' directory is locked with flock(). Every holder records itself in
+//! 'objects/locks/', thus diagnostics can tell who holds the lock.
 use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use uberall::addy::{self, Signal::*};
+use uberall::chrono;
 use uberall::clap::ArgMatches;
 use uberall::libc;
 
@@ -7,43 +15,106 @@ use crate::prelude::*;
 use crate::objectstore::ObjectStore;
 
 pub(crate) fn opt_lock(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
-    let _objectstore = ObjectStore::open(
-        dir.as_ref(),
-        if matches.is_present("wait") {
-            LockingMethod::WaitForLock
-        } else {
-            LockingMethod::TryLock
-        },
-    )?;
+    if matches.is_present("status") {
+        let holders = lock_holders(dir.as_ref())?;
+        if holders.is_empty() {
+            println!("unlocked");
+        }
+        for holder in holders {
+            println!("{}", holder);
+        }
+        return Ok(());
+    }
 
-    // Wait until the process is killed (ctrl-c)
-    loop {
+    let locking_method = if matches.is_present("wait") {
+        LockingMethod::WaitForLock
+    } else {
+        LockingMethod::TryLock
+    };
+    let objectstore = if matches.is_present("shared") {
+        ObjectStore::open_shared(dir.as_ref(), locking_method)?
+    } else {
+        ObjectStore::open(dir.as_ref(), locking_method)?
+    };
+
+    // Hold the lock until interrupted (ctrl-c) or terminated, then drop the objectstore
+    // which removes the lock record
+    static RELEASE: AtomicBool = AtomicBool::new(false);
+    for signal in [SIGINT, SIGTERM, SIGHUP] {
+        addy::mediate(signal)
+            .register("release", |_signal| RELEASE.store(true, Ordering::SeqCst))?
+            .enable()?;
+    }
+    while !RELEASE.load(Ordering::SeqCst) {
         unsafe {
             libc::sleep(1);
         }
     }
+    info!("lock released");
+    drop(objectstore);
+    Ok(())
 }
 
 /// Opening an objectstore will lock its directory, to obtain this lock there are two methods.
 ///
 ///  * TryLock:: Try to lock the objectstore and return an error immediately when that fails.
 ///  * WaitForLock:: Wait until the lock becomes available.
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum LockingMethod {
     TryLock,
     WaitForLock,
 }
 
+/// How an objectstore is locked.
+///
+///  * Shared:: read-only access, runs alongside other shared holders and a mounted
+///    filesystem.
+///  * Exclusive:: changes the objectstore, excludes everyone else.
+///  * Daemon:: a mounted filesystem, it coordinates its own changes. Locks the objectstore
+///    shared, thus read-only tools still work, and takes the exclusive daemon lock so that
+///    only one daemon serves an objectstore.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+    Daemon,
+}
+
+impl LockMode {
+    fn name(&self) -> &'static str {
+        match self {
+            LockMode::Shared => "shared",
+            LockMode::Exclusive => "exclusive",
+            LockMode::Daemon => "daemon",
+        }
+    }
+}
+
 /// Place an exclusive lock on a file descriptor
 /// This lock will exist as long the file descriptor is open.
 #[cfg(unix)]
 pub fn lock_fd<T: std::os::unix::io::AsRawFd>(fd: &T, locking_method: LockingMethod) -> Result<()> {
+    flock(fd.as_raw_fd(), libc::LOCK_EX, locking_method)
+}
+
+/// Place a shared lock on a file descriptor
+/// This lock will exist as long the file descriptor is open.
+#[cfg(unix)]
+pub fn lock_fd_shared<T: std::os::unix::io::AsRawFd>(
+    fd: &T,
+    locking_method: LockingMethod,
+) -> Result<()> {
+    flock(fd.as_raw_fd(), libc::LOCK_SH, locking_method)
+}
+
+#[cfg(unix)]
+fn flock(fd: libc::c_int, operation: libc::c_int, locking_method: LockingMethod) -> Result<()> {
     let mut lockerr;
 
     // first try locking without wait
     loop {
         lockerr = unsafe {
-            if libc::flock(fd.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == -1 {
+            if libc::flock(fd, operation | libc::LOCK_NB) == -1 {
                 *libc::__errno_location()
             } else {
                 0
@@ -60,7 +131,7 @@ pub fn lock_fd<T: std::os::unix::io::AsRawFd>(fd: &T, locking_method: LockingMet
             warn!("Waiting for lock");
             loop {
                 lockerr = unsafe {
-                    if libc::flock(fd.as_raw_fd(), libc::LOCK_EX) == -1 {
+                    if libc::flock(fd, operation) == -1 {
                         *libc::__errno_location()
                     } else {
                         0
@@ -84,3 +155,95 @@ pub fn lock_fd<T: std::os::unix::io::AsRawFd>(fd: &T, locking_method: LockingMet
         Ok(())
     }
 }
+
+/// Distinguishes multiple opens within one process
+static LOCK_SERIAL: AtomicUsize = AtomicUsize::new(0);
+
+impl ObjectStore {
+    /// Records this process as lock holder in 'objects/locks/PID-N'.
+    pub(crate) fn register_lock(&mut self, mode: LockMode) -> Result<()> {
+        match self.objects.create_dir("locks", 0o770) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+            _ => {}
+        }
+
+        let path = PathBuf::from("locks").join(format!(
+            "{}-{}",
+            std::process::id(),
+            LOCK_SERIAL.fetch_add(1, Ordering::Relaxed)
+        ));
+        let info = format!(
+            "pid {}\nstarttime {}\nmode {}\nstarted {}\ncommand {}\n",
+            std::process::id(),
+            process_starttime(std::process::id() as libc::pid_t).unwrap_or(0),
+            mode.name(),
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            std::env::args().collect::<Vec<_>>().join(" ")
+        );
+        self.write_atomic(&path, info.as_bytes())?;
+        self.lock_info = Some(path);
+        Ok(())
+    }
+}
+
+/// Describes the processes holding a lock on the objectstore at 'dir'. Records of processes
+/// which are gone are skipped.
+pub fn lock_holders(dir: &std::path::Path) -> Result<Vec<String>> {
+    let locks = dir.join("objects/locks");
+    let entries = match std::fs::read_dir(&locks) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut holders = Vec::new();
+    for entry in entries {
+        let info = match std::fs::read_to_string(entry?.path()) {
+            Ok(info) => info,
+            // released meanwhile
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let field = |name: &str| {
+            info.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+                .unwrap_or("?")
+        };
+        let pid: libc::pid_t = field("pid").parse().unwrap_or(0);
+        // the pid may have been reused by another process, then its start time differs
+        let starttime: u64 = field("starttime").parse().unwrap_or(0);
+        let gone = pid == 0
+            || (unsafe { libc::kill(pid, 0) } == -1
+                && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH))
+            || (starttime != 0
+                && process_starttime(pid).map_or(false, |current| current != starttime));
+        if gone {
+            trace!("stale lock record: {:?}", info);
+            continue;
+        }
+
+        holders.push(format!(
+            "pid {} {} since {}: {}",
+            pid,
+            field("mode"),
+            field("started"),
+            field("command")
+        ));
+    }
+    holders.sort();
+    Ok(holders)
+}
+
+/// The start time of process 'pid' in clock ticks since boot, from '/proc/PID/stat'.
+fn process_starttime(pid: libc::pid_t) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name in parentheses may contain spaces, the fields after it are
+    // 'state' (3) up to 'starttime' (22)
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(22 - 3)?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,143 @@
+//! Listing directories.
+//!
+//! Lists the objects linked in a directory sorted by name, user symlinks are not objects and
+//! not listed. The long format shows the abbreviated identifier, the identifier components
+//! and the size. Filters select entries by their identifier components, recursion still
+//! descends into directories that are filtered out.
+use std::ffi::OsStr;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::{DirEntry, Identifier, LockingMethod::*, ObjectStore};
+
+/// The values the type filter accepts
+const OBJECT_TYPES: [ObjectType; 4] = [
+    ObjectType::File,
+    ObjectType::Directory,
+    ObjectType::DirectoryWithParent,
+    ObjectType::RevocationList,
+];
+
+/// The values the sharing filter accepts
+const SHARING_POLICIES: [SharingPolicy; 3] = [
+    SharingPolicy::Private,
+    SharingPolicy::PublicAcl,
+    SharingPolicy::Anonymous,
+];
+
+/// The values the mutability filter accepts
+const MUTABILITIES: [Mutability; 3] = [
+    Mutability::Mutable,
+    Mutability::Immutable,
+    Mutability::Versioned,
+];
+
+/// Selects entries by their identifier components, 'None' matches everything
+struct Filter {
+    object_type:    Option<ObjectType>,
+    sharing_policy: Option<SharingPolicy>,
+    mutability:     Option<Mutability>,
+}
+
+impl Filter {
+    fn matches(&self, identifier: &Identifier) -> bool {
+        let (object_type, sharing_policy, mutability) = identifier.components();
+        self.object_type
+            .map_or(true, |wanted| wanted == object_type)
+            && self
+                .sharing_policy
+                .map_or(true, |wanted| wanted == sharing_policy)
+            && self.mutability.map_or(true, |wanted| wanted == mutability)
+    }
+}
+
+pub(crate) fn opt_ls(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+
+    let path = matches
+        .value_of_os("PATH")
+        .unwrap_or_else(|| OsStr::new("/"));
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+    identifier.ensure_dir()?;
+
+    let filter = Filter {
+        object_type:    parse_filter(matches.value_of("type"), &OBJECT_TYPES),
+        sharing_policy: parse_filter(matches.value_of("sharing"), &SHARING_POLICIES),
+        mutability:     parse_filter(matches.value_of("mutability"), &MUTABILITIES),
+    };
+
+    objectstore.list_tree(
+        &mut vec![identifier],
+        &mut PathBuf::new(),
+        &filter,
+        matches.is_present("long"),
+        matches.is_present("recursive"),
+    )
+}
+
+/// Clap already checked the value against the candidates.
+fn parse_filter<T: Debug + Copy>(value: Option<&str>, candidates: &[T]) -> Option<T> {
+    value.map(|value| {
+        *candidates
+            .iter()
+            .find(|candidate| format!("{:?}", candidate).eq_ignore_ascii_case(value))
+            .unwrap()
+    })
+}
+
+impl ObjectStore {
+    /// Prints the entries of the last directory in 'ancestors', recursion skips directories
+    /// already on the way.
+    fn list_tree(
+        &self,
+        ancestors: &mut Vec<Identifier>,
+        path: &mut PathBuf,
+        filter: &Filter,
+        long: bool,
+        recursive: bool,
+    ) -> Result<()> {
+        let directory = ancestors.last().unwrap().clone();
+        let mut entries: Vec<DirEntry> = self.list_directory(&directory)?.collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for entry in entries {
+            path.push(entry.name());
+            let identifier = entry.identifier();
+
+            if filter.matches(identifier) {
+                if long {
+                    let (object_type, sharing_policy, mutability) = identifier.components();
+                    println!(
+                        "{} {:?} {:?} {:?} {} {}",
+                        self.abbreviate(identifier)?,
+                        object_type,
+                        sharing_policy,
+                        mutability,
+                        self.object_metadata(identifier)?.len(),
+                        path.display()
+                    );
+                } else {
+                    println!("{}", path.display());
+                }
+            }
+
+            if recursive
+                && identifier.object_type().is_directory()
+                && !ancestors.contains(identifier)
+            {
+                ancestors.push(identifier.clone());
+                self.list_tree(ancestors, path, filter, long, recursive)?;
+                ancestors.pop();
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+}
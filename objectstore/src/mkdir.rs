@@ -14,6 +14,16 @@ pub(crate) fn opt_mkdir(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
     let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
 
     let mut sharing_policy = SharingPolicy::Private;
+    let object_type = if matches.is_present("with-parent") {
+        ObjectType::DirectoryWithParent
+    } else {
+        ObjectType::Directory
+    };
+    let mutability = if matches.is_present("versioned") {
+        Mutability::Versioned
+    } else {
+        Mutability::Mutable
+    };
 
     let acl = if let Some(_acls) = matches.value_of("acl") {
         sharing_policy = SharingPolicy::PublicAcl;
@@ -64,6 +74,12 @@ pub(crate) fn opt_mkdir(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
             }
         }
 
+        // check the rules before creating a new object which would become garbage otherwise
+        objectstore.rules_check_create(
+            &SubObject(&src, remaining.components().last().unwrap().as_os_str()),
+            (object_type, sharing_policy, mutability),
+        )?;
+
         let object = match matches.value_of_os("SOURCE") {
             Some(path) => {
                 if acl.is_some() {
@@ -86,9 +102,15 @@ pub(crate) fn opt_mkdir(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
                 Object::from(source_id)
             }
 
-            None => Object::build(ObjectType::Directory, sharing_policy, Mutability::Mutable)
-                .acl(&acl)
-                .realize(&objectstore)?,
+            None => {
+                let builder = Object::build(object_type, sharing_policy, mutability).acl(&acl);
+                if object_type == ObjectType::DirectoryWithParent {
+                    builder.parent(&src)
+                } else {
+                    builder
+                }
+                .realize(&objectstore)?
+            }
         };
 
         trace!("identifier: {:?}", &object.identifier);
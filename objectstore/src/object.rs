@@ -1,14 +1,18 @@
 use std::fmt::{self, Debug};
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+use uberall::libc;
 
 use crate::prelude::*;
-use crate::objectstore::{DirectoryPermissions, ObjectStore};
+use crate::objectstore::{DirectoryPermissions, FileAccess, FilePermissions, ObjectStore};
 
 #[derive(Debug)]
 pub struct Acl;
 #[derive(Debug)]
 pub struct Creator;
 
-use crate::identifier::{Identifier, IdentifierBuilder};
+use crate::identifier::{Identifier, IdentifierBin, IdentifierBuilder};
 use crate::identifier_kind::*;
 
 /// An Objectstore object
@@ -73,10 +77,44 @@ impl ObjectBuilder {
         self
     }
 
+    /// Sets the content for objects which are created from existing data.
+    #[must_use = "configure the builder and finally call realize()"]
+    pub fn source(mut self, file: std::fs::File) -> Self {
+        match &mut self.opts {
+            ObjectImpl::AnonymousImmutableFile { source }
+            | ObjectImpl::PublicImmutableFile { source, .. } => *source = Some(file),
+            _ => warn!("source ignored for {:?}", self.identifier.components()),
+        }
+        self
+    }
+
+    /// Makes the new object an immutable snapshot of the current content of 'identifier'.
+    #[must_use = "configure the builder and finally call realize()"]
+    pub fn snapshot_of(mut self, identifier: &Identifier) -> Self {
+        match &mut self.opts {
+            ObjectImpl::PrivateSnapshot { of } => *of = Some(identifier.clone()),
+            _ => warn!("snapshot ignored for {:?}", self.identifier.components()),
+        }
+        self
+    }
+
+    /// Declares the parent of a directory with parent.
+    #[must_use = "configure the builder and finally call realize()"]
+    pub fn parent(mut self, identifier: &Identifier) -> Self {
+        match &mut self.opts {
+            ObjectImpl::DirectoryWithParent { parent } => *parent = Some(identifier.clone()),
+            _ => warn!("parent ignored for {:?}", self.identifier.components()),
+        }
+        self
+    }
+
     /// Realizes the final Object. This creates the respective files in the
     /// backing 'Objectstore'.
     pub fn realize(self, objectstore: &ObjectStore) -> Result<Object> {
-        self.opts.realize(self.identifier, objectstore)
+        let _gate = objectstore.barrier.enter();
+        let object = self.opts.realize(self.identifier, objectstore)?;
+        objectstore.barrier.record(&object.identifier);
+        Ok(object)
     }
 }
 
@@ -85,10 +123,22 @@ impl ObjectBuilder {
 enum ObjectImpl {
     NotSupported,
     PrivateMutable,
+    DirectoryWithParent {
+        parent: Option<Identifier>,
+    },
+    PrivateVersioned,
+    PublicMutableDirectory,
+    PrivateSnapshot {
+        of: Option<Identifier>,
+    },
+    RevocationList,
+    AnonymousImmutableFile {
+        source: Option<std::fs::File>,
+    },
     PublicImmutableFile {
         creator: Option<Creator>,
         acl:     Option<Acl>,
-        // from file
+        source:  Option<std::fs::File>,
     },
 }
 
@@ -97,10 +147,19 @@ impl ObjectImpl {
     fn new(kind: IdentifierKind) -> ObjectImpl {
         use crate::identifier_kind::{Mutability::*, ObjectType::*, SharingPolicy::*};
         match kind.components() {
+            (RevocationList, Private, Mutable) => ObjectImpl::RevocationList,
+            (DirectoryWithParent, Private, Mutable) => {
+                ObjectImpl::DirectoryWithParent { parent: None }
+            }
             (_, Private, Mutable) => ObjectImpl::PrivateMutable,
+            (File | Directory, Private, Versioned) => ObjectImpl::PrivateVersioned,
+            (Directory, PublicAcl, Mutable) => ObjectImpl::PublicMutableDirectory,
+            (File | Directory, Private, Immutable) => ObjectImpl::PrivateSnapshot { of: None },
+            (File, Anonymous, Immutable) => ObjectImpl::AnonymousImmutableFile { source: None },
             (File, PublicAcl, Immutable) => ObjectImpl::PublicImmutableFile {
                 creator: None,
                 acl:     None,
+                source:  None,
             },
             _ => ObjectImpl::NotSupported,
         }
@@ -110,6 +169,71 @@ impl ObjectImpl {
     fn realize(self, identifier: IdentifierBuilder, objectstore: &ObjectStore) -> Result<Object> {
         match self {
             ObjectImpl::PrivateMutable => {
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                if identifier.object_type() == ObjectType::File {
+                    objectstore.openat_file(
+                        &identifier.to_pathbuf(),
+                        FileAccess::new()
+                            .writeonly()
+                            .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                            .get(),
+                        FilePermissions::new().full().get(),
+                    )?;
+                } else {
+                    objectstore
+                        .create_directory(&identifier, DirectoryPermissions::new().full())?;
+                }
+
+                Ok(Object {
+                    identifier,
+                    opts: self,
+                })
+            }
+
+            ObjectImpl::DirectoryWithParent { parent } => {
+                let parent = parent.ok_or_else(|| {
+                    ObjectStoreError::OptArgError(String::from(
+                        "directories with parent need a parent",
+                    ))
+                })?;
+                parent.ensure_dir()?;
+
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                objectstore.create_directory(&identifier, DirectoryPermissions::new().full())?;
+                let mut meta = objectstore.object_meta(&identifier)?;
+                meta.set("parent", &parent);
+                objectstore.write_object_meta(&identifier, &meta)?;
+
+                Ok(Object {
+                    identifier,
+                    opts: ObjectImpl::DirectoryWithParent { parent: None },
+                })
+            }
+
+            ObjectImpl::PrivateVersioned => {
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                if identifier.object_type() == ObjectType::Directory {
+                    objectstore
+                        .create_directory(&identifier, DirectoryPermissions::new().full())?;
+                } else {
+                    objectstore.openat_file(
+                        &identifier.to_pathbuf(),
+                        FileAccess::new()
+                            .writeonly()
+                            .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                            .get(),
+                        FilePermissions::new().full().get(),
+                    )?;
+                }
+
+                Ok(Object {
+                    identifier,
+                    opts: self,
+                })
+            }
+
+            ObjectImpl::PublicMutableDirectory => {
+                // access is granted by the perm manifest, set up by the creator afterwards
                 let identifier = identifier.with_binary(objectstore.rng_identifier());
                 objectstore.create_directory(&identifier, DirectoryPermissions::new().full())?;
 
@@ -119,8 +243,144 @@ impl ObjectImpl {
                 })
             }
 
-            ObjectImpl::PublicImmutableFile { .. } => {
-                todo!();
+            ObjectImpl::PrivateSnapshot { of } => {
+                let of = of.ok_or_else(|| {
+                    ObjectStoreError::OptArgError(String::from(
+                        "immutable private objects are created as snapshots",
+                    ))
+                })?;
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                objectstore.copy_snapshot(&of, &identifier)?;
+
+                Ok(Object {
+                    identifier,
+                    opts: ObjectImpl::PrivateSnapshot { of: None },
+                })
+            }
+
+            ObjectImpl::RevocationList => {
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                objectstore.openat_file(
+                    &identifier.to_pathbuf(),
+                    FileAccess::new()
+                        .writeonly()
+                        .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                        .get(),
+                    FilePermissions::new().full().get(),
+                )?;
+
+                Ok(Object {
+                    identifier,
+                    opts: self,
+                })
+            }
+
+            ObjectImpl::AnonymousImmutableFile { source } => {
+                use std::io::{Read, Write};
+
+                let mut source = source.ok_or_else(|| {
+                    ObjectStoreError::OptArgError(String::from(
+                        "anonymous files are created from a source",
+                    ))
+                })?;
+
+                // copy to a tempfile while hashing, the hash becomes the identifier
+                let tmp = PathBuf::from("tmp").join(
+                    Identifier::build(IdentifierKind::create(
+                        ObjectType::File,
+                        SharingPolicy::Anonymous,
+                        Mutability::Immutable,
+                    ))
+                    .with_binary(objectstore.rng_identifier())
+                    .as_os_str(),
+                );
+                let mut file = objectstore.openat_file(
+                    &tmp,
+                    FileAccess::new()
+                        .writeonly()
+                        .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                        .get(),
+                    FilePermissions::new().read().get(),
+                )?;
+
+                let mut hasher = Sha256::new();
+                let mut buffer = vec![0u8; 65536];
+                loop {
+                    let len = match source.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(len) => len,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => {
+                            objectstore.objects.remove_file(&tmp).ok();
+                            return Err(err.into());
+                        }
+                    };
+                    hasher.update(&buffer[..len]);
+                    file.write_all(&buffer[..len])?;
+                }
+                file.sync_all()?;
+                drop(file);
+
+                let identifier = identifier.with_binary(IdentifierBin(hasher.finalize().into()));
+
+                // same content gives the same identifier, existing objects are reused
+                if objectstore.object_metadata(&identifier).is_ok() {
+                    trace!("anonymous object exists: {}", identifier);
+                    objectstore.objects.remove_file(&tmp)?;
+                } else {
+                    info!("anonymous object: {}", identifier);
+                    objectstore
+                        .objects
+                        .local_rename(&tmp, &identifier.to_pathbuf())?;
+                }
+
+                Ok(Object {
+                    identifier,
+                    opts: ObjectImpl::AnonymousImmutableFile { source: None },
+                })
+            }
+
+            ObjectImpl::PublicImmutableFile {
+                creator,
+                acl,
+                source,
+            } => {
+                let mut source = source.ok_or_else(|| {
+                    ObjectStoreError::OptArgError(String::from(
+                        "public immutable files are created from a source",
+                    ))
+                })?;
+
+                // PLANNED: the creator signs the object and the acl becomes its perm manifest
+                let identifier = identifier.with_binary(objectstore.rng_identifier());
+                let tmp = PathBuf::from("tmp").join(identifier.as_os_str());
+                let mut file = objectstore.openat_file(
+                    &tmp,
+                    FileAccess::new()
+                        .writeonly()
+                        .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                        .get(),
+                    FilePermissions::new().read().get(),
+                )?;
+                if let Err(err) = io::copy(&mut source, &mut file).and_then(|_| file.sync_all()) {
+                    objectstore.objects.remove_file(&tmp).ok();
+                    return Err(err.into());
+                }
+                drop(file);
+
+                info!("public immutable object: {}", identifier);
+                objectstore
+                    .objects
+                    .local_rename(&tmp, &identifier.to_pathbuf())?;
+
+                Ok(Object {
+                    identifier,
+                    opts: ObjectImpl::PublicImmutableFile {
+                        creator,
+                        acl,
+                        source: None,
+                    },
+                })
             }
 
             ObjectImpl::NotSupported => {
@@ -132,6 +392,11 @@ impl ObjectImpl {
     pub fn delete_method(&self) -> DeleteMethod {
         match self {
             ObjectImpl::PrivateMutable => DeleteMethod::Immediate,
+            ObjectImpl::DirectoryWithParent { .. } => DeleteMethod::Immediate,
+            ObjectImpl::PrivateVersioned => DeleteMethod::Immediate,
+            ObjectImpl::PrivateSnapshot { .. } => DeleteMethod::Immediate,
+            ObjectImpl::RevocationList => DeleteMethod::Immediate,
+            ObjectImpl::AnonymousImmutableFile { .. } => DeleteMethod::Immediate,
             _ => DeleteMethod::Unknown,
         }
     }
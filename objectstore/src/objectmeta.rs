@@ -0,0 +1,184 @@
+//! The 'meta' metadata of objects. Stored as simple 'key value' lines, holds authority, trail,
+//! generation and distribution information.
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt;
+
+use uberall::SerialNonce;
+
+use crate::prelude::*;
+use crate::objectstore::Meta;
+use crate::{Identifier, ObjectStore};
+
+/// Key/value pairs of an objects 'meta' metadata
+#[derive(Debug, Default)]
+pub struct ObjectMeta(BTreeMap<String, String>);
+
+impl ObjectMeta {
+    /// Parse from the textual representation
+    pub fn parse(text: &str) -> Result<ObjectMeta> {
+        let mut map = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let (key, value) = line.split_once(' ').ok_or_else(|| {
+                ObjectStoreError::ObjectStoreFatal(format!("malformed meta line: {:?}", line))
+            })?;
+            map.insert(key.into(), value.into());
+        }
+        Ok(ObjectMeta(map))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn set<T: ToString>(&mut self, key: &str, value: T) {
+        self.0.insert(key.into(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+}
+
+impl fmt::Display for ObjectMeta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        for (key, value) in &self.0 {
+            writeln!(f, "{} {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every signed metadata block carries a stamp made of a generation counter and a serial
+/// nonce. Both must grow with every change, this makes replayed old metadata detectable.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MetadataStamp {
+    pub generation: u64,
+    pub nonce:      SerialNonce,
+}
+
+impl MetadataStamp {
+    /// Length of the binary representation
+    pub const LEN: usize = 8 + 16;
+
+    /// The binary representation as embedded in signed metadata blocks.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..8].copy_from_slice(&self.generation.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.nonce.0.to_be_bytes());
+        bytes
+    }
+
+    /// Decode the binary representation.
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> MetadataStamp {
+        MetadataStamp {
+            generation: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+            nonce:      SerialNonce(u128::from_be_bytes(bytes[8..].try_into().unwrap())),
+        }
+    }
+
+    /// Parse the textual representation, 'generation:nonce'.
+    pub fn parse(text: &str) -> Result<MetadataStamp> {
+        let malformed =
+            || ObjectStoreError::ObjectStoreFatal(format!("malformed stamp: {:?}", text));
+        let (generation, nonce) = text.split_once(':').ok_or_else(malformed)?;
+        Ok(MetadataStamp {
+            generation: generation.parse().map_err(|_| malformed())?,
+            nonce:      SerialNonce(u128::from_str_radix(nonce, 16).map_err(|_| malformed())?),
+        })
+    }
+
+    /// A stamp supersedes an older one only when both the generation and the nonce grew.
+    pub fn supersedes(&self, older: &MetadataStamp) -> bool {
+        self.generation > older.generation && self.nonce > older.nonce
+    }
+}
+
+impl fmt::Display for MetadataStamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}:{:032x}", self.generation, self.nonce.0)
+    }
+}
+
+impl ObjectStore {
+    /// Returns the 'meta' metadata of an object, empty when it has none yet.
+    pub fn object_meta(&self, identifier: &Identifier) -> Result<ObjectMeta> {
+        match self.read_metadata(identifier, Meta::Meta)? {
+            Some(data) => ObjectMeta::parse(std::str::from_utf8(&data)?),
+            None => Ok(ObjectMeta::default()),
+        }
+    }
+
+    /// Replaces the 'meta' metadata of an object.
+    pub(crate) fn write_object_meta(
+        &self,
+        identifier: &Identifier,
+        meta: &ObjectMeta,
+    ) -> Result<()> {
+        self.write_metadata(identifier, Meta::Meta, meta.to_string().as_bytes())
+    }
+
+    /// Returns the stamp of the newest metadata seen for an object.
+    pub fn last_stamp(&self, identifier: &Identifier) -> Result<Option<MetadataStamp>> {
+        self.object_meta(identifier)?
+            .get("stamp")
+            .map(MetadataStamp::parse)
+            .transpose()
+    }
+
+    /// Creates the stamp for a new local metadata block of an object. It supersedes the last
+    /// recorded stamp and gets recorded by 'accept_stamp()' once the block becomes effective.
+    pub fn next_stamp(&self, identifier: &Identifier) -> Result<MetadataStamp> {
+        let stamp = match self.last_stamp(identifier)? {
+            Some(last) => MetadataStamp {
+                generation: last.generation + 1,
+                nonce:      self.uberall.serial_nonce_next(last.nonce).ok_or_else(|| {
+                    ObjectStoreError::ObjectStoreFatal(format!(
+                        "serial nonce exhausted for {}",
+                        identifier
+                    ))
+                })?,
+            },
+            None => MetadataStamp {
+                generation: 0,
+                nonce:      self.uberall.serial_nonce(),
+            },
+        };
+
+        trace!("next_stamp: {}: {}", identifier, stamp);
+        Ok(stamp)
+    }
+
+    /// Checks the stamp of a metadata block against the newest one seen before. Older or
+    /// reused stamps are rejected as replay. Returns false when it is the newest one already.
+    pub fn check_stamp(&self, identifier: &Identifier, stamp: MetadataStamp) -> Result<bool> {
+        match self.last_stamp(identifier)? {
+            Some(last) if last == stamp => Ok(false),
+            Some(last) if !stamp.supersedes(&last) => {
+                warn!(
+                    "metadata replay on {}: {} after {}",
+                    identifier, stamp, last
+                );
+                Err(ObjectStoreError::MetadataReplay {
+                    object: identifier.as_os_str().into(),
+                    have:   last,
+                    got:    stamp,
+                }
+                .into())
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Validates the stamp of local, imported or received metadata with 'check_stamp()' and
+    /// records it as the newest one. The same stamp again is accepted as no-op.
+    pub fn accept_stamp(&self, identifier: &Identifier, stamp: MetadataStamp) -> Result<()> {
+        if self.check_stamp(identifier, stamp)? {
+            trace!("accept_stamp: {}: {}", identifier, stamp);
+            let mut meta = self.object_meta(identifier)?;
+            meta.set("stamp", stamp);
+            self.write_object_meta(identifier, &meta)?;
+        }
+        Ok(())
+    }
+}
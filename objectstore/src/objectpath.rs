@@ -5,6 +5,7 @@ use std::path::PathBuf;
 
 use crate::prelude::*;
 use crate::identifier::Identifier;
+use crate::objectstore::Meta;
 
 #[inline]
 pub fn from_bytes(bytes: &[u8]) -> PathBuf {
@@ -19,11 +20,15 @@ pub trait ObjectPath {
     fn push_identifier(&mut self, identifier: &Identifier) -> &mut Self;
 
     fn push_link(&mut self, identifier: &Identifier) -> &mut Self;
+
+    fn push_metadata(&mut self, identifier: &Identifier, metadata: Meta) -> &mut Self;
 }
 
 impl ObjectPath for PathBuf {
     /// normalize a path by removing all current dir ('.') and parent dir
-    /// ('*/..') references.
+    /// ('*/..') references. This is purely lexical, paths into the objectstore
+    /// resolve '..' along the traversed directories instead, see
+    /// 'ObjectStore::traverse_path()'.
     fn normalize(&mut self) -> Result<&mut Self> {
         let mut new_path = PathBuf::new();
         for p in self.iter() {
@@ -57,4 +62,11 @@ impl ObjectPath for PathBuf {
         self.push(OsStr::from_bytes(&identifier.id_base64().0));
         self
     }
+
+    /// create a 'ab/flipbase64identifier.extension' path to an objects metadata
+    fn push_metadata(&mut self, identifier: &Identifier, metadata: Meta) -> &mut Self {
+        self.push_identifier(identifier);
+        self.set_extension(metadata.extension());
+        self
+    }
 }
@@ -1,9 +1,9 @@
 use std::convert::TryInto;
-use std::ffi::{OsStr, OsString};
+use std::ffi::{CString, OsStr, OsString};
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::os::unix::prelude::RawFd;
-use std::{fs::OpenOptions, path::Path, path::PathBuf};
+use std::{fs::File, fs::OpenOptions, path::Path, path::PathBuf};
 
 use openat_ct as openat;
 use openat::{Dir, DirIter, Entry, Metadata, SimpleType};
@@ -12,12 +12,62 @@ use uberall::{lazy_static::lazy_static, libc, UberAll};
 use itertools::repeat_n;
 
 use crate::prelude::*;
+use crate::barrier::GcBarrier;
+use crate::lock::{lock_fd_shared, lock_holders};
 use crate::{
-    lock_fd, objectpath, Flipbase64, Handle, Identifier, IdentifierBin, LockingMethod, Object,
-    ObjectPath,
+    Flipbase64, Handle, Identifier, IdentifierBin, LockMode, LockingMethod, Object, ObjectPath,
+    ObjectType, lock_fd, objectpath,
 };
 
-pub struct Meta;
+/// The kinds of metadata that can be associated with an object. Metadata is stored next to
+/// the object with the same name plus an extension per kind.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Meta {
+    /// Security manifest, access control
+    Perm,
+    /// Authority/trail/generation/distribution
+    Meta,
+    /// Maps to the nodes holding the data
+    Dmap,
+    /// Hash list for immutable files
+    Hash,
+    /// Pointer to a new identifier
+    Link,
+    /// Policies for directories
+    Rule,
+    /// Proposed change of the security manifest collecting signatures
+    Pend,
+    /// User extended attributes
+    Xatr,
+}
+
+impl Meta {
+    /// All metadata kinds in a stable order
+    pub const ALL: [Meta; 8] = [
+        Meta::Perm,
+        Meta::Meta,
+        Meta::Dmap,
+        Meta::Hash,
+        Meta::Link,
+        Meta::Rule,
+        Meta::Pend,
+        Meta::Xatr,
+    ];
+
+    /// The filename extension used for this kind of metadata
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Meta::Perm => "perm",
+            Meta::Meta => "meta",
+            Meta::Dmap => "dmap",
+            Meta::Hash => "hash",
+            Meta::Link => "link",
+            Meta::Rule => "rule",
+            Meta::Pend => "pend",
+            Meta::Xatr => "xatr",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ObjectStore {
@@ -27,7 +77,14 @@ pub struct ObjectStore {
     // handle:             Dir, // FIXME: remove handle, needs only objects and lock on that
     pub(crate) objects: Dir,
 
-    pub(crate) uberall: UberAll,
+    pub(crate) uberall:     UberAll,
+    /// Write barrier for online garbage collection
+    pub(crate) barrier:     GcBarrier,
+    /// This processes record in 'objects/locks/'
+    pub(crate) lock_info:   Option<PathBuf>,
+    /// Held by the daemon serving the objectstore
+    #[allow(dead_code)]
+    pub(crate) daemon_lock: Option<File>,
     /* TODO: log: File, logging 'dangerous' actions to be undone
      * PLANNED: pid: dir stack for all open dir handles (cwd/parents)
      * PLANNED: fd/object cache (drop handles when permissions get changed), MRU
@@ -59,10 +116,45 @@ impl ObjectStore {
 
     /// Opens an ObjectStore at the given path.
     pub fn open(dir: &Path, locking_method: LockingMethod) -> Result<ObjectStore> {
+        Self::open_with(dir, LockMode::Exclusive, locking_method)
+    }
+
+    /// Opens an objectstore for read-only access, this can run alongside a mounted
+    /// filesystem.
+    pub fn open_shared(dir: &Path, locking_method: LockingMethod) -> Result<ObjectStore> {
+        Self::open_with(dir, LockMode::Shared, locking_method)
+    }
+
+    /// Opens an objectstore locked with 'lock_mode'.
+    pub fn open_with(
+        dir: &Path,
+        lock_mode: LockMode,
+        locking_method: LockingMethod,
+    ) -> Result<ObjectStore> {
         let mut objects_dir = PathBuf::from(dir);
         objects_dir.push("objects");
         let objects = Dir::flags().open(&objects_dir)?;
-        lock_fd(&objects, locking_method)?;
+
+        let mut daemon_lock = None;
+        match lock_mode {
+            LockMode::Shared => lock_fd_shared(&objects, locking_method),
+            LockMode::Exclusive => lock_fd(&objects, locking_method),
+            LockMode::Daemon => {
+                let file = objects.update_file("daemon.lock", 0o660)?;
+                lock_fd(&file, locking_method)?;
+                daemon_lock = Some(file);
+                lock_fd_shared(&objects, locking_method)
+            }
+        }
+        .map_err(|err| match err.downcast_ref() {
+            Some(ObjectStoreError::NoLock) => match lock_holders(dir) {
+                Ok(holders) if !holders.is_empty() => {
+                    ObjectStoreError::LockedBy(holders.join(", ")).into()
+                }
+                _ => err,
+            },
+            _ => err,
+        })?;
 
         let version = Self::get_version(dir)?;
         debug!("open {:?}, version: {}", dir, version);
@@ -70,11 +162,16 @@ impl ObjectStore {
             return Err(ObjectStoreError::UnsupportedObjectStore(version).into());
         }
 
-        Ok(ObjectStore {
+        let mut objectstore = ObjectStore {
             version,
             objects,
             uberall: UberAll::new()?,
-        })
+            barrier: GcBarrier::default(),
+            lock_info: None,
+            daemon_lock,
+        };
+        objectstore.register_lock(lock_mode)?;
+        Ok(objectstore)
     }
 
     /// Returns an all-random binary representaton of an Object Identifier.
@@ -83,6 +180,8 @@ impl ObjectStore {
     }
 
     pub(crate) fn import(&self, _archive: &OsStr) -> Result<Object> {
+        // PLANNED: archives, their signed metadata goes through the same checks as received
+        // metadata, see 'receive_perm_manifest()'
         unimplemented!()
     }
 
@@ -156,6 +255,8 @@ impl ObjectStore {
     /// The path is traversed as much as possible, optionally storing the
     /// identifiers (parents) leading to that. Returns the finally found
     /// identifiers and the rest of the path thats is not existant.
+    /// Directories have no implicit parent, '..' goes back along the traversed
+    /// chain, see 'traverse_path()'.
     pub fn path_lookup(
         &self,
         path: &Path,
@@ -168,49 +269,77 @@ impl ObjectStore {
                 static ref PATH_RE: Regex = Regex::new(r"^(?:([^/]{4,44})/|)/(.*)").unwrap();
             }
 
-            let (root, mut path) =
-                if let Some(captures) = PATH_RE.captures(path.as_os_str().as_bytes()) {
-                    let root;
-                    let id: &OsStr = OsStrExt::from_bytes(if let Some(capture) = captures.get(1) {
-                        capture.as_bytes()
-                    } else {
-                        root = self.get_root_id()?;
-                        &root.id_base64().0
-                    });
-                    (
-                        self.identifier_lookup(id)?,
-                        objectpath::from_bytes(captures.get(2).unwrap().as_bytes()),
-                    )
+            let (root, path) = if let Some(captures) = PATH_RE.captures(path.as_os_str().as_bytes())
+            {
+                let root;
+                let id: &OsStr = OsStrExt::from_bytes(if let Some(capture) = captures.get(1) {
+                    capture.as_bytes()
                 } else {
-                    return Err(
-                        ObjectStoreError::ObjectStoreFatal(String::from("Invalid Path")).into(),
-                    );
-                };
-
-            path.normalize()?;
+                    root = self.get_root_id()?;
+                    &root.id_base64().0
+                });
+                (
+                    self.identifier_lookup(id)?,
+                    objectpath::from_bytes(captures.get(2).unwrap().as_bytes()),
+                )
+            } else {
+                return Err(
+                    ObjectStoreError::ObjectStoreFatal(String::from("Invalid Path")).into(),
+                );
+            };
 
             self.traverse_path(root, path, parents)
         }
     }
 
-    /// Walk the path starting at root following existing elements
+    /// Walk the path starting at root following existing elements. The chain of
+    /// directories leading to the current object is recorded, '..' returns to the
+    /// previous one. Going up from the start is only possible from directories with
+    /// parent to their declared parent. '..' within the not existing rest of the path
+    /// fails with 'ObjectNotFound'. Reaching a directory that is already in the chain is a
+    /// cycle, the chain is cut back to its first occurrence. The chain is stored in
+    /// 'parents' when given.
     pub(crate) fn traverse_path(
         &self,
         mut root: Identifier,
         path: PathBuf,
-        _parents: Option<&mut Vec<Identifier>>,
+        parents: Option<&mut Vec<Identifier>>,
     ) -> Result<(Identifier, PathBuf)> {
-        // TODO: track parents
+        let mut chain = Vec::new();
         let mut out = PathBuf::new();
 
         let mut still_going = true;
         for p in path.iter() {
+            if p == "." {
+                continue;
+            }
+            if p == ".." {
+                if !still_going {
+                    // a missing directory has no parent to go back to
+                    trace!("traverse '..' of missing {:?}", &out);
+                    return Err(ObjectStoreError::ObjectNotFound(out.into_os_string()).into());
+                } else if let Some(parent) = chain.pop() {
+                    root = parent;
+                } else if let Some(parent) = self.declared_parent(&root)? {
+                    trace!("traverse into declared parent: {:?}", &parent);
+                    root = parent;
+                } else {
+                    return Err(ObjectStoreError::NoParent.into());
+                }
+                continue;
+            }
+
             let subobject = SubObject(&root, p);
             if still_going {
                 trace!("traverse element: {:?}", &p);
                 match self.sub_object_id(&subobject) {
                     Ok(r) => {
                         trace!("subobject: ok {:?}", &r);
+                        chain.push(root);
+                        if let Some(pos) = chain.iter().position(|parent| *parent == r) {
+                            debug!("cycle at {:?}: {:?}", &p, &r);
+                            chain.truncate(pos);
+                        }
                         root = r;
                     }
                     Err(err) => match err.downcast_ref::<io::Error>().map(io::Error::kind) {
@@ -232,6 +361,9 @@ impl ObjectStore {
             }
         }
 
+        if let Some(parents) = parents {
+            *parents = chain;
+        }
         Ok((root, out))
     }
 
@@ -239,29 +371,184 @@ impl ObjectStore {
     pub fn sub_object_id(&self, sub_object: &SubObject) -> Result<Identifier> {
         sub_object.0.ensure_dir()?;
 
-        let r = self.objects.read_link(&sub_object.to_pathbuf())?;
+        let r = match self.objects.read_link(&sub_object.to_pathbuf()) {
+            Ok(r) => r,
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => {
+                return Err(ObjectStoreError::SpecialFile(sub_object.1.into()).into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if !is_reserved(r.as_os_str()) {
+            return Err(ObjectStoreError::IsSymlink(sub_object.1.into()).into());
+        }
         Identifier::from_flipbase64(Flipbase64(
             r.as_os_str().as_bytes()[crate::RESERVED_PREFIX.len() + 1..].try_into()?,
         ))
     }
 
+    /// Open a file relative to the objects directory with raw open flags.
+    pub(crate) fn openat_file(
+        &self,
+        path: &Path,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+    ) -> io::Result<File> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe {
+            libc::openat(
+                self.objects.as_raw_fd(),
+                path.as_ptr(),
+                flags,
+                mode as libc::c_uint,
+            )
+        };
+        if fd == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(unsafe { File::from_raw_fd(fd) })
+        }
+    }
+
+    /// Opens existing metadata of an object.
     pub(crate) fn open_metadata(
         &self,
-        _identifier: &Identifier,
-        _metadata: Meta,
-        _access: FileAccess,
+        identifier: &Identifier,
+        metadata: Meta,
+        access: FileAccess,
     ) -> Result<Handle> {
-        unimplemented!()
+        let mut path = PathBuf::new();
+        path.push_metadata(identifier, metadata);
+        trace!("open_metadata: {:?}", path.as_os_str());
+        Ok(Handle::File(self.openat_file(&path, access.get(), 0)?))
     }
 
+    /// Creates new metadata for an object, fails when it already exists.
     pub(crate) fn create_metadata(
         &self,
-        _identifier: &Identifier,
-        _metadata: Meta,
-        _perm: FilePermissions, // readwrite or readonly for immutable metadata
+        identifier: &Identifier,
+        metadata: Meta,
+        perm: FilePermissions, // readwrite or readonly for immutable metadata
     ) -> Result<Handle> {
         // access: FileAccess, -> always readwrite
-        unimplemented!()
+        let mut path = PathBuf::new();
+        path.push_metadata(identifier, metadata);
+        trace!("create_metadata: {:?}", path.as_os_str());
+        Ok(Handle::File(
+            self.openat_file(
+                &path,
+                FileAccess::new()
+                    .readwrite()
+                    .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                    .get(),
+                perm.get(),
+            )?,
+        ))
+    }
+
+    /// Reads the complete metadata of an object, returns 'None' when the object has no such
+    /// metadata.
+    pub fn read_metadata(
+        &self,
+        identifier: &Identifier,
+        metadata: Meta,
+    ) -> Result<Option<Vec<u8>>> {
+        use std::io::Read;
+
+        match self.open_metadata(identifier, metadata, FileAccess::new().readonly()) {
+            Ok(Handle::File(mut file)) => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(Some(data))
+            }
+            Ok(_) => unreachable!(),
+            Err(err)
+                if err.downcast_ref::<io::Error>().map(io::Error::kind)
+                    == Some(io::ErrorKind::NotFound) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes (replaces) the metadata of an object.
+    pub(crate) fn write_metadata(
+        &self,
+        identifier: &Identifier,
+        metadata: Meta,
+        data: &[u8],
+    ) -> Result<()> {
+        let mut path = PathBuf::new();
+        path.push_metadata(identifier, metadata);
+        trace!("write_metadata: {:?}", path.as_os_str());
+        self.write_atomic(&path, data)
+    }
+
+    /// Replaces the file at 'path' with 'data'. The data is written to a tempfile first and
+    /// then renamed over the old file.
+    pub(crate) fn write_atomic(&self, path: &Path, data: &[u8]) -> Result<()> {
+        use std::io::Write;
+
+        let tmp = PathBuf::from("tmp").join(path.file_name().unwrap());
+        let mut file = self.openat_file(
+            &tmp,
+            FileAccess::new()
+                .writeonly()
+                .extra_flags(libc::O_CREAT | libc::O_TRUNC)
+                .get(),
+            FilePermissions::new().full().get(),
+        )?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+
+        Ok(self.objects.local_rename(&tmp, path)?)
+    }
+
+    /// Stores state of a frontend, like its inode database, as 'objects/NAME'.
+    pub fn write_state(&self, name: &str, data: &[u8]) -> Result<()> {
+        trace!("write_state: {:?}", name);
+        self.write_atomic(Path::new(name), data)
+    }
+
+    /// Reads state stored by 'write_state()', 'None' when there is none.
+    pub fn read_state(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        use std::io::Read;
+
+        trace!("read_state: {:?}", name);
+        match self.objects.open_file(name) {
+            Ok(mut file) => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(Some(data))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Removes metadata from an object, not existing metadata is not an error.
+    pub(crate) fn remove_metadata(&self, identifier: &Identifier, metadata: Meta) -> Result<()> {
+        let mut path = PathBuf::new();
+        path.push_metadata(identifier, metadata);
+        trace!("remove_metadata: {:?}", path.as_os_str());
+        match self.objects.remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the kinds of metadata that are present for an object.
+    pub fn present_metadata(&self, identifier: &Identifier) -> Vec<Meta> {
+        Meta::ALL
+            .iter()
+            .copied()
+            .filter(|meta| {
+                let mut path = PathBuf::new();
+                path.push_metadata(identifier, *meta);
+                self.objects.metadata(&path).is_ok()
+            })
+            .collect()
     }
 
     pub(crate) fn open_link(
@@ -275,12 +562,15 @@ impl ObjectStore {
         unimplemented!()
     }
 
-    pub(crate) fn open_file(
-        &self,
-        _identifier: &Identifier,
-        _access: FileAccess,
-    ) -> Result<Handle> {
-        unimplemented!()
+    /// Opens the content of a file object.
+    pub(crate) fn open_file(&self, identifier: &Identifier, access: FileAccess) -> Result<Handle> {
+        identifier.ensure_file()?;
+        trace!("open_file: {}", identifier);
+        Ok(Handle::File(self.openat_file(
+            &identifier.to_pathbuf(),
+            access.get(),
+            0,
+        )?))
     }
 
     pub(crate) fn create_file(
@@ -300,23 +590,61 @@ impl ObjectStore {
     /// given identifier
     pub(crate) fn create_link(&self, identifier: &Identifier, parent: SubObject) -> Result<()> {
         parent.0.ensure_dir()?;
+        let _gate = self.barrier.enter();
+        self.barrier.record(identifier);
+
+        self.check_declared_parent(identifier, parent.0)?;
 
         let source = parent.to_pathbuf();
         let mut dest = PathBuf::new();
         dest.push_link(identifier);
 
         let file_name = source.file_name().unwrap();
-        if file_name.as_bytes().len() >= crate::RESERVED_PREFIX.len()
-            && file_name.as_bytes()[..crate::RESERVED_PREFIX.len()] == crate::RESERVED_PREFIX
-        {
+        if is_reserved(file_name) {
             warn!("link: illegal file name: {:?}", &file_name);
             Err(ObjectStoreError::IllegalFileName(file_name.into()).into())
         } else {
+            self.rules_check_create(&parent, identifier.components())?;
             trace!("link: {:?} -> {:?}", source.as_os_str(), dest.as_os_str());
 
-            self.objects
-                .symlink(source.as_os_str(), dest.as_os_str())
-                .map_err(|e| e.into())
+            self.objects.symlink(source.as_os_str(), dest.as_os_str())?;
+            drop(_gate);
+            self.snapshot_on_change(parent.0)
+        }
+    }
+
+    /// Fails with 'ParentMismatch' when 'identifier' is a directory with parent which belongs
+    /// to another directory than 'parent'.
+    pub(crate) fn check_declared_parent(
+        &self,
+        identifier: &Identifier,
+        parent: &Identifier,
+    ) -> Result<()> {
+        if let Some(declared) = self.declared_parent(identifier)? {
+            if declared != *parent {
+                warn!("link: {} belongs to {}", identifier, declared);
+                return Err(ObjectStoreError::ParentMismatch {
+                    object: identifier.as_os_str().into(),
+                    parent: declared.as_os_str().into(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the declared parent of a directory with parent, 'None' for all other objects.
+    pub fn declared_parent(&self, identifier: &Identifier) -> Result<Option<Identifier>> {
+        if identifier.object_type() != ObjectType::DirectoryWithParent {
+            return Ok(None);
+        }
+        match self.object_meta(identifier)?.get("parent") {
+            Some(parent) => Ok(Some(Identifier::from_filename(Path::new(parent))?)),
+            None => Err(ObjectStoreError::ObjectStoreFatal(format!(
+                "directory with parent has no parent: {}",
+                identifier
+            ))
+            .into()),
         }
     }
 
@@ -330,12 +658,12 @@ impl ObjectStore {
         self.objects.list_dir(identifier.to_pathbuf().as_path())
     }
 
-    /// Returns an iterator listing name/identifier pairs for a directory
-    // TODO: make return type a struct DirEntry(CString, Identifier)
+    /// Returns an iterator listing the entries linking to objects in a directory, user
+    /// symlinks are skipped.
     pub fn list_directory(
         &self,
         identifier: &Identifier,
-    ) -> io::Result<impl Iterator<Item = (PathBuf, Identifier)>> {
+    ) -> io::Result<impl Iterator<Item = DirEntry>> {
         let dir = self.open_directory(identifier)?;
 
         Ok(dir.list_self()?.filter_map(move |item| match item {
@@ -344,9 +672,13 @@ impl ObjectStore {
                 file_type: Some(SimpleType::Symlink),
                 ..
             }) => {
-                let name = PathBuf::from(OsStr::from_bytes(name.to_bytes()));
-                let identifier = Identifier::from_filename(&dir.read_link(&name).ok()?).ok()?;
-                Some((name, identifier))
+                let target = dir.read_link(OsStr::from_bytes(name.to_bytes())).ok()?;
+                // user symlinks are not objects
+                if !is_reserved(target.as_os_str()) {
+                    return None;
+                }
+                let identifier = Identifier::from_filename(&target).ok()?;
+                Some(DirEntry(name, identifier))
             }
             _ => None,
         }))
@@ -379,16 +711,12 @@ impl ObjectStore {
 
     // pub fn cleanup_deleted // delete expired objects
 
-    /// Registers the objectstores root directory to 'identifier'.
+    /// Registers the objectstores root directory to 'identifier', replaces the old root
+    /// atomically. Use 'replace_root()' to keep the old root.
     pub(crate) fn set_root(&self, identifier: &Identifier) -> Result<()> {
-        identifier.ensure_dir()?;
-        let mut path = PathBuf::new();
-        path.push_identifier(identifier);
-        info!("set_root: {:?}", path.as_os_str());
-        self.objects.remove_file("root").ok();
-        self.objects
-            .symlink("root", path.as_os_str())
-            .map_err(|e| e.into())
+        identifier.ensure_root()?;
+        info!("set_root: {}", identifier);
+        self.symlink_atomic(Path::new("root"), identifier)
     }
 
     /// Returns an iterator over all objects in the store
@@ -418,6 +746,36 @@ impl ObjectStore {
     }
 }
 
+impl Drop for ObjectStore {
+    fn drop(&mut self) {
+        if let Some(lock_info) = &self.lock_info {
+            let _ = self.objects.remove_file(lock_info);
+        }
+    }
+}
+
+/// Returns true when 'name' starts with the reserved prefix, identifier links in directories
+/// always do, user file names and symlink targets must not.
+pub(crate) fn is_reserved(name: &OsStr) -> bool {
+    name.as_bytes().starts_with(&crate::RESERVED_PREFIX)
+}
+
+/// name/identifier pair of an entry listed from a directory
+#[derive(Debug, Clone)]
+pub struct DirEntry(pub CString, pub Identifier);
+
+impl DirEntry {
+    #[inline]
+    pub fn name(&self) -> &OsStr {
+        OsStr::from_bytes(self.0.to_bytes())
+    }
+
+    #[inline]
+    pub fn identifier(&self) -> &Identifier {
+        &self.1
+    }
+}
+
 /// identifier/name pair for a subobject in a directory
 #[derive(Debug)]
 pub struct SubObject<'a>(pub &'a Identifier, pub &'a OsStr);
@@ -465,18 +823,19 @@ impl FileAccess {
     }
 
     pub fn append(mut self) -> Self {
-        assert_eq!(self.0, 0);
+        assert_eq!(self.0 & libc::O_APPEND, 0);
         self.0 |= libc::O_APPEND;
         self
     }
 
+    /// Adds open flags, these must not change the access mode.
     pub fn extra_flags(mut self, flags: libc::c_int) -> Self {
-        assert_eq!(self.0, 0);
+        assert_eq!(flags & libc::O_ACCMODE, 0);
         self.0 |= flags;
         self
     }
 
-    fn get(self) -> libc::c_int {
+    pub(crate) fn get(self) -> libc::c_int {
         self.0 | libc::O_CLOEXEC
     }
 
@@ -510,7 +869,7 @@ impl FilePermissions {
         self
     }
 
-    fn get(self) -> libc::mode_t {
+    pub(crate) fn get(self) -> libc::mode_t {
         self.0
     }
 }
@@ -12,8 +12,28 @@ pub fn optargs() -> App<'static, 'static> {
         .subcommand(init_optargs())
         .subcommand(lock_optargs())
         .subcommand(show_optargs())
+        .subcommand(ls_optargs())
+        .subcommand(root_optargs())
+        .subcommand(ln_optargs())
+        .subcommand(unlink_optargs())
+        .subcommand(mv_optargs())
+        .subcommand(chtype_optargs())
         .subcommand(mkdir_optargs())
         .subcommand(gc_optargs())
+        .subcommand(gc_race_optargs())
+        .subcommand(pin_optargs())
+        .subcommand(rules_optargs())
+        .subcommand(key_optargs())
+        .subcommand(revoke_optargs())
+        .subcommand(revocations_optargs())
+        .subcommand(perm_optargs())
+        .subcommand(chacl_optargs())
+        .subcommand(add_anonymous_optargs())
+        .subcommand(versions_optargs())
+        .subcommand(symlink_optargs())
+        .subcommand(mknod_optargs())
+        .subcommand(xattr_optargs())
+        .subcommand(SubCommand::with_name("status").about("Show version, root and object counts"))
         .subcommand(send_optargs())
         .subcommand(receive_optargs())
         .subcommand(getid_optargs())
@@ -54,6 +74,18 @@ fn lock_optargs() -> App<'static, 'static> {
                 .long("wait")
                 .help("Wait for the lock"),
         )
+        .arg(
+            Arg::with_name("shared")
+                .short("s")
+                .long("shared")
+                .help("Take a shared lock as read-only commands do"),
+        )
+        .arg(
+            Arg::with_name("status")
+                .long("status")
+                .conflicts_with_all(&["wait", "shared"])
+                .help("Show who holds the lock and exit"),
+        )
 }
 
 fn gc_optargs() -> App<'static, 'static> {
@@ -65,6 +97,81 @@ fn gc_optargs() -> App<'static, 'static> {
                 .short("n")
                 .help("Don't remove any data, only show what would been done"),
         )
+        .arg(
+            Arg::with_name("ROOT")
+                .long("root")
+                .short("r")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .help("Additional root to keep alive, identifier or PATH"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .short("j")
+                .takes_value(true)
+                .help("Number of threads, defaults to the number of CPUs"),
+        )
+        .arg(
+            Arg::with_name("memory-limit")
+                .long("memory-limit")
+                .takes_value(true)
+                .help("Live objects held in memory before spilling them to disk"),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .help("Periodically print progress to stderr"),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .help("Run in the background when daemonizing is allowed"),
+        )
+}
+
+// Hidden option only for testing/diagnostics
+fn gc_race_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("gc-race")
+        .setting(AppSettings::Hidden)
+        .about("Races directory creation against concurrent garbage collection")
+        .arg(
+            Arg::with_name("writers")
+                .long("writers")
+                .takes_value(true)
+                .help("Number of threads creating directories"),
+        )
+        .arg(
+            Arg::with_name("rounds")
+                .long("rounds")
+                .takes_value(true)
+                .help("Directories created by each writer"),
+        )
+}
+
+fn pin_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("pin")
+        .about("Keep objects alive in garbage collection, lists the pins without OBJECT")
+        .arg(
+            Arg::with_name("SET")
+                .long("set")
+                .short("s")
+                .takes_value(true)
+                .help("Name of the pin set, defaults to 'default'"),
+        )
+        .arg(
+            Arg::with_name("remove")
+                .long("remove")
+                .short("r")
+                .requires("OBJECT")
+                .help("Unpin instead pinning"),
+        )
+        .arg(
+            Arg::with_name("OBJECT")
+                .multiple(true)
+                .help("Identifier or PATH of the objects to pin"),
+        )
 }
 
 fn mkdir_optargs() -> App<'static, 'static> {
@@ -94,6 +201,18 @@ fn mkdir_optargs() -> App<'static, 'static> {
                 .takes_value(true) // optional? from parent? 'default'
                 .help("Create a public shared directory"),
         )
+        .arg(
+            Arg::with_name("versioned")
+                .long("versioned")
+                .conflicts_with("acl")
+                .help("Create a versioned directory"),
+        )
+        .arg(
+            Arg::with_name("with-parent")
+                .long("with-parent")
+                .conflicts_with_all(&["acl", "versioned"])
+                .help("Create a directory bound to its parent, allows relative symlinks to '..'"),
+        )
         .arg(
             Arg::with_name("SOURCE")
                 .long("link")
@@ -116,11 +235,504 @@ fn show_optargs() -> App<'static, 'static> {
                 .takes_value(true)
                 .help("Path to investigate"),
         )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print JSON for tooling"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Show the whole tree below PATH"),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .conflicts_with_all(&["json", "recursive"])
+                .help("Show the URL other nodes fetch the object from"),
+        )
+}
+
+fn ls_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("ls")
+        .about("Lists the objects in a directory")
+        .arg(
+            Arg::with_name("PATH")
+                .takes_value(true)
+                .help("Directory to list, defaults to the root"),
+        )
+        .arg(
+            Arg::with_name("long")
+                .short("l")
+                .long("long")
+                .help("Show abbreviated identifier, kind and size"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("R")
+                .long("recursive")
+                .help("List subdirectories too"),
+        )
+        .arg(
+            Arg::with_name("type")
+                .long("type")
+                .takes_value(true)
+                .possible_values(&["File", "Directory", "DirectoryWithParent", "RevocationList"])
+                .case_insensitive(true)
+                .help("Only list objects of this ObjectType"),
+        )
+        .arg(
+            Arg::with_name("sharing")
+                .long("sharing")
+                .takes_value(true)
+                .possible_values(&["Private", "PublicAcl", "Anonymous"])
+                .case_insensitive(true)
+                .help("Only list objects with this SharingPolicy"),
+        )
+        .arg(
+            Arg::with_name("mutability")
+                .long("mutability")
+                .takes_value(true)
+                .possible_values(&["Mutable", "Immutable", "Versioned"])
+                .case_insensitive(true)
+                .help("Only list objects with this Mutability"),
+        )
+}
+
+fn root_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("root")
+        .about("Show or set the root directory and named roots")
+        .arg(
+            Arg::with_name("show")
+                .long("show")
+                .help("Show the root and all named roots (default)"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .value_name("OBJECT")
+                .conflicts_with_all(&["show", "remove"])
+                .help("Identifier or PATH of the directory to become the root"),
+        )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .short("n")
+                .takes_value(true)
+                .requires("set")
+                .help("Set the named root NAME instead of the root"),
+        )
+        .arg(
+            Arg::with_name("keep")
+                .long("keep")
+                .takes_value(true)
+                .value_name("NAME")
+                .requires("set")
+                .conflicts_with("name")
+                .help("Name the old root is kept under, defaults to 'previous'"),
+        )
+        .arg(
+            Arg::with_name("remove")
+                .long("remove")
+                .takes_value(true)
+                .value_name("NAME")
+                .conflicts_with("show")
+                .help("Remove the named root NAME, it becomes garbage unless linked elsewhere"),
+        )
+}
+
+fn ln_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("ln")
+        .about("Link an existing object into a directory")
+        .arg(
+            Arg::with_name("OBJECT")
+                .required(true)
+                .help("Identifier or path of the object to link"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The new directory entry"),
+        )
+}
+
+fn unlink_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("unlink")
+        .about("Remove a directory entry, the object is left for gc")
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The directory entry to remove"),
+        )
+}
+
+fn mv_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("mv")
+        .about("Rename or move a directory entry")
+        .arg(
+            Arg::with_name("FROM")
+                .required(true)
+                .help("The directory entry to move"),
+        )
+        .arg(
+            Arg::with_name("TO")
+                .required(true)
+                .help("The new directory entry, must not exist"),
+        )
+}
+
+fn chtype_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("chtype")
+        .about("Convert an object to another sharing policy and mutability")
+        .arg(
+            Arg::with_name("TYPE")
+                .required(true)
+                .possible_values(&crate::link::CHTYPE_KINDS)
+                .help("The new kind of the object"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The directory entry of the object, keeps its name"),
+        )
+}
+
+fn rules_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("rules")
+        .about("Show and test the rules of a directory")
+        .arg(
+            Arg::with_name("RULEFILE")
+                .long("set")
+                .takes_value(true)
+                .help("Attach the rules from RULEFILE to the directory"),
+        )
+        .arg(
+            Arg::with_name("clear")
+                .long("clear")
+                .conflicts_with("RULEFILE")
+                .help("Remove all rules from the directory"),
+        )
+        .arg(
+            Arg::with_name("NAME")
+                .short("t")
+                .long("test")
+                .takes_value(true)
+                .help("Test if NAME could be created in the directory"),
+        )
+        .arg(
+            Arg::with_name("FROM")
+                .long("from")
+                .takes_value(true)
+                .requires("NAME")
+                .help("Test renaming FROM to NAME instead creating it"),
+        )
+        .arg(
+            Arg::with_name("directory")
+                .long("directory")
+                .requires("NAME")
+                .help("Test for a directory instead a file"),
+        )
+        .arg(
+            Arg::with_name("anonymous")
+                .long("anonymous")
+                .requires("NAME")
+                .help("Test for an anonymous immutable object"),
+        )
+        .arg(
+            Arg::with_name("SIZE")
+                .long("size")
+                .takes_value(true)
+                .requires("NAME")
+                .help("Test for a file of SIZE bytes"),
+        )
+        .arg(Arg::with_name("PATH").required(true).help("The directory"))
+}
+
+fn key_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("key")
+        .about("Generate keys and show the key of a key file")
+        .arg(
+            Arg::with_name("generate")
+                .long("generate")
+                .takes_value(true)
+                .value_name("KEYFILE")
+                .help("Generate a new key, store its secret in KEYFILE"),
+        )
+        .arg(
+            Arg::with_name("KEYFILE")
+                .required_unless("generate")
+                .conflicts_with("generate")
+                .help("Key file to show the key of"),
+        )
+}
+
+fn revoke_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("revoke")
+        .about("Revoke a key")
+        .arg(
+            Arg::with_name("SIGNER")
+                .long("signer")
+                .takes_value(true)
+                .conflicts_with("SIGNWITH")
+                .help("Key which signed the revocation, defaults to KEY itself"),
+        )
+        .arg(
+            Arg::with_name("SIGNATURE")
+                .long("signature")
+                .takes_value(true)
+                .required_unless("SIGNWITH")
+                .conflicts_with("SIGNWITH")
+                .help("Signature over the revocation"),
+        )
+        .arg(
+            Arg::with_name("SIGNWITH")
+                .long("sign-with")
+                .takes_value(true)
+                .value_name("KEYFILE")
+                .help("Sign with the secret key from KEYFILE"),
+        )
+        .arg(
+            Arg::with_name("DAYS")
+                .long("expire")
+                .takes_value(true)
+                .default_value("90")
+                .help("Days until the revocation expires"),
+        )
+        .arg(
+            Arg::with_name("UNTIL")
+                .long("until")
+                .takes_value(true)
+                .value_name("EPOCH")
+                .required_unless_one(&["SIGNWITH", "admin"])
+                .conflicts_with("admin")
+                .help("Seconds since epoch when the revocation expires, as signed"),
+        )
+        .arg(
+            Arg::with_name("admin")
+                .long("admin")
+                .help("Authorize KEY to revoke other keys instead revoking it"),
+        )
+        .arg(
+            Arg::with_name("KEY")
+                .required(true)
+                .help("The key to revoke"),
+        )
+}
+
+fn revocations_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("revocations").about("Show the revoked keys")
+}
+
+fn perm_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("perm")
+        .about("Show and change the perm manifest of an object")
+        .arg(
+            Arg::with_name("MANIFESTFILE")
+                .long("propose")
+                .takes_value(true)
+                .help("Propose the manifest from MANIFESTFILE as pending change"),
+        )
+        .arg(
+            Arg::with_name("KEY")
+                .long("sign")
+                .takes_value(true)
+                .requires("SIGNATURE")
+                .conflicts_with("MANIFESTFILE")
+                .help("Sign the pending change as admin KEY"),
+        )
+        .arg(
+            Arg::with_name("SIGNATURE")
+                .long("signature")
+                .takes_value(true)
+                .requires("KEY")
+                .help("Signature over the pending change, or over its abort with --abort"),
+        )
+        .arg(
+            Arg::with_name("SIGNWITH")
+                .long("sign-with")
+                .takes_value(true)
+                .value_name("KEYFILE")
+                .conflicts_with_all(&["MANIFESTFILE", "KEY"])
+                .help("Sign the pending change, or its abort, with the secret key from KEYFILE"),
+        )
+        .arg(
+            Arg::with_name("finalize")
+                .long("finalize")
+                .conflicts_with_all(&["MANIFESTFILE", "KEY", "SIGNWITH"])
+                .help("Make the pending change effective when the quorum is reached"),
+        )
+        .arg(
+            Arg::with_name("abort")
+                .long("abort")
+                .conflicts_with_all(&["MANIFESTFILE", "finalize"])
+                .help("Discard the pending change, must be signed by an admin"),
+        )
+        .arg(
+            Arg::with_name("RECEIVE")
+                .long("receive")
+                .takes_value(true)
+                .conflicts_with_all(&["MANIFESTFILE", "KEY", "SIGNWITH", "finalize", "abort"])
+                .help("Take over the finalized manifest from RECEIVE, as exported by another node"),
+        )
+        .arg(
+            Arg::with_name("EXPORT")
+                .long("export")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "MANIFESTFILE",
+                    "KEY",
+                    "SIGNWITH",
+                    "finalize",
+                    "abort",
+                    "RECEIVE",
+                ])
+                .help("Write the effective manifest with its signatures to EXPORT"),
+        )
+        .arg(Arg::with_name("PATH").required(true).help("The object"))
+}
+
+fn chacl_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("chacl")
+        .about("Grant or withdraw a permission by a signed change of the perm manifest")
+        .arg(
+            Arg::with_name("CHANGE")
+                .required(true)
+                .allow_hyphen_values(true)
+                .help("+PERMISSION or -PERMISSION, PERMISSION may be 'admin'"),
+        )
+        .arg(
+            Arg::with_name("KEY")
+                .required(true)
+                .allow_hyphen_values(true)
+                .help("The key to change"),
+        )
+        .arg(
+            Arg::with_name("SIGNWITH")
+                .long("sign-with")
+                .takes_value(true)
+                .value_name("KEYFILE")
+                .required(true)
+                .help("Sign the change with the secret key of an admin from KEYFILE"),
+        )
+        .arg(Arg::with_name("PATH").required(true).help("The object"))
+}
+
+fn add_anonymous_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("add-anonymous")
+        .about("Add a file as anonymous immutable object")
+        .arg(
+            Arg::with_name("SOURCE")
+                .required(true)
+                .help("The file to add"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("Name of the new object"),
+        )
+}
+
+fn versions_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("versions")
+        .about("List and manage the snapshots of a versioned object")
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .help("Take a snapshot first"),
+        )
+        .arg(
+            Arg::with_name("COUNT")
+                .long("retain-count")
+                .takes_value(true)
+                .help("Keep only the COUNT newest snapshots"),
+        )
+        .arg(
+            Arg::with_name("DAYS")
+                .long("retain-days")
+                .takes_value(true)
+                .help("Keep only snapshots younger than DAYS"),
+        )
+        .arg(
+            Arg::with_name("NUMBER")
+                .long("show")
+                .takes_value(true)
+                .help("Show the content of snapshot NUMBER"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The versioned object"),
+        )
+}
+
+fn symlink_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("symlink")
+        .about("Create a symlink")
+        .arg(
+            Arg::with_name("TARGET")
+                .required(true)
+                .help("The target of the symlink"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The symlink to create"),
+        )
+}
+
+fn mknod_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("mknod")
+        .about("Create a special file in a private directory")
+        .arg(
+            Arg::with_name("PATH")
+                .required(true)
+                .help("The special file to create"),
+        )
+        .arg(
+            Arg::with_name("TYPE")
+                .required(true)
+                .possible_values(&["p", "s", "c", "b"])
+                .help("fifo, socket, char or block device"),
+        )
+        .arg(Arg::with_name("MAJOR").help("Major device number"))
+        .arg(Arg::with_name("MINOR").help("Minor device number"))
+}
+
+fn xattr_optargs() -> App<'static, 'static> {
+    SubCommand::with_name("xattr")
+        .about("Show or change extended attributes")
+        .arg(
+            Arg::with_name("GET")
+                .long("get")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Show a single attribute"),
+        )
+        .arg(
+            Arg::with_name("SET")
+                .long("set")
+                .takes_value(true)
+                .number_of_values(2)
+                .value_names(&["NAME", "VALUE"])
+                .conflicts_with("GET")
+                .help("Set a 'user.' attribute"),
+        )
+        .arg(
+            Arg::with_name("REMOVE")
+                .long("remove")
+                .takes_value(true)
+                .value_name("NAME")
+                .conflicts_with_all(&["GET", "SET"])
+                .help("Remove a 'user.' attribute"),
+        )
+        .arg(Arg::with_name("PATH").required(true).help("The object"))
 }
 
 fn send_optargs() -> App<'static, 'static> {
     SubCommand::with_name("send")
-        .about("Exports an object")
+        .about("Exports an object, lists the objects to be exported for now")
         .arg(
             Arg::with_name("ID_OR_PATH")
                 .required(true)
@@ -176,9 +788,23 @@ fn getid_optargs() -> App<'static, 'static> {
         .about("Get the identifier on a object")
         .arg(
             Arg::with_name("PATH")
-                .required(true)
+                .required_unless("paths")
                 .help("Path to a file in the objectstore"),
         )
+        .arg(
+            Arg::with_name("abbrev")
+                .short("a")
+                .long("abbrev")
+                .help("Print the shortest unambiguous abbreviation"),
+        )
+        .arg(
+            Arg::with_name("paths")
+                .short("p")
+                .long("paths")
+                .value_name("OBJECT")
+                .conflicts_with_all(&["PATH", "abbrev"])
+                .help("List all paths from the root where OBJECT is linked"),
+        )
 }
 
 fn check_optargs() -> App<'static, 'static> {
@@ -0,0 +1,661 @@
+//! Perm manifests and quorum approved changes.
+//!
+//! The 'perm' metadata of an object lists its admins, the quorum and the access control
+//! lists. Any change to it has to be signed by at least 'quorum' of its admins, the first
+//! manifest of an object by the quorum of its own admins. Changes are proposed as a pending
+//! change ('pend' metadata), collect the signatures of the admins there and become effective
+//! only when finalized. Thus no single compromised admin key can take over an object.
+//! Discarding a pending change has to be signed by an admin as well. Manifests are stored
+//! one entry per line:
+//!
+//!  * admin KEY:: KEY may sign changes of this manifest
+//!  * quorum M:: at least M of the admins must sign a change (defaults to 1)
+//!  * acl PERMISSION KEY..:: the KEYs are granted PERMISSION
+//!  * stamp STAMP:: stamp of the manifest, assigned when a change gets proposed
+//!
+//! Pending changes hold the proposed manifest and additionally:
+//!
+//!  * base STAMP:: stamp of the manifest the change is based on
+//!  * signature KEY SIGNATURE:: partial signature of an admin
+//!
+//! Admins sign 'uberallfs perm IDENTIFIER BASE' followed by a newline and the proposed
+//! manifest, BASE is '-' for the first manifest. Aborts are signed over
+//! 'uberallfs perm-abort IDENTIFIER STAMP' with the stamp of the pending manifest.
+//!
+//! 'chacl' is the shortcut for the common case: it grants or withdraws a single permission
+//! (or admin rights) of one key based on the current manifest, proposes that and signs it.
+//! When the signer's signature reaches the quorum the change is finalized right away.
+//!
+//! A finalized change is kept with its base and signatures as the effective manifest. Other
+//! nodes receive it in this form, its stamp must supersede the last one seen for the object,
+//! thus replayed old manifests are rejected.
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::Path;
+
+use ed25519_dalek::Keypair;
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::objectstore::Meta;
+use crate::{
+    Identifier, KeyId, LockingMethod::*, MetadataStamp, ObjectStore, key_id, load_key, sign,
+    verify_signature,
+};
+
+/// The permissions an 'acl' entry can grant
+pub const PERMISSIONS: [&str; 7] = ["read", "write", "append", "list", "add", "rename", "delete"];
+
+pub(crate) fn opt_perm(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+
+    let keypair = matches
+        .value_of_os("SIGNWITH")
+        .map(|keyfile| load_key(Path::new(keyfile)))
+        .transpose()?;
+    let signer: Option<KeyId> = match &keypair {
+        Some(keypair) => Some(key_id(keypair)),
+        None => matches.value_of("KEY").map(KeyId::from),
+    };
+    let signature = |message: String| match &keypair {
+        Some(keypair) => Some(sign(keypair, message.as_bytes())),
+        None => matches.value_of("SIGNATURE").map(String::from),
+    };
+    let pending = || -> Result<PendingChange> {
+        Ok(objectstore
+            .pending_perm_change(&identifier)?
+            .ok_or_else(|| ObjectStoreError::NoPendingChange(identifier.as_os_str().into()))?)
+    };
+
+    if let Some(manifestfile) = matches.value_of_os("MANIFESTFILE") {
+        let manifest = PermManifest::parse(&std::fs::read_to_string(manifestfile)?)?;
+        objectstore.propose_perm_change(&identifier, manifest)?;
+    } else if matches.is_present("finalize") {
+        objectstore.finalize_perm_change(&identifier)?;
+    } else if matches.is_present("abort") {
+        match (&signer, signature(pending()?.abort_message(&identifier))) {
+            (Some(key), Some(signature)) => {
+                objectstore.abort_perm_change(&identifier, key, &signature)?
+            }
+            _ => {
+                return Err(ObjectStoreError::OptArgError(String::from(
+                    "--abort must be signed by an admin",
+                ))
+                .into());
+            }
+        }
+    } else if let Some(key) = &signer {
+        let signature = signature(pending()?.message(&identifier)).unwrap();
+        let (have, want) = objectstore.sign_perm_change(&identifier, key, &signature)?;
+        println!("signed: {} of {}", have, want);
+    } else if let Some(received) = matches.value_of_os("RECEIVE") {
+        let change = PendingChange::parse(&std::fs::read_to_string(received)?)?;
+        objectstore.receive_perm_manifest(&identifier, change)?;
+    } else if let Some(export) = matches.value_of_os("EXPORT") {
+        let data = objectstore
+            .read_metadata(&identifier, Meta::Perm)?
+            .ok_or_else(|| ObjectStoreError::ObjectNotFound(path.into()))?;
+        std::fs::write(export, data)?;
+    }
+
+    if let Some(manifest) = objectstore.perm_manifest(&identifier)? {
+        print!("{}", manifest);
+    }
+    if let Some(pending) = objectstore.pending_perm_change(&identifier)? {
+        println!("pending:");
+        print!("{}", pending);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn opt_chacl(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+
+    let keypair = load_key(Path::new(matches.value_of_os("SIGNWITH").unwrap()))?;
+    let signer = key_id(&keypair);
+    let pending = objectstore.propose_acl_change(
+        &identifier,
+        matches.value_of("CHANGE").unwrap(),
+        matches.value_of("KEY").unwrap(),
+        &signer,
+    )?;
+    let signature = sign(&keypair, pending.message(&identifier).as_bytes());
+    let (have, want) = objectstore.approve_perm_change(&identifier, &signer, &signature)?;
+    println!("signed: {} of {}", have, want);
+    Ok(())
+}
+
+/// The access control manifest of an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermManifest {
+    admins: Vec<KeyId>,
+    quorum: usize,
+    acls:   BTreeMap<String, Vec<KeyId>>,
+    stamp:  Option<MetadataStamp>,
+}
+
+impl Default for PermManifest {
+    fn default() -> Self {
+        PermManifest {
+            admins: Vec::new(),
+            quorum: 1,
+            acls:   BTreeMap::new(),
+            stamp:  None,
+        }
+    }
+}
+
+impl PermManifest {
+    /// Parse a manifest from its textual representation.
+    pub fn parse(text: &str) -> Result<PermManifest> {
+        let mut manifest = PermManifest::default();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let syntax_error = |what: &str| {
+                ObjectStoreError::ManifestSyntax(format!("line {}: {}", lineno + 1, what))
+            };
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                ["admin", key] => {
+                    if !manifest.is_admin(key) {
+                        manifest.admins.push(key.into());
+                    }
+                }
+                ["quorum", quorum] => {
+                    manifest.quorum = quorum
+                        .parse()
+                        .map_err(|_| syntax_error("quorum must be a number"))?;
+                }
+                ["acl", permission, ref keys @ ..] => {
+                    if !PERMISSIONS.contains(&permission) {
+                        return Err(
+                            syntax_error(&format!("unknown permission '{}'", permission)).into(),
+                        );
+                    }
+                    manifest
+                        .acls
+                        .entry(permission.into())
+                        .or_default()
+                        .extend(keys.iter().map(|key| KeyId::from(*key)));
+                }
+                ["stamp", stamp] => manifest.stamp = Some(MetadataStamp::parse(stamp)?),
+                _ => return Err(syntax_error(&format!("unexpected '{}'", line)).into()),
+            }
+        }
+
+        if manifest.quorum == 0
+            || (!manifest.admins.is_empty() && manifest.quorum > manifest.admins.len())
+        {
+            return Err(ObjectStoreError::ManifestSyntax(format!(
+                "quorum {} not satisfiable by {} admins",
+                manifest.quorum,
+                manifest.admins.len()
+            ))
+            .into());
+        }
+
+        Ok(manifest)
+    }
+
+    /// Returns true when 'key' is one of the admins.
+    pub fn is_admin(&self, key: &str) -> bool {
+        self.admins.iter().any(|admin| admin == key)
+    }
+
+    /// Returns the admin keys.
+    pub fn admins(&self) -> &[KeyId] {
+        &self.admins
+    }
+
+    /// Returns the number of admin signatures required to change this manifest.
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    /// Returns the keys granted 'permission'.
+    pub fn acl(&self, permission: &str) -> &[KeyId] {
+        self.acls.get(permission).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns all keys the manifest refers to.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.admins
+            .iter()
+            .chain(self.acls.values().flatten())
+            .map(KeyId::as_str)
+    }
+
+    /// Returns the permissions granted to any key.
+    pub fn permissions(&self) -> impl Iterator<Item = &str> {
+        self.acls.keys().map(String::as_str)
+    }
+
+    pub fn stamp(&self) -> Option<MetadataStamp> {
+        self.stamp
+    }
+
+    /// The manifest of a freshly created object, 'key' is its only admin and has all
+    /// permissions.
+    pub fn creator(key: &str) -> PermManifest {
+        PermManifest {
+            admins: vec![key.into()],
+            acls: PERMISSIONS
+                .iter()
+                .map(|permission| (String::from(*permission), vec![KeyId::from(key)]))
+                .collect(),
+            ..PermManifest::default()
+        }
+    }
+
+    /// Applies a 'chacl' change to the manifest. 'change' is '+PERMISSION' to grant or
+    /// '-PERMISSION' to withdraw one of 'PERMISSIONS' or 'admin' from 'key'.
+    pub fn change_acl(&mut self, change: &str, key: &str) -> Result<()> {
+        let (grant, permission) = if let Some(permission) = change.strip_prefix('+') {
+            (true, permission)
+        } else if let Some(permission) = change.strip_prefix('-') {
+            (false, permission)
+        } else {
+            return Err(ObjectStoreError::OptArgError(format!(
+                "{:?}: expected +PERMISSION or -PERMISSION",
+                change
+            ))
+            .into());
+        };
+
+        let keys = if permission == "admin" {
+            &mut self.admins
+        } else if PERMISSIONS.contains(&permission) {
+            self.acls.entry(permission.into()).or_default()
+        } else {
+            return Err(ObjectStoreError::OptArgError(format!(
+                "unknown permission '{}'",
+                permission
+            ))
+            .into());
+        };
+        if grant {
+            if !keys.iter().any(|granted| granted == key) {
+                keys.push(key.into());
+            }
+        } else {
+            keys.retain(|granted| granted != key);
+        }
+        self.acls.retain(|_, keys| !keys.is_empty());
+
+        if self.quorum > self.admins.len() {
+            return Err(ObjectStoreError::ManifestSyntax(format!(
+                "quorum {} not satisfiable by {} admins",
+                self.quorum,
+                self.admins.len()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PermManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        for admin in &self.admins {
+            writeln!(f, "admin {}", admin)?;
+        }
+        writeln!(f, "quorum {}", self.quorum)?;
+        for (permission, keys) in &self.acls {
+            writeln!(f, "acl {} {}", permission, keys.join(" "))?;
+        }
+        if let Some(stamp) = self.stamp {
+            writeln!(f, "stamp {}", stamp)?;
+        }
+        Ok(())
+    }
+}
+
+/// A proposed change to a perm manifest which collects admin signatures
+#[derive(Debug)]
+pub struct PendingChange {
+    base:       Option<MetadataStamp>,
+    manifest:   PermManifest,
+    signatures: Vec<(KeyId, String)>,
+}
+
+impl PendingChange {
+    /// Parse a pending change from its textual representation.
+    pub fn parse(text: &str) -> Result<PendingChange> {
+        let mut base = None;
+        let mut signatures = Vec::new();
+        let mut manifest = String::new();
+
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["base", stamp] => base = Some(MetadataStamp::parse(stamp)?),
+                ["signature", key, signature] => signatures.push((key.into(), signature.into())),
+                _ => {
+                    manifest.push_str(line);
+                    manifest.push('\n');
+                }
+            }
+        }
+
+        Ok(PendingChange {
+            base,
+            manifest: PermManifest::parse(&manifest)?,
+            signatures,
+        })
+    }
+
+    /// The message admins sign to approve this change of the manifest of 'identifier'.
+    pub fn message(&self, identifier: &Identifier) -> String {
+        format!(
+            "uberallfs perm {} {}\n{}",
+            identifier,
+            self.base
+                .map_or_else(|| String::from("-"), |base| base.to_string()),
+            self.manifest
+        )
+    }
+
+    /// The message an admin signs to discard this change.
+    pub fn abort_message(&self, identifier: &Identifier) -> String {
+        format!(
+            "uberallfs perm-abort {} {}",
+            identifier,
+            self.manifest
+                .stamp
+                .map_or_else(|| String::from("-"), |stamp| stamp.to_string())
+        )
+    }
+
+    /// Adds or replaces the signature of 'key' after verifying it.
+    pub fn sign(&mut self, identifier: &Identifier, key: &str, signature: &str) -> Result<()> {
+        verify_signature(key, self.message(identifier).as_bytes(), signature)?;
+        self.signatures.retain(|(signer, _)| signer != key);
+        self.signatures.push((key.into(), signature.into()));
+        Ok(())
+    }
+
+    /// Returns the keys which signed this change.
+    pub fn signers(&self) -> impl Iterator<Item = &str> {
+        self.signatures.iter().map(|(key, _)| key.as_str())
+    }
+}
+
+impl fmt::Display for PendingChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        if let Some(base) = self.base {
+            writeln!(f, "base {}", base)?;
+        }
+        write!(f, "{}", self.manifest)?;
+        for (key, signature) in &self.signatures {
+            writeln!(f, "signature {} {}", key, signature)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore {
+    /// Returns the effective perm manifest of an object, 'None' when it has none.
+    pub fn perm_manifest(&self, identifier: &Identifier) -> Result<Option<PermManifest>> {
+        match self.read_metadata(identifier, Meta::Perm)? {
+            Some(data) => Ok(Some(
+                PendingChange::parse(std::str::from_utf8(&data)?)?.manifest,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the pending change of the perm manifest of an object, 'None' when there is none.
+    pub fn pending_perm_change(&self, identifier: &Identifier) -> Result<Option<PendingChange>> {
+        match self.read_metadata(identifier, Meta::Pend)? {
+            Some(data) => Ok(Some(PendingChange::parse(std::str::from_utf8(&data)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Proposes a new perm manifest, replaces any pending change. The manifest gets the next
+    /// stamp of the object, the admins sign it along with the manifest. Returns the pending
+    /// change for signing.
+    pub fn propose_perm_change(
+        &self,
+        identifier: &Identifier,
+        mut manifest: PermManifest,
+    ) -> Result<PendingChange> {
+        self.check_perm_manifest(&manifest)?;
+        manifest.stamp = Some(self.next_stamp(identifier)?);
+        let pending = PendingChange {
+            base: self
+                .perm_manifest(identifier)?
+                .and_then(|current| current.stamp),
+            manifest,
+            signatures: Vec::new(),
+        };
+        info!("propose perm change on {}", identifier);
+        self.write_metadata(identifier, Meta::Pend, pending.to_string().as_bytes())?;
+        Ok(pending)
+    }
+
+    /// Makes the owner of 'keypair' the creator of an object without perm manifest, see
+    /// 'PermManifest::creator()'.
+    pub fn init_creator_perm(&self, identifier: &Identifier, keypair: &Keypair) -> Result<()> {
+        if self.perm_manifest(identifier)?.is_some() {
+            return Err(ObjectStoreError::ObjectExists(identifier.as_os_str().into()).into());
+        }
+        let key = key_id(keypair);
+        let pending = self.propose_perm_change(identifier, PermManifest::creator(&key))?;
+        let signature = sign(keypair, pending.message(identifier).as_bytes());
+        self.approve_perm_change(identifier, &key, &signature)?;
+        info!("{} created {}", key, identifier);
+        Ok(())
+    }
+
+    /// Proposes the current manifest changed by 'chacl' semantics, see
+    /// 'PermManifest::change_acl()'. 'signer' must be an admin, an object without manifest
+    /// gets it as its admin. Returns the pending change for signing.
+    pub fn propose_acl_change(
+        &self,
+        identifier: &Identifier,
+        change: &str,
+        key: &str,
+        signer: &str,
+    ) -> Result<PendingChange> {
+        let mut manifest = match self.perm_manifest(identifier)? {
+            Some(manifest) if !manifest.is_admin(signer) => {
+                return Err(ObjectStoreError::NotAnAdmin(signer.into()).into());
+            }
+            Some(manifest) => manifest,
+            None => PermManifest {
+                admins: vec![signer.into()],
+                ..PermManifest::default()
+            },
+        };
+        manifest.change_acl(change, key)?;
+        self.propose_perm_change(identifier, manifest)
+    }
+
+    /// Signs the pending change and finalizes it once the quorum is reached. Returns the
+    /// number of valid signatures and the quorum.
+    pub fn approve_perm_change(
+        &self,
+        identifier: &Identifier,
+        key: &str,
+        signature: &str,
+    ) -> Result<(usize, usize)> {
+        let (have, want) = self.sign_perm_change(identifier, key, signature)?;
+        if have >= want {
+            self.finalize_perm_change(identifier)?;
+        }
+        Ok((have, want))
+    }
+
+    /// Adds the signature of an admin to the pending change. Returns the number of valid
+    /// signatures and the quorum.
+    pub(crate) fn sign_perm_change(
+        &self,
+        identifier: &Identifier,
+        key: &str,
+        signature: &str,
+    ) -> Result<(usize, usize)> {
+        let mut pending = self
+            .pending_perm_change(identifier)?
+            .ok_or_else(|| ObjectStoreError::NoPendingChange(identifier.as_os_str().into()))?;
+        let current = self.perm_manifest(identifier)?;
+
+        self.check_perm_admin(current.as_ref(), &pending, key)?;
+        pending.sign(identifier, key, signature)?;
+        self.write_metadata(identifier, Meta::Pend, pending.to_string().as_bytes())?;
+
+        self.perm_quorum(identifier, current.as_ref(), &pending)
+    }
+
+    /// Discards the pending change, 'signature' by an admin must verify over its abort
+    /// message.
+    pub(crate) fn abort_perm_change(
+        &self,
+        identifier: &Identifier,
+        key: &str,
+        signature: &str,
+    ) -> Result<()> {
+        let pending = self
+            .pending_perm_change(identifier)?
+            .ok_or_else(|| ObjectStoreError::NoPendingChange(identifier.as_os_str().into()))?;
+        let current = self.perm_manifest(identifier)?;
+
+        self.check_perm_admin(current.as_ref(), &pending, key)?;
+        verify_signature(key, pending.abort_message(identifier).as_bytes(), signature)?;
+
+        info!("abort perm change on {}", identifier);
+        self.remove_metadata(identifier, Meta::Pend)
+    }
+
+    /// Makes the pending change effective when enough admins signed it.
+    pub(crate) fn finalize_perm_change(&self, identifier: &Identifier) -> Result<()> {
+        let pending = self
+            .pending_perm_change(identifier)?
+            .ok_or_else(|| ObjectStoreError::NoPendingChange(identifier.as_os_str().into()))?;
+        let current = self.perm_manifest(identifier)?;
+
+        if current.as_ref().and_then(|current| current.stamp) != pending.base {
+            return Err(ObjectStoreError::StalePendingChange(identifier.as_os_str().into()).into());
+        }
+
+        let (have, want) = self.perm_quorum(identifier, current.as_ref(), &pending)?;
+        if have < want {
+            return Err(ObjectStoreError::QuorumNotReached { have, want }.into());
+        }
+
+        info!("finalize perm change on {}", identifier);
+        self.commit_perm_change(identifier, &pending)?;
+        self.remove_metadata(identifier, Meta::Pend)
+    }
+
+    /// Takes over a finalized change of the perm manifest from another node. It must be based
+    /// on the current manifest and reach its quorum, replayed older manifests are rejected.
+    pub(crate) fn receive_perm_manifest(
+        &self,
+        identifier: &Identifier,
+        change: PendingChange,
+    ) -> Result<()> {
+        let stamp = change.manifest.stamp.ok_or_else(|| {
+            ObjectStoreError::ManifestSyntax(String::from("received manifest has no stamp"))
+        })?;
+        if !self.check_stamp(identifier, stamp)? {
+            trace!("perm manifest {} of {} already known", stamp, identifier);
+            return Ok(());
+        }
+
+        self.check_perm_manifest(&change.manifest)?;
+        let current = self.perm_manifest(identifier)?;
+        if current.as_ref().and_then(|current| current.stamp) != change.base {
+            return Err(ObjectStoreError::StalePendingChange(identifier.as_os_str().into()).into());
+        }
+        let (have, want) = self.perm_quorum(identifier, current.as_ref(), &change)?;
+        if have < want {
+            return Err(ObjectStoreError::QuorumNotReached { have, want }.into());
+        }
+
+        info!("received perm manifest {} of {}", stamp, identifier);
+        self.commit_perm_change(identifier, &change)
+    }
+
+    /// Records the stamp of a verified change and makes it the effective manifest.
+    fn commit_perm_change(&self, identifier: &Identifier, change: &PendingChange) -> Result<()> {
+        let stamp = change.manifest.stamp.ok_or_else(|| {
+            ObjectStoreError::ObjectStoreFatal(format!(
+                "perm change on {} has no stamp",
+                identifier
+            ))
+        })?;
+        self.accept_stamp(identifier, stamp)?;
+        self.write_metadata(identifier, Meta::Perm, change.to_string().as_bytes())
+    }
+
+    /// Checks a new manifest, it needs admins and all its keys must be valid and not revoked.
+    fn check_perm_manifest(&self, manifest: &PermManifest) -> Result<()> {
+        if manifest.admins.is_empty() {
+            return Err(
+                ObjectStoreError::ManifestSyntax(String::from("manifest has no admins")).into(),
+            );
+        }
+        let revocations = self.revocation_list()?;
+        manifest
+            .keys()
+            .try_for_each(|key| revocations.check_key(key))
+    }
+
+    /// Checks that 'key' may sign a change, the first manifest is signed by its own admins,
+    /// later ones by the current admins.
+    fn check_perm_admin(
+        &self,
+        current: Option<&PermManifest>,
+        pending: &PendingChange,
+        key: &str,
+    ) -> Result<()> {
+        if !current.unwrap_or(&pending.manifest).is_admin(key) {
+            return Err(ObjectStoreError::NotAnAdmin(key.into()).into());
+        }
+        self.revocation_list()?.check_key(key)
+    }
+
+    /// Counts the verified signatures of not revoked admins on a pending change against the
+    /// quorum of the current manifest, the first manifest against its own quorum.
+    fn perm_quorum(
+        &self,
+        identifier: &Identifier,
+        current: Option<&PermManifest>,
+        pending: &PendingChange,
+    ) -> Result<(usize, usize)> {
+        let authority = current.unwrap_or(&pending.manifest);
+        let revocations = self.revocation_list()?;
+        let message = pending.message(identifier);
+
+        let have = pending
+            .signatures
+            .iter()
+            .filter(|(key, signature)| {
+                authority.is_admin(key)
+                    && revocations.check_key(key).is_ok()
+                    && verify_signature(key, message.as_bytes(), signature).is_ok()
+            })
+            .map(|(key, _)| key.as_str())
+            .collect::<BTreeSet<_>>()
+            .len();
+
+        Ok((have, authority.quorum))
+    }
+}
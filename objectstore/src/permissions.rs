@@ -2,14 +2,20 @@ use std::io;
 use std::sync::Arc;
 use std::{collections::HashMap, time};
 
-use crate::{Identifier, Mutability, ObjectStore, ObjectType, SharingPolicy, UserId};
+use uberall::parking_lot::Mutex;
+
+use crate::prelude::*;
+use crate::{
+    Identifier, KeyId, Mutability, ObjectStore, ObjectType, RevocationList, SharingPolicy, UserId,
+    verify_signature,
+};
 
 /// Defines when authenticated keys expire and will be removed.
 ///  * Never:: keeps the keys forever
 ///  * Exact:: The key will expire at the given time
 ///  * Idle:: The will expire when it was not used for 'idle_time'
-#[derive(Debug)]
-enum KeyExpirePolicy {
+#[derive(Debug, Clone, Copy)]
+pub enum KeyExpirePolicy {
     Never,
     Exact {
         at: time::Instant,
@@ -20,63 +26,159 @@ enum KeyExpirePolicy {
     },
 }
 
+impl KeyExpirePolicy {
+    fn expired(&self, now: time::Instant) -> bool {
+        use KeyExpirePolicy::*;
+        match *self {
+            Never => false,
+            Exact { at } => at <= now,
+            Idle { at, idle_time: _ } => at <= now,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Debug)]
 struct AuthenticatedEntry {
     uid: UserId,
-    // PLANNED: pubkey: PublicKey,
+    key: KeyId,
 }
 
 /// stores authenticated keys
 #[derive(Debug)]
 pub struct PermissionController {
     objectstore:   Arc<ObjectStore>,
-    authenticated: HashMap<AuthenticatedEntry, KeyExpirePolicy>,
-    gc_countdown:  usize,
+    authenticated: Mutex<HashMap<AuthenticatedEntry, KeyExpirePolicy>>,
+    challenges:    Mutex<HashMap<UserId, String>>,
+    revocations:   Mutex<RevocationList>,
+    gc_countdown:  Mutex<usize>,
 }
 
 impl PermissionController {
     /// Creates a new PermissionControler for an Objectstore
-    pub fn new(objectstore: Arc<ObjectStore>) -> Self {
-        Self {
+    pub fn new(objectstore: Arc<ObjectStore>) -> Result<Self> {
+        let revocations = objectstore.revocation_list()?;
+        Ok(Self {
             objectstore,
-            authenticated: HashMap::new(),
-            gc_countdown: 63,
+            authenticated: Mutex::new(HashMap::new()),
+            challenges: Mutex::new(HashMap::new()),
+            revocations: Mutex::new(revocations),
+            gc_countdown: Mutex::new(63),
+        })
+    }
+
+    /// Reloads the revocation list from the objectstore.
+    pub fn reload_revocations(&self) -> Result<()> {
+        *self.revocations.lock() = self.objectstore.revocation_list()?;
+        Ok(())
+    }
+
+    /// Checks that 'key' is well formed and not revoked. Must be used for all keys before
+    /// they are used for authentication or permission checks.
+    pub fn verify_key(&self, key: &str) -> io::Result<()> {
+        self.revocations
+            .lock()
+            .check_key(key)
+            .map_err(|_| io::Error::from(io::ErrorKind::PermissionDenied))
+    }
+
+    /// Returns true when 'identifier' is a directory with the 'add-anonymous' rule.
+    pub(crate) fn is_dropbox(&self, identifier: &Identifier) -> bool {
+        identifier.object_type().is_directory()
+            && matches!(
+                self.objectstore.directory_rules(identifier),
+                Ok(Some(rules)) if rules.is_dropbox()
+            )
+    }
+
+    /// Returns true when any key authenticated for 'uid' is revoked.
+    fn uid_revoked(&self, uid: UserId) -> bool {
+        let revocations = self.revocations.lock();
+        self.authenticated
+            .lock()
+            .keys()
+            .any(|entry| entry.uid == uid && revocations.is_revoked(&entry.key))
+    }
+
+    /// Returns the keys 'uid' authenticated which are neither expired nor revoked. Keys with
+    /// an idle policy count as used.
+    fn keys_of(&self, uid: UserId) -> Vec<KeyId> {
+        let now = time::Instant::now();
+        let revocations = self.revocations.lock();
+        let mut keys = Vec::new();
+        for (entry, expire) in self.authenticated.lock().iter_mut() {
+            if entry.uid != uid || expire.expired(now) || revocations.check_key(&entry.key).is_err()
+            {
+                continue;
+            }
+            if let KeyExpirePolicy::Idle { at, idle_time } = expire {
+                *at = now + *idle_time;
+            }
+            keys.push(entry.key.clone());
         }
+        keys
     }
 
     // PLANNED: improve gc, can expire things at lockup/hash-collisions already
-    fn garbage_collect(&mut self) {
-        // TODO: gc test
-        self.gc_countdown -= 1;
-        if self.gc_countdown == 0 {
+    fn garbage_collect(&self) {
+        let mut countdown = self.gc_countdown.lock();
+        *countdown -= 1;
+        if *countdown == 0 {
             let now = time::Instant::now();
-            self.authenticated.retain(|_, expire| {
-                use KeyExpirePolicy::*;
-                match *expire {
-                    Never => true,
-                    Exact { at } => at > now,
-                    Idle { at, idle_time: _ } => at > now,
-                }
-            });
+            let mut authenticated = self.authenticated.lock();
+            authenticated.retain(|_, expire| !expire.expired(now));
             // gc every half capacity (-1), but no more frequent than every 63th insert
-            self.gc_countdown = std::cmp::max(self.authenticated.capacity(), 128) / 2 - 1;
+            *countdown = std::cmp::max(authenticated.capacity(), 128) / 2 - 1;
         }
     }
 
-    // PLANNED:    fn lookup(&mut self) { update incremental expire
+    /// Keys are authenticated by signing a challenge. This allows all private key handling
+    /// on a dedicated process outside of the vfs instance. Returns a new challenge for 'uid',
+    /// any former challenge becomes invalid.
+    pub fn challenge(&self, uid: UserId) -> String {
+        let challenge = base64::encode_config(
+            self.objectstore.uberall.rng_gen::<[u8; 32]>(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        self.challenges.lock().insert(uid, challenge.clone());
+        challenge
+    }
 
-    // PLANNED: Keys are authenticated by requesting a challenge against a pubkey.
-    // When this
-    //+ challenge succeeds then the Pubkey is stored as being authorized. This
-    //+ allowes for handling all private key handling on a dedicated process
-    //+ outside of the vfs instance.
-    //    pub fn auth_key(uid, PubKey, expire_policy) -> Result<Challenge> {
-    //        todo!()
-    //    }
-    //    pub fn add_key(Response) {
-    //        self.garbage_collect();
-    //        todo!()
-    //    }
+    /// The message which has to be signed to answer 'challenge'.
+    pub fn challenge_message(uid: UserId, challenge: &str) -> String {
+        format!("uberallfs authenticate {} {}", uid, challenge)
+    }
+
+    /// Authenticates 'key' for 'uid' when 'signature' answers the last challenge of 'uid'.
+    /// The challenge is used up by this.
+    pub fn authenticate(
+        &self,
+        uid: UserId,
+        key: &str,
+        signature: &str,
+        expire: KeyExpirePolicy,
+    ) -> Result<()> {
+        let challenge = self.challenges.lock().remove(&uid).ok_or_else(|| {
+            ObjectStoreError::OptArgError(format!("no challenge for uid {}", uid))
+        })?;
+        self.reload_revocations()?;
+        self.verify_key(key)?;
+        verify_signature(
+            key,
+            Self::challenge_message(uid, &challenge).as_bytes(),
+            signature,
+        )?;
+
+        self.garbage_collect();
+        info!("uid {} authenticated {}", uid, key);
+        self.authenticated.lock().insert(
+            AuthenticatedEntry {
+                uid,
+                key: key.into(),
+            },
+            expire,
+        );
+        Ok(())
+    }
 
     pub fn permission_check<'a>(
         &'a self,
@@ -104,84 +206,144 @@ use ObjectType::*;
 use SharingPolicy::*;
 
 impl PermissionCheck<'_> {
-    pub fn read(&self) -> io::Result<()> {
+    /// Components of the checked object, directories with parent are checked like
+    /// directories.
+    fn components(&self) -> (ObjectType, SharingPolicy, Mutability) {
         match self.identifier.components() {
+            (DirectoryWithParent, sharing_policy, mutability) => {
+                (Directory, sharing_policy, mutability)
+            }
+            components => components,
+        }
+    }
+
+    /// PublicAcl objects grant 'permission' to the keys listed in their perm manifest, the
+    /// uid must have authenticated one of them.
+    fn acl(&self, permission: &str) -> io::Result<()> {
+        let uid = self
+            .uid
+            .ok_or_else(|| io::Error::from(io::ErrorKind::PermissionDenied))?;
+        let manifest = match self.controller.objectstore.perm_manifest(self.identifier) {
+            Ok(Some(manifest)) => manifest,
+            Ok(None) => return Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            Err(err) => {
+                warn!("perm manifest of {}: {}", self.identifier, err);
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+            }
+        };
+        let granted = manifest.acl(permission);
+        if self
+            .controller
+            .keys_of(uid)
+            .iter()
+            .any(|key| granted.contains(key))
+        {
+            Ok(())
+        } else {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        }
+    }
+
+    /// Revoked keys lose all access.
+    fn not_revoked(&self) -> io::Result<()> {
+        match self.uid {
+            Some(uid) if self.controller.uid_revoked(uid) => {
+                warn!("uid {} uses a revoked key", uid);
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read(&self) -> io::Result<()> {
+        self.not_revoked()?;
+        match self.components() {
             (_, Private | Anonymous, _) => Ok(()),
-            (_, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (_, PublicAcl, _) => self.acl("read"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
     pub fn write(&self) -> io::Result<()> {
-        match self.identifier.components() {
-            (File, Private, _) => Ok(()),
+        self.not_revoked()?;
+        match self.components() {
             (File, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
-            (File, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (File, Private, _) => Ok(()),
+            (File, PublicAcl, _) => self.acl("write"),
+            _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+
+    /// Changing attributes of the object itself, files and directories alike.
+    pub fn setattr(&self) -> io::Result<()> {
+        self.not_revoked()?;
+        match self.components() {
+            (_, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            (_, Private, _) => Ok(()),
+            (_, PublicAcl, _) => self.acl("write"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
     pub fn append(&self) -> io::Result<()> {
-        match self.identifier.components() {
-            (File, Private, _) => Ok(()),
+        self.not_revoked()?;
+        match self.components() {
             (File, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
-            (File, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (File, Private, _) => Ok(()),
+            (File, PublicAcl, _) => self.acl("append"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
     pub fn list(&self) -> io::Result<()> {
-        match self.identifier.components() {
+        self.not_revoked()?;
+        match self.components() {
             (Directory, Private | Anonymous, _) => Ok(()),
-            (Directory, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (Directory, PublicAcl, _) => self.acl("list"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
     pub fn add(&self) -> io::Result<()> {
-        match self.identifier.components() {
-            (Directory, Private, _) => Ok(()),
+        self.not_revoked()?;
+        match self.components() {
             (Directory, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
-            (Directory, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (Directory, Private, _) => Ok(()),
+            (Directory, PublicAcl, _) => self.acl("add"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
+    /// Anyone may add anonymous immutable objects to drop-box directories, otherwise the
+    /// same as 'add()'.
+    pub fn add_anonymous(&self) -> io::Result<()> {
+        self.not_revoked()?;
+        match self.components() {
+            (Directory, Private | PublicAcl, Mutable)
+                if self.controller.is_dropbox(self.identifier) =>
+            {
+                Ok(())
+            }
+            _ => self.add(),
+        }
+    }
+
     pub fn rename(&self) -> io::Result<()> {
-        match self.identifier.components() {
-            (Directory, Private, _) => Ok(()),
+        self.not_revoked()?;
+        match self.components() {
             (Directory, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
-            (Directory, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (Directory, Private, _) => Ok(()),
+            (Directory, PublicAcl, _) => self.acl("rename"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
 
     pub fn delete(&self) -> io::Result<()> {
-        match self.identifier.components() {
-            (Directory, Private, _) => Ok(()),
+        self.not_revoked()?;
+        match self.components() {
             (Directory, _, Immutable) => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
-            (Directory, PublicAcl, _) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "//TODO: Unimplemented",
-            )),
+            (Directory, Private, _) => Ok(()),
+            (Directory, PublicAcl, _) => self.acl("delete"),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
         }
     }
@@ -0,0 +1,178 @@
+//! Pin sets.
+//!
+//! Pins keep objects alive in garbage collection even when they are not reachable from the
+//! root, like backup snapshots. Pinned directories keep everything below them alive.
+//!
+//! Pin sets are stored as 'objects/pins/NAME', one identifier per line. Objects pinned
+//! without naming a set go into the 'default' set.
+use std::convert::TryInto;
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::{Flipbase64, Identifier, LockingMethod::*, ObjectStore};
+
+/// The pin set used when none is given
+pub const DEFAULT_PIN_SET: &str = "default";
+
+pub(crate) fn opt_pin(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = if matches.is_present("OBJECT") {
+        ObjectStore::open(dir.as_ref(), WaitForLock)?
+    } else {
+        ObjectStore::open_shared(dir.as_ref(), WaitForLock)?
+    };
+
+    let set = matches.value_of("SET").unwrap_or(DEFAULT_PIN_SET);
+
+    match matches.values_of_os("OBJECT") {
+        Some(objects) => {
+            for object in objects {
+                let identifier = objectstore.object_lookup(object)?;
+                if matches.is_present("remove") {
+                    objectstore.unpin(set, &identifier)?;
+                } else {
+                    objectstore.pin(set, &identifier)?;
+                }
+            }
+        }
+        None => {
+            for set in objectstore.pin_sets()? {
+                for identifier in objectstore.pins(&set)? {
+                    println!("{} {}", set, identifier);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ObjectStore {
+    /// Resolves an abbreviated identifier or a path as accepted by 'path_lookup()'. Paths
+    /// must exist completely.
+    pub fn object_lookup(&self, object: &OsStr) -> Result<Identifier> {
+        if !object.as_bytes().contains(&b'/') {
+            return self.identifier_lookup(object);
+        }
+
+        let (identifier, remaining) = self.path_lookup(Path::new(object), None)?;
+        if remaining.as_os_str().is_empty() {
+            Ok(identifier)
+        } else {
+            Err(ObjectStoreError::ObjectNotFound(object.into()).into())
+        }
+    }
+
+    /// Returns the names of all pin sets.
+    pub fn pin_sets(&self) -> Result<Vec<String>> {
+        let mut sets = Vec::new();
+        match self.objects.list_dir("pins") {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    sets.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        sets.sort();
+        Ok(sets)
+    }
+
+    /// Returns the objects pinned by the set 'name', an unknown set is empty.
+    pub fn pins(&self, name: &str) -> Result<Vec<Identifier>> {
+        use std::io::Read;
+
+        check_pin_set(name)?;
+        let mut text = String::new();
+        match self.objects.open_file(&pin_set_path(name)) {
+            Ok(mut file) => file.read_to_string(&mut text)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                Identifier::from_flipbase64(Flipbase64(line.as_bytes().try_into().map_err(
+                    |_| {
+                        ObjectStoreError::ObjectStoreFatal(format!(
+                            "malformed pin in set {}: {:?}",
+                            name, line
+                        ))
+                    },
+                )?))
+            })
+            .collect()
+    }
+
+    /// Returns all pinned objects of all sets.
+    pub fn all_pins(&self) -> Result<Vec<Identifier>> {
+        let mut pins = Vec::new();
+        for set in self.pin_sets()? {
+            for identifier in self.pins(&set)? {
+                if !pins.contains(&identifier) {
+                    pins.push(identifier);
+                }
+            }
+        }
+        Ok(pins)
+    }
+
+    /// Adds 'identifier' to the pin set 'name', creates the set when necessary.
+    pub fn pin(&self, name: &str, identifier: &Identifier) -> Result<()> {
+        let mut pins = self.pins(name)?;
+        if !pins.contains(identifier) {
+            pins.push(identifier.clone());
+            info!("pin {}: {}", name, identifier);
+            self.write_pin_set(name, &pins)?;
+        }
+        Ok(())
+    }
+
+    /// Removes 'identifier' from the pin set 'name', empty sets are removed.
+    pub fn unpin(&self, name: &str, identifier: &Identifier) -> Result<()> {
+        let mut pins = self.pins(name)?;
+        let len = pins.len();
+        pins.retain(|pinned| pinned != identifier);
+        if pins.len() == len {
+            return Err(ObjectStoreError::ObjectNotFound(identifier.to_string().into()).into());
+        }
+        info!("unpin {}: {}", name, identifier);
+        if pins.is_empty() {
+            Ok(self.objects.remove_file(&pin_set_path(name))?)
+        } else {
+            self.write_pin_set(name, &pins)
+        }
+    }
+
+    fn write_pin_set(&self, name: &str, pins: &[Identifier]) -> Result<()> {
+        match self.objects.create_dir("pins", 0o770) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+            _ => {}
+        }
+
+        let text: String = pins
+            .iter()
+            .map(|identifier| format!("{}\n", identifier))
+            .collect();
+        self.write_atomic(&pin_set_path(name), text.as_bytes())
+    }
+}
+
+fn pin_set_path(name: &str) -> PathBuf {
+    PathBuf::from("pins").join(name)
+}
+
+/// Pin set names become file names.
+fn check_pin_set(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        Err(ObjectStoreError::OptArgError(format!("invalid pin set name: {:?}", name)).into())
+    } else {
+        Ok(())
+    }
+}
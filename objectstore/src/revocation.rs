@@ -0,0 +1,376 @@
+//! Key revocation lists.
+//!
+//! A revocation list is an object of its own type holding revoked keys. Entries must be
+//! signed by the owner of the revoked key or by one of the admins listed in the revocation
+//! list. Admins are authorized by an existing admin, only the first admin signs for itself.
+//! To be safe against flooding, lists are bounded in size and every entry expires.
+//!
+//! The objectstore refers to its revocation list by the 'objects/revocations' symlink. The
+//! list is stored as text, one entry per line:
+//!
+//!  * admin KEY SIGNER SIGNATURE:: KEY is authorized to revoke any key
+//!  * revoke KEY SIGNER EXPIRES SIGNATURE:: KEY is revoked until EXPIRES (seconds since epoch)
+//!  * stamp STAMP:: stamp of the list, renewed with every change
+//!
+//! Signatures are made over 'uberallfs revocation-admin KEY' and
+//! 'uberallfs revoke KEY EXPIRES'.
+use std::convert::TryInto;
+use std::ffi::OsStr;
+use std::fmt;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::object::Object;
+use crate::objectstore::FileAccess;
+use crate::{
+    Flipbase64, Identifier, KeyId, LockingMethod::*, MetadataStamp, ObjectPath, ObjectStore,
+    key_id, load_key, sign, verify_signature,
+};
+
+/// Maximum number of entries in a revocation list
+pub const MAX_REVOCATIONS: usize = 4096;
+
+pub(crate) fn opt_revoke(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+    let mut list = objectstore.revocation_list()?;
+    let key = matches.value_of("KEY").unwrap();
+    let keypair = matches
+        .value_of_os("SIGNWITH")
+        .map(|keyfile| load_key(Path::new(keyfile)))
+        .transpose()?;
+    let signer: KeyId = match &keypair {
+        Some(keypair) => key_id(keypair),
+        None => matches.value_of("SIGNER").unwrap_or(key).into(),
+    };
+
+    if matches.is_present("admin") {
+        let mut admin = Admin {
+            key: key.into(),
+            signer,
+            signature: matches.value_of("SIGNATURE").unwrap_or_default().into(),
+        };
+        if let Some(keypair) = &keypair {
+            admin.signature = sign(keypair, admin.message().as_bytes());
+        }
+        list.add_admin(admin)?;
+    } else {
+        let mut revocation = Revocation {
+            key: key.into(),
+            signer,
+            expires: match matches.value_of("UNTIL") {
+                Some(until) => until.parse()?,
+                None => now() + matches.value_of("DAYS").unwrap().parse::<u64>()? * 86400,
+            },
+            signature: matches.value_of("SIGNATURE").unwrap_or_default().into(),
+        };
+        if let Some(keypair) = &keypair {
+            revocation.signature = sign(keypair, revocation.message().as_bytes());
+        }
+        list.insert(revocation)?;
+    }
+
+    objectstore.write_revocation_list(&mut list)
+}
+
+pub(crate) fn opt_revocations(dir: &OsStr, _matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+
+    if let Some(identifier) = objectstore.revocation_list_id()? {
+        println!("revocation list: {}", identifier);
+    }
+    print!("{}", objectstore.revocation_list()?);
+    Ok(())
+}
+
+/// Seconds since epoch
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A single revoked key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revocation {
+    pub key:       KeyId,
+    pub signer:    KeyId,
+    pub expires:   u64,
+    pub signature: String,
+}
+
+impl Revocation {
+    /// The message the signature is made over.
+    pub fn message(&self) -> String {
+        format!("uberallfs revoke {} {}", self.key, self.expires)
+    }
+}
+
+/// A key authorized to revoke other keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct Admin {
+    pub key:       KeyId,
+    pub signer:    KeyId,
+    pub signature: String,
+}
+
+impl Admin {
+    /// The message the signature is made over.
+    pub fn message(&self) -> String {
+        format!("uberallfs revocation-admin {}", self.key)
+    }
+}
+
+/// The revoked keys of an objectstore
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    admins:  Vec<Admin>,
+    entries: Vec<Revocation>,
+    stamp:   Option<MetadataStamp>,
+}
+
+impl RevocationList {
+    /// Parse a revocation list from its textual representation, expired entries are dropped.
+    pub fn parse(text: &str) -> Result<RevocationList> {
+        let mut list = RevocationList::default();
+        let now = now();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let malformed = || {
+                ObjectStoreError::ObjectStoreFatal(format!(
+                    "malformed revocation list, line {}",
+                    lineno + 1
+                ))
+            };
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                ["admin", key, signer, signature] => list.admins.push(Admin {
+                    key:       key.into(),
+                    signer:    signer.into(),
+                    signature: signature.into(),
+                }),
+                ["revoke", key, signer, expires, signature] => {
+                    let expires = expires.parse().map_err(|_| malformed())?;
+                    if expires > now {
+                        list.entries.push(Revocation {
+                            key: key.into(),
+                            signer: signer.into(),
+                            expires,
+                            signature: signature.into(),
+                        });
+                    }
+                }
+                ["stamp", stamp] => list.stamp = Some(MetadataStamp::parse(stamp)?),
+                _ => return Err(malformed().into()),
+            }
+        }
+
+        if list.entries.len() > MAX_REVOCATIONS {
+            return Err(ObjectStoreError::RevocationListFull.into());
+        }
+
+        Ok(list)
+    }
+
+    /// Returns true when 'key' is revoked.
+    pub fn is_revoked(&self, key: &str) -> bool {
+        let now = now();
+        self.entries
+            .iter()
+            .any(|entry| entry.key == key && entry.expires > now)
+    }
+
+    /// Checks that 'key' is well formed and not revoked.
+    pub fn check_key(&self, key: &str) -> Result<()> {
+        crate::check_key(key)?;
+        if self.is_revoked(key) {
+            warn!("key revoked: {}", key);
+            return Err(ObjectStoreError::KeyRevoked(key.into()).into());
+        }
+        Ok(())
+    }
+
+    /// Returns true when 'key' is an admin which is not revoked.
+    pub fn is_admin(&self, key: &str) -> bool {
+        self.admins.iter().any(|admin| admin.key == key) && !self.is_revoked(key)
+    }
+
+    /// Authorize a key to revoke other keys. The signer must be an admin already, only the
+    /// first admin of a list signs for itself.
+    pub fn add_admin(&mut self, admin: Admin) -> Result<()> {
+        let authorized = if self.admins.is_empty() {
+            admin.signer == admin.key
+        } else {
+            self.is_admin(&admin.signer)
+        };
+        if !authorized {
+            return Err(ObjectStoreError::RevocationUnauthorized {
+                key:    admin.key,
+                signer: admin.signer,
+            }
+            .into());
+        }
+        self.check_key(&admin.key)?;
+        verify_signature(&admin.signer, admin.message().as_bytes(), &admin.signature)?;
+
+        if !self.admins.iter().any(|existing| existing.key == admin.key) {
+            info!("revocation admin: {}", admin.key);
+            self.admins.push(admin);
+        }
+        Ok(())
+    }
+
+    /// Adds a revocation. The signer must be the revoked key itself or an admin and the
+    /// signature must verify. An existing revocation of the same key is replaced when the new
+    /// one expires later.
+    pub fn insert(&mut self, revocation: Revocation) -> Result<()> {
+        if revocation.signer != revocation.key && !self.is_admin(&revocation.signer) {
+            return Err(ObjectStoreError::RevocationUnauthorized {
+                key:    revocation.key,
+                signer: revocation.signer,
+            }
+            .into());
+        }
+        verify_signature(
+            &revocation.signer,
+            revocation.message().as_bytes(),
+            &revocation.signature,
+        )?;
+
+        let now = now();
+        self.entries.retain(|entry| entry.expires > now);
+
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.key == revocation.key)
+        {
+            if revocation.expires > entry.expires {
+                *entry = revocation;
+            }
+            Ok(())
+        } else if self.entries.len() >= MAX_REVOCATIONS {
+            warn!("revocation list full, rejecting {}", revocation.key);
+            Err(ObjectStoreError::RevocationListFull.into())
+        } else {
+            info!("revoke: {}", revocation.key);
+            self.entries.push(revocation);
+            Ok(())
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Revocation> {
+        self.entries.iter()
+    }
+
+    pub fn stamp(&self) -> Option<MetadataStamp> {
+        self.stamp
+    }
+}
+
+impl fmt::Display for RevocationList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        for admin in &self.admins {
+            writeln!(
+                f,
+                "admin {} {} {}",
+                admin.key, admin.signer, admin.signature
+            )?;
+        }
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "revoke {} {} {} {}",
+                entry.key, entry.signer, entry.expires, entry.signature
+            )?;
+        }
+        if let Some(stamp) = self.stamp {
+            writeln!(f, "stamp {}", stamp)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore {
+    /// Returns the Identifier of the ObjectStores revocation list, 'None' when there is none.
+    pub fn revocation_list_id(&self) -> Result<Option<Identifier>> {
+        match self.objects.read_link("revocations") {
+            Ok(link) => {
+                let identifier = Identifier::from_flipbase64(Flipbase64(
+                    link.file_name()
+                        .ok_or_else(|| {
+                            ObjectStoreError::ObjectStoreFatal(String::from(
+                                "revocation list not found",
+                            ))
+                        })?
+                        .as_bytes()
+                        .try_into()?,
+                ))?;
+                if identifier.object_type() != ObjectType::RevocationList {
+                    return Err(ObjectStoreError::ObjectType {
+                        have: identifier.object_type(),
+                        want: ObjectType::RevocationList,
+                    }
+                    .into());
+                }
+                Ok(Some(identifier))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the ObjectStores revocation list, empty when there is none.
+    pub fn revocation_list(&self) -> Result<RevocationList> {
+        use std::io::Read;
+
+        match self.revocation_list_id()? {
+            Some(identifier) => {
+                let mut text = String::new();
+                self.openat_file(
+                    &identifier.to_pathbuf(),
+                    FileAccess::new().readonly().get(),
+                    0,
+                )?
+                .read_to_string(&mut text)?;
+                RevocationList::parse(&text)
+            }
+            None => Ok(RevocationList::default()),
+        }
+    }
+
+    /// Stores the revocation list with a new stamp, creates the revocation list object when
+    /// necessary.
+    pub(crate) fn write_revocation_list(&self, list: &mut RevocationList) -> Result<()> {
+        let identifier = match self.revocation_list_id()? {
+            Some(identifier) => identifier,
+            None => {
+                let object = Object::build(
+                    ObjectType::RevocationList,
+                    SharingPolicy::Private,
+                    Mutability::Mutable,
+                )
+                .realize(self)?;
+
+                let mut path = PathBuf::new();
+                path.push_identifier(&object.identifier);
+                info!("revocation list: {:?}", path.as_os_str());
+                self.objects.symlink("revocations", path.as_os_str())?;
+                object.identifier
+            }
+        };
+
+        let stamp = self.next_stamp(&identifier)?;
+        list.stamp = Some(stamp);
+        self.write_atomic(&identifier.to_pathbuf(), list.to_string().as_bytes())?;
+        self.accept_stamp(&identifier, stamp)
+    }
+}
@@ -0,0 +1,228 @@
+//! Root management.
+//!
+//! Besides the root directory ('objects/root') an objectstore can have named roots like
+//! 'home' or 'archive', stored as symlinks 'objects/roots/NAME' to the directory objects.
+//! Named roots can be mounted with 'fuse mount --root NAME' and all of them are gc roots.
+//! Setting a new root keeps the old one as named root, it never becomes garbage silently.
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::{Identifier, LockingMethod::*, ObjectPath, ObjectStore};
+
+/// Name the previous root is kept under unless another one is given
+pub const PREVIOUS_ROOT: &str = "previous";
+
+pub(crate) fn opt_root(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    if let Some(object) = matches.value_of_os("set") {
+        let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+        let identifier = objectstore.object_lookup(object)?;
+        match matches.value_of("name") {
+            Some(name) => objectstore.set_named_root(name, &identifier),
+            None => {
+                let kept = objectstore.replace_root(&identifier, matches.value_of("keep"))?;
+                if let Some(kept) = kept {
+                    println!("kept {}", kept);
+                }
+                Ok(())
+            }
+        }
+    } else if let Some(name) = matches.value_of("remove") {
+        let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+        objectstore.remove_named_root(name)
+    } else {
+        let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+        if let Ok(root) = objectstore.get_root_id() {
+            println!("root {}", root);
+        }
+        for (name, identifier) in objectstore.named_roots()? {
+            println!("{} {}", name, identifier);
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore {
+    /// Returns all named roots sorted by name.
+    pub fn named_roots(&self) -> Result<Vec<(String, Identifier)>> {
+        let mut roots = Vec::new();
+        match self.objects.list_dir("roots") {
+            Ok(entries) => {
+                for entry in entries {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    let identifier = self.named_root(&name)?;
+                    roots.push((name, identifier));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        roots.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(roots)
+    }
+
+    /// Returns the named root 'name'.
+    pub fn named_root(&self, name: &str) -> Result<Identifier> {
+        check_root_name(name)?;
+        match self.objects.read_link(&root_path(name)) {
+            Ok(target) => Identifier::from_filename(&target),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Err(ObjectStoreError::ObjectNotFound(name.into()).into())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Resolves the root to mount: the root directory when 'root' is empty, a named root
+    /// when it contains no '/', otherwise a path as accepted by 'path_lookup()'.
+    pub fn mount_root(&self, root: &OsStr) -> Result<Identifier> {
+        if root.is_empty() || root.as_bytes().contains(&b'/') {
+            let (identifier, remaining) = self.path_lookup(Path::new(root), None)?;
+            if !remaining.as_os_str().is_empty() {
+                return Err(ObjectStoreError::ObjectNotFound(root.into()).into());
+            }
+            Ok(identifier)
+        } else {
+            self.named_root(&root.to_string_lossy())
+        }
+    }
+
+    /// Sets or replaces the named root 'name'.
+    pub fn set_named_root(&self, name: &str, identifier: &Identifier) -> Result<()> {
+        check_root_name(name)?;
+        identifier.ensure_root()?;
+        match self.objects.create_dir("roots", 0o770) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+            _ => {}
+        }
+        info!("set root {}: {}", name, identifier);
+        self.symlink_atomic(&root_path(name), identifier)
+    }
+
+    /// Adds the named root 'name', fails with 'ObjectExists' when it exists already.
+    pub fn add_named_root(&self, name: &str, identifier: &Identifier) -> Result<()> {
+        check_root_name(name)?;
+        identifier.ensure_root()?;
+        match self.objects.create_dir("roots", 0o770) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+            _ => {}
+        }
+        info!("add root {}: {}", name, identifier);
+        let path = root_path(name);
+        match self.objects.symlink(&path, &link_target(&path, identifier)) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                Err(ObjectStoreError::ObjectExists(name.into()).into())
+            }
+            other => Ok(other?),
+        }
+    }
+
+    /// Removes the named root 'name', the directory becomes garbage unless referenced
+    /// otherwise.
+    pub fn remove_named_root(&self, name: &str) -> Result<()> {
+        check_root_name(name)?;
+        info!("remove root {}", name);
+        match self.objects.remove_file(&root_path(name)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Err(ObjectStoreError::ObjectNotFound(name.into()).into())
+            }
+            other => Ok(other?),
+        }
+    }
+
+    /// Makes 'identifier' the root directory. The old root is kept as named root 'keep' or
+    /// the first free of 'previous', 'previous-1', ... and that name is returned. An existing
+    /// named root 'keep' is never replaced.
+    pub fn replace_root(
+        &self,
+        identifier: &Identifier,
+        keep: Option<&str>,
+    ) -> Result<Option<String>> {
+        identifier.ensure_root()?;
+        let old = match self.get_root_id() {
+            Ok(old) if old == *identifier => return Ok(None),
+            Ok(old) => Some(old),
+            Err(err)
+                if err.downcast_ref::<io::Error>().map(io::Error::kind)
+                    == Some(io::ErrorKind::NotFound) =>
+            {
+                None
+            }
+            Err(err) => return Err(err),
+        };
+
+        let kept = match old {
+            Some(old) => {
+                let name = match keep {
+                    Some(name) => String::from(name),
+                    None => self.free_root_name(PREVIOUS_ROOT)?,
+                };
+                self.add_named_root(&name, &old)?;
+                Some(name)
+            }
+            None => None,
+        };
+
+        self.set_root(identifier)?;
+        Ok(kept)
+    }
+
+    fn free_root_name(&self, base: &str) -> Result<String> {
+        let taken: Vec<String> = self
+            .named_roots()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        Ok((0..)
+            .map(|n| {
+                if n == 0 {
+                    String::from(base)
+                } else {
+                    format!("{}-{}", base, n)
+                }
+            })
+            .find(|name| !taken.contains(name))
+            .unwrap())
+    }
+
+    /// Replaces the symlink 'path' (relative to 'objects/') by one pointing to 'identifier'.
+    pub(crate) fn symlink_atomic(&self, path: &Path, identifier: &Identifier) -> Result<()> {
+        let target = link_target(path, identifier);
+
+        let tmp = PathBuf::from("tmp").join(format!(
+            "{}.{}",
+            path.file_name().unwrap().to_string_lossy(),
+            std::process::id()
+        ));
+        match self.objects.remove_file(&tmp) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        self.objects.symlink(&tmp, &target)?;
+        Ok(self.objects.local_rename(&tmp, path)?)
+    }
+}
+
+fn root_path(name: &str) -> PathBuf {
+    PathBuf::from("roots").join(name)
+}
+
+/// The target of a symlink at 'path' (relative to 'objects/') to 'identifier'.
+fn link_target(path: &Path, identifier: &Identifier) -> PathBuf {
+    let mut target: PathBuf = path.iter().skip(1).map(|_| "..").collect();
+    target.push_identifier(identifier);
+    target
+}
+
+/// Root names become file names.
+fn check_root_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        Err(ObjectStoreError::OptArgError(format!("invalid root name: {:?}", name)).into())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+//! Declarative rules attached to directories.
+//!
+//! Rules are stored as 'rule' metadata next to a directory object. The format is line based,
+//! empty lines and lines starting with '#' are ignored:
+//!
+//!  * max-size SIZE:: files must not become larger than SIZE bytes (K/M/G/T suffixes allowed)
+//!  * only files|directories:: only accept the given kind of entries
+//!  * accept GLOB..:: names must match one of the accepted patterns (when any are given)
+//!  * reject GLOB..:: names matching any of these patterns are refused
+//!  * retype FROM TO SHARING MUTABILITY:: renaming an object from FROM to TO changes its type,
+//!    SHARING is one of 'private', 'public' or 'anonymous', MUTABILITY is 'mutable' or
+//!    'immutable'
+//!  * add-anonymous:: makes the directory a drop-box, anyone may add anonymous immutable
+//!    objects but nothing else can be added and entries can not be renamed. Files created
+//!    there through the filesystem become anonymous objects when they are closed
+//!
+//! Violations are reported as io errors with EPERM (names, types) or EFBIG (size).
+use std::ffi::OsStr;
+use std::fmt;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use regex::bytes::Regex;
+use uberall::clap::ArgMatches;
+use uberall::libc;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectstore::{FileAccess, Meta};
+use crate::{Identifier, LockingMethod::*, Object, ObjectStore, SubObject};
+
+pub(crate) fn opt_rules(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (directory, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+    directory.ensure_dir()?;
+
+    if let Some(rulefile) = matches.value_of_os("RULEFILE") {
+        let text = std::fs::read_to_string(rulefile)?;
+        // validate before attaching
+        Rules::parse(&text)?;
+        objectstore.write_metadata(&directory, Meta::Rule, text.as_bytes())?;
+    } else if matches.is_present("clear") {
+        objectstore.remove_metadata(&directory, Meta::Rule)?;
+    }
+
+    let rules = objectstore.directory_rules(&directory)?.unwrap_or_default();
+
+    if let Some(name) = matches.value_of_os("NAME") {
+        let object_type = if matches.is_present("directory") {
+            ObjectType::Directory
+        } else {
+            ObjectType::File
+        };
+        let components = if matches.is_present("anonymous") {
+            (object_type, SharingPolicy::Anonymous, Mutability::Immutable)
+        } else {
+            (object_type, SharingPolicy::Private, Mutability::Mutable)
+        };
+
+        if let Some(from) = matches.value_of_os("FROM") {
+            if let Some((sharing_policy, mutability)) =
+                rules.check_rename(from, name, components)?
+            {
+                println!(
+                    "{:?} -> {:?}: retype to {:?} {:?}",
+                    from, name, sharing_policy, mutability
+                );
+            }
+        } else {
+            rules.check_create(name, components)?;
+        }
+
+        if let Some(size) = matches.value_of("SIZE") {
+            rules.check_size(parse_size(size)?)?;
+        }
+
+        println!("{:?}: ok", name);
+    } else {
+        print!("{}", rules);
+    }
+
+    Ok(())
+}
+
+/// The parsed rules of a directory
+#[derive(Debug, Default)]
+pub struct Rules {
+    max_size:      Option<u64>,
+    only:          Option<ObjectType>,
+    accept:        Vec<Glob>,
+    reject:        Vec<Glob>,
+    retype:        Vec<Retype>,
+    add_anonymous: bool,
+}
+
+/// Filename pattern, '*' matches any sequence and '?' any single character
+#[derive(Debug)]
+struct Glob {
+    pattern: String,
+    regex:   Regex,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Result<Glob> {
+        let mut re = String::from("(?s-u)^");
+        for c in pattern.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0u8; 4]))),
+            }
+        }
+        re.push('$');
+
+        Ok(Glob {
+            pattern: pattern.into(),
+            regex:   Regex::new(&re)?,
+        })
+    }
+
+    fn matches(&self, name: &OsStr) -> bool {
+        self.regex.is_match(name.as_bytes())
+    }
+}
+
+#[derive(Debug)]
+struct Retype {
+    from:           Glob,
+    to:             Glob,
+    sharing_policy: SharingPolicy,
+    mutability:     Mutability,
+}
+
+impl Rules {
+    /// Parse rules from their textual representation.
+    pub fn parse(text: &str) -> Result<Rules> {
+        let mut rules = Rules::default();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let syntax_error =
+                |what: &str| ObjectStoreError::RuleSyntax(format!("line {}: {}", lineno + 1, what));
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some(word) if word.starts_with('#') => continue,
+                Some("max-size") => {
+                    rules.max_size = Some(parse_size(
+                        words.next().ok_or_else(|| syntax_error("size missing"))?,
+                    )?);
+                }
+                Some("only") => {
+                    rules.only = Some(match words.next() {
+                        Some("files") => ObjectType::File,
+                        Some("directories") => ObjectType::Directory,
+                        _ => {
+                            return Err(syntax_error("expected 'files' or 'directories'").into());
+                        }
+                    });
+                }
+                Some("accept") => {
+                    for pattern in words.by_ref() {
+                        rules.accept.push(Glob::new(pattern)?);
+                    }
+                }
+                Some("reject") => {
+                    for pattern in words.by_ref() {
+                        rules.reject.push(Glob::new(pattern)?);
+                    }
+                }
+                Some("add-anonymous") => rules.add_anonymous = true,
+                Some("retype") => {
+                    let mut next = |what| words.next().ok_or_else(|| syntax_error(what));
+                    let from = Glob::new(next("source pattern missing")?)?;
+                    let to = Glob::new(next("destination pattern missing")?)?;
+                    let sharing_policy = match next("sharing policy missing")? {
+                        "private" => SharingPolicy::Private,
+                        "public" => SharingPolicy::PublicAcl,
+                        "anonymous" => SharingPolicy::Anonymous,
+                        other => {
+                            return Err(syntax_error(&format!(
+                                "unknown sharing policy '{}'",
+                                other
+                            ))
+                            .into());
+                        }
+                    };
+                    let mutability = match next("mutability missing")? {
+                        "mutable" => Mutability::Mutable,
+                        "immutable" => Mutability::Immutable,
+                        other => {
+                            return Err(
+                                syntax_error(&format!("unknown mutability '{}'", other)).into()
+                            );
+                        }
+                    };
+                    if (sharing_policy, mutability)
+                        == (SharingPolicy::Anonymous, Mutability::Mutable)
+                    {
+                        return Err(syntax_error("anonymous objects must be immutable").into());
+                    }
+                    rules.retype.push(Retype {
+                        from,
+                        to,
+                        sharing_policy,
+                        mutability,
+                    });
+                }
+                Some(other) => {
+                    return Err(syntax_error(&format!("unknown rule '{}'", other)).into());
+                }
+            }
+
+            if let Some(extra) = words.next() {
+                return Err(syntax_error(&format!("unexpected '{}'", extra)).into());
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Check that an object named 'name' of the given kind can be created or added.
+    pub fn check_create(
+        &self,
+        name: &OsStr,
+        (object_type, sharing_policy, mutability): (ObjectType, SharingPolicy, Mutability),
+    ) -> io::Result<()> {
+        if self.add_anonymous
+            && (sharing_policy, mutability) != (SharingPolicy::Anonymous, Mutability::Immutable)
+        {
+            warn!("rule violated: add-anonymous: {:?}", name);
+            return Err(io::Error::from_raw_os_error(libc::EPERM));
+        }
+        if let Some(only) = self.only {
+            if only != object_type && !(only.is_directory() && object_type.is_directory()) {
+                warn!("rule violated: only {:?}: {:?}", only, name);
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+        }
+        self.check_name(name)
+    }
+
+    /// Check that a file does not exceed the maximum size.
+    pub fn check_size(&self, size: u64) -> io::Result<()> {
+        match self.max_size {
+            Some(max_size) if size > max_size => {
+                warn!("rule violated: max-size {}: {}", max_size, size);
+                Err(io::Error::from_raw_os_error(libc::EFBIG))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that an object can be renamed from 'from' to 'to'. Returns the new sharing
+    /// policy and mutability when a 'retype' rule applies.
+    pub fn check_rename(
+        &self,
+        from: &OsStr,
+        to: &OsStr,
+        components: (ObjectType, SharingPolicy, Mutability),
+    ) -> io::Result<Option<(SharingPolicy, Mutability)>> {
+        if self.add_anonymous {
+            warn!("rule violated: add-anonymous: rename {:?}", from);
+            return Err(io::Error::from_raw_os_error(libc::EPERM));
+        }
+        self.check_create(to, components)?;
+
+        Ok(self
+            .retype
+            .iter()
+            .find(|retype| retype.from.matches(from) && retype.to.matches(to))
+            .map(|retype| (retype.sharing_policy, retype.mutability)))
+    }
+
+    /// Returns true when the directory is a drop-box for anonymous objects.
+    pub fn is_dropbox(&self) -> bool {
+        self.add_anonymous
+    }
+
+    fn check_name(&self, name: &OsStr) -> io::Result<()> {
+        if let Some(glob) = self.reject.iter().find(|glob| glob.matches(name)) {
+            warn!("rule violated: reject {}: {:?}", glob.pattern, name);
+            Err(io::Error::from_raw_os_error(libc::EPERM))
+        } else if !self.accept.is_empty() && !self.accept.iter().any(|glob| glob.matches(name)) {
+            warn!("rule violated: not accepted: {:?}", name);
+            Err(io::Error::from_raw_os_error(libc::EPERM))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        if let Some(max_size) = self.max_size {
+            writeln!(f, "max-size {}", max_size)?;
+        }
+        match self.only {
+            Some(ObjectType::File) => writeln!(f, "only files")?,
+            Some(ObjectType::Directory) => writeln!(f, "only directories")?,
+            _ => {}
+        }
+        for (keyword, globs) in [("accept", &self.accept), ("reject", &self.reject)] {
+            if !globs.is_empty() {
+                write!(f, "{}", keyword)?;
+                for glob in globs {
+                    write!(f, " {}", glob.pattern)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        if self.add_anonymous {
+            writeln!(f, "add-anonymous")?;
+        }
+        for retype in &self.retype {
+            writeln!(
+                f,
+                "retype {} {} {} {}",
+                retype.from.pattern,
+                retype.to.pattern,
+                match retype.sharing_policy {
+                    SharingPolicy::Private => "private",
+                    SharingPolicy::PublicAcl => "public",
+                    _ => "anonymous",
+                },
+                match retype.mutability {
+                    Mutability::Mutable => "mutable",
+                    _ => "immutable",
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a size with an optional binary K/M/G/T suffix.
+fn parse_size(size: &str) -> Result<u64> {
+    let (number, shift) = match size.char_indices().last() {
+        Some((pos, 'K' | 'k')) => (&size[..pos], 10),
+        Some((pos, 'M' | 'm')) => (&size[..pos], 20),
+        Some((pos, 'G' | 'g')) => (&size[..pos], 30),
+        Some((pos, 'T' | 't')) => (&size[..pos], 40),
+        _ => (size, 0),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| ObjectStoreError::RuleSyntax(format!("invalid size '{}'", size)).into())
+}
+
+impl ObjectStore {
+    /// Returns the rules attached to a directory, 'None' when it has no rules.
+    pub fn directory_rules(&self, identifier: &Identifier) -> Result<Option<Rules>> {
+        identifier.ensure_dir()?;
+        match self.read_metadata(identifier, Meta::Rule)? {
+            Some(data) => Ok(Some(Rules::parse(std::str::from_utf8(&data)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the rules of the parent directory for creating a new entry.
+    pub(crate) fn rules_check_create(
+        &self,
+        sub_object: &SubObject,
+        components: (ObjectType, SharingPolicy, Mutability),
+    ) -> Result<()> {
+        if let Some(rules) = self.directory_rules(sub_object.0)? {
+            rules.check_create(sub_object.1, components)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the rules of the parent directory when a file was written and gets closed.
+    pub(crate) fn rules_check_close(&self, sub_object: &SubObject, size: u64) -> Result<()> {
+        if let Some(rules) = self.directory_rules(sub_object.0)? {
+            rules.check_size(size)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the rules of a directory for renaming an entry within it. Returns the sharing
+    /// policy and mutability the object shall be changed to when a 'retype' rule applies.
+    pub(crate) fn rules_check_rename(
+        &self,
+        directory: &Identifier,
+        from: &OsStr,
+        to: &OsStr,
+        identifier: &Identifier,
+    ) -> Result<Option<(SharingPolicy, Mutability)>> {
+        match self.directory_rules(directory)? {
+            Some(rules) => Ok(rules.check_rename(from, to, identifier.components())?),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates a new object of the kind a 'retype' rule demands from the content of
+    /// 'identifier' and returns it. The old object is left alone, the caller relinks the new
+    /// one in its place. Only files can be retyped. Every change of the sharing policy goes
+    /// through here and is checked by 'check_share()'.
+    pub(crate) fn retype(
+        &self,
+        identifier: &Identifier,
+        (sharing_policy, mutability): (SharingPolicy, Mutability),
+    ) -> Result<Identifier> {
+        self.check_share(identifier, sharing_policy)?;
+        if identifier.object_type() != ObjectType::File {
+            warn!("retype: {} is not a file", identifier);
+            return Err(ObjectStoreError::RetypeUnsupported(identifier.as_os_str().into()).into());
+        }
+
+        let builder = Object::build(ObjectType::File, sharing_policy, mutability);
+        let object = match (sharing_policy, mutability) {
+            (SharingPolicy::Private, Mutability::Immutable) => {
+                builder.snapshot_of(identifier).realize(self)?
+            }
+            (SharingPolicy::Anonymous | SharingPolicy::PublicAcl, Mutability::Immutable) => {
+                let source = self.openat_file(
+                    &identifier.to_pathbuf(),
+                    FileAccess::new().readonly().get(),
+                    0,
+                )?;
+                builder.source(source).realize(self)?
+            }
+            _ => {
+                warn!(
+                    "retype: can not convert {} to {:?} {:?}",
+                    identifier, sharing_policy, mutability
+                );
+                return Err(
+                    ObjectStoreError::RetypeUnsupported(identifier.as_os_str().into()).into(),
+                );
+            }
+        };
+
+        info!("retype: {} -> {}", identifier, object.identifier);
+        Ok(object.identifier)
+    }
+}
@@ -0,0 +1,120 @@
+//! Exporting objects.
+//!
+//! 'send' collects the objects to export, starting at an object and descending into
+//! directories up to the given depth. Private objects are only exported when asked for, below
+//! the starting object they are skipped otherwise. Directories holding special files are
+//! refused, these only make sense on the local node.
+//!
+//! Other nodes fetch shared objects by URL, 'uberallfs://HOST/IDENTIFIER'.
+// PLANNED: stream the objects to the node, until then send lists what would be exported
+// PLANNED: the port of the node belongs into the URL
+use std::ffi::{CStr, OsStr};
+use std::path::Path;
+
+use uberall::clap::ArgMatches;
+use uberall::libc;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::{Identifier, LockingMethod::*, ObjectStore};
+
+pub(crate) fn opt_send(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("ID_OR_PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+    let depth = matches
+        .value_of("recursive")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(0);
+
+    for identifier in
+        objectstore.export_objects(&identifier, depth, matches.is_present("private"))?
+    {
+        println!("{}", identifier);
+    }
+    Ok(())
+}
+
+impl ObjectStore {
+    /// Returns the objects exported by sending 'identifier', directories are descended
+    /// 'depth' levels. Fails when any of them can not be exported.
+    pub fn export_objects(
+        &self,
+        identifier: &Identifier,
+        depth: usize,
+        private: bool,
+    ) -> Result<Vec<Identifier>> {
+        if identifier.sharing_policy() == SharingPolicy::Private && !private {
+            return Err(ObjectStoreError::PrivateObject(identifier.as_os_str().into()).into());
+        }
+        let mut objects = Vec::new();
+        self.collect_exports(identifier, depth, private, &mut objects)?;
+        Ok(objects)
+    }
+
+    /// Returns the URL other nodes fetch 'identifier' from, private objects have none.
+    pub fn object_url(&self, identifier: &Identifier) -> Result<String> {
+        if identifier.sharing_policy() == SharingPolicy::Private {
+            return Err(ObjectStoreError::PrivateObject(identifier.as_os_str().into()).into());
+        }
+        Ok(format!("uberallfs://{}/{}", hostname()?, identifier))
+    }
+
+    /// Returns the object 'url' refers to, it must be present in this objectstore.
+    // PLANNED: fetch missing objects through the node
+    pub fn url_lookup(&self, url: &str) -> Result<Identifier> {
+        let id = url
+            .strip_prefix("uberallfs://")
+            .and_then(|rest| rest.splitn(2, '/').nth(1))
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| {
+                ObjectStoreError::InvalidIdentifier(format!("not an uberallfs URL: {}", url))
+            })?;
+        self.identifier_lookup(OsStr::new(id))
+    }
+
+    fn collect_exports(
+        &self,
+        identifier: &Identifier,
+        depth: usize,
+        private: bool,
+        objects: &mut Vec<Identifier>,
+    ) -> Result<()> {
+        if objects.contains(identifier) {
+            return Ok(());
+        }
+        self.check_exportable(identifier)?;
+        objects.push(identifier.clone());
+
+        if depth > 0 && identifier.object_type().is_directory() {
+            for entry in self.list_directory(identifier)? {
+                if entry.identifier().sharing_policy() == SharingPolicy::Private && !private {
+                    trace!("send: skipping private {}", entry.identifier());
+                    continue;
+                }
+                self.collect_exports(entry.identifier(), depth - 1, private, objects)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of this host.
+fn hostname() -> io::Result<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // truncated names are not guaranteed to be terminated
+    name[name.len() - 1] = 0;
+    Ok(
+        unsafe { CStr::from_ptr(name.as_ptr() as *const libc::c_char) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
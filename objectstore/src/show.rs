@@ -1,15 +1,38 @@
+//! Showing objects.
+//!
+//! Prints everything known about an object: the components of its identifier, how it would
+//! be deleted, the on-disk metadata of the object and its side files, a summary of the perm
+//! manifest and for directories the number of entries.
+//!
+//! The '--json' output is for tooling, its schema is kept stable. One object is printed as
+//! a JSON object:
+//!
+//!  * path:: the path as given or as found by '--recursive'
+//!  * id:: the identifier
+//!  * type, sharing, mutability:: the identifier components
+//!  * delete:: the delete method
+//!  * size, mtime, links:: the on-disk metadata of the object itself
+//!  * metadata:: the extensions of the present side files
+//!  * perm:: 'null' or an object with 'admins', 'quorum' and 'acls' (the permissions with
+//!    keys granted)
+//!  * entries:: 'null' or for directories the number of entries
+//!
+//! '--recursive --json' prints a JSON array of these. '--url' prints only the URL of the
+//! object, see 'send'.
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::ffi::OsStr;
+use std::fmt;
 
 use uberall::clap::ArgMatches;
 
 use crate::prelude::*;
-use crate::{LockingMethod::*, ObjectStore};
+use crate::identifier_kind::*;
+use crate::{Identifier, LockingMethod::*, Meta, Object, ObjectStore};
 
 pub(crate) fn opt_show(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
-    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
 
     let path = matches
         .value_of_os("PATH")
@@ -17,12 +40,230 @@ pub(crate) fn opt_show(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
 
     let (src, remaining) = objectstore.path_lookup(&path.map(PathBuf::from).unwrap(), None)?;
 
-    if remaining.as_os_str().is_empty() {
-        println!("{:?} -> {:?}", path.unwrap(), src);
-    } else {
+    if !remaining.as_os_str().is_empty() {
         println!("remaining {:?}", remaining);
 
         return Err(io::Error::from(io::ErrorKind::NotFound).into());
     }
+
+    if matches.is_present("url") {
+        println!("{}", objectstore.object_url(&src)?);
+        return Ok(());
+    }
+
+    let path = PathBuf::from(path.unwrap());
+    let json = matches.is_present("json");
+    if matches.is_present("recursive") {
+        let mut infos = Vec::new();
+        objectstore.show_tree(&mut vec![src], path, &mut infos)?;
+        if json {
+            let infos: Vec<String> = infos.iter().map(|(_, info)| info.to_json()).collect();
+            println!("[{}]", infos.join(","));
+        } else {
+            for (depth, info) in infos {
+                println!(
+                    "{:indent$}{} {} {:?}",
+                    "",
+                    info.path
+                        .file_name()
+                        .unwrap_or(info.path.as_os_str())
+                        .to_string_lossy(),
+                    info.identifier,
+                    info.object_type,
+                    indent = depth * 2
+                );
+            }
+        }
+    } else {
+        let info = objectstore.object_info(&src, path)?;
+        if json {
+            println!("{}", info.to_json());
+        } else {
+            print!("{}", info);
+        }
+    }
     Ok(())
 }
+
+/// Summary of a perm manifest
+#[derive(Debug)]
+pub struct PermSummary {
+    pub admins: usize,
+    pub quorum: usize,
+    /// The permissions granted to any key
+    pub acls:   Vec<String>,
+}
+
+/// Everything 'show' reports about an object
+#[derive(Debug)]
+pub struct ObjectInfo {
+    pub path:           PathBuf,
+    pub identifier:     Identifier,
+    pub object_type:    ObjectType,
+    pub sharing_policy: SharingPolicy,
+    pub mutability:     Mutability,
+    pub delete_method:  String,
+    pub size:           u64,
+    /// Seconds since the unix epoch
+    pub mtime:          i64,
+    pub links:          u64,
+    pub metadata:       Vec<Meta>,
+    pub perm:           Option<PermSummary>,
+    /// Number of entries of directories
+    pub entries:        Option<usize>,
+}
+
+impl fmt::Display for ObjectInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        writeln!(f, "{:?} -> {:?}", self.path, self.identifier)?;
+        writeln!(f, "id {}", self.identifier)?;
+        writeln!(f, "type {:?}", self.object_type)?;
+        writeln!(f, "sharing {:?}", self.sharing_policy)?;
+        writeln!(f, "mutability {:?}", self.mutability)?;
+        writeln!(f, "delete {}", self.delete_method)?;
+        writeln!(f, "size {}", self.size)?;
+        writeln!(f, "mtime {}", self.mtime)?;
+        writeln!(f, "links {}", self.links)?;
+        if !self.metadata.is_empty() {
+            let metadata: Vec<_> = self.metadata.iter().map(Meta::extension).collect();
+            writeln!(f, "metadata {}", metadata.join(" "))?;
+        }
+        if let Some(perm) = &self.perm {
+            writeln!(
+                f,
+                "perm admins {} quorum {} acls {}",
+                perm.admins,
+                perm.quorum,
+                perm.acls.join(" ")
+            )?;
+        }
+        if let Some(entries) = self.entries {
+            writeln!(f, "entries {}", entries)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectInfo {
+    /// Returns the JSON representation, see the module documentation for the schema.
+    pub fn to_json(&self) -> String {
+        let metadata: Vec<_> = self
+            .metadata
+            .iter()
+            .map(|meta| json_string(meta.extension()))
+            .collect();
+        let perm = match &self.perm {
+            Some(perm) => {
+                let acls: Vec<_> = perm.acls.iter().map(|acl| json_string(acl)).collect();
+                format!(
+                    r#"{{"admins":{},"quorum":{},"acls":[{}]}}"#,
+                    perm.admins,
+                    perm.quorum,
+                    acls.join(",")
+                )
+            }
+            None => String::from("null"),
+        };
+
+        format!(
+            concat!(
+                r#"{{"path":{},"id":{},"type":{},"sharing":{},"mutability":{},"delete":{},"#,
+                r#""size":{},"mtime":{},"links":{},"metadata":[{}],"perm":{},"entries":{}}}"#
+            ),
+            json_string(&self.path.to_string_lossy()),
+            json_string(&self.identifier.to_string()),
+            json_string(&format!("{:?}", self.object_type)),
+            json_string(&format!("{:?}", self.sharing_policy)),
+            json_string(&format!("{:?}", self.mutability)),
+            json_string(&self.delete_method),
+            self.size,
+            self.mtime,
+            self.links,
+            metadata.join(","),
+            perm,
+            self.entries
+                .map_or_else(|| String::from("null"), |entries| entries.to_string())
+        )
+    }
+}
+
+/// Quotes and escapes a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl ObjectStore {
+    /// Collects the information 'show' prints about an object, 'path' is only recorded.
+    pub fn object_info(&self, identifier: &Identifier, path: PathBuf) -> Result<ObjectInfo> {
+        let (object_type, sharing_policy, mutability) = identifier.components();
+        let stat = *self.object_metadata(identifier)?.stat();
+
+        let perm = self.perm_manifest(identifier)?.map(|manifest| PermSummary {
+            admins: manifest.admins().len(),
+            quorum: manifest.quorum(),
+            acls:   manifest.permissions().map(String::from).collect(),
+        });
+
+        let entries = if object_type.is_directory() {
+            Some(self.list_directory(identifier)?.count())
+        } else {
+            None
+        };
+
+        Ok(ObjectInfo {
+            path,
+            identifier: identifier.clone(),
+            object_type,
+            sharing_policy,
+            mutability,
+            delete_method: Object::from(identifier.clone()).delete_method().to_string(),
+            size: stat.st_size as u64,
+            mtime: stat.st_mtime,
+            links: stat.st_nlink as u64,
+            metadata: self.present_metadata(identifier),
+            perm,
+            entries,
+        })
+    }
+
+    /// Collects the object infos of a directory tree with their depth. Directories already
+    /// on the way are not entered again.
+    fn show_tree(
+        &self,
+        ancestors: &mut Vec<Identifier>,
+        path: PathBuf,
+        infos: &mut Vec<(usize, ObjectInfo)>,
+    ) -> Result<()> {
+        let identifier = ancestors.last().unwrap().clone();
+        infos.push((
+            ancestors.len() - 1,
+            self.object_info(&identifier, path.clone())?,
+        ));
+        if !identifier.object_type().is_directory() {
+            return Ok(());
+        }
+
+        let mut entries: Vec<_> = self.list_directory(&identifier)?.collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for entry in entries {
+            if ancestors.contains(entry.identifier()) {
+                continue;
+            }
+            ancestors.push(entry.identifier().clone());
+            self.show_tree(ancestors, path.join(entry.name()), infos)?;
+            ancestors.pop();
+        }
+        Ok(())
+    }
+}
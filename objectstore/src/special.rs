@@ -0,0 +1,171 @@
+//! Unix special files.
+//!
+//! Private directories may store fifos, sockets and device nodes. They are plain host special
+//! files next to the identifier links and have no identity of their own. They only make sense
+//! on the local node, thus directories holding them can not be exported or shared.
+use std::ffi::{CString, OsStr, OsString};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+use openat_ct as openat;
+use openat::{Entry, SimpleType};
+use uberall::clap::ArgMatches;
+use uberall::libc;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectstore::is_reserved;
+use crate::{Identifier, LockingMethod::*, ObjectStore, SubObject};
+
+pub(crate) fn opt_mknod(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").map(PathBuf::from).unwrap();
+    let (parent, remaining) = objectstore.path_lookup(&path, None)?;
+
+    let mut components = remaining.components();
+    let name = match (components.next(), components.next()) {
+        (Some(name), None) => name.as_os_str(),
+        (None, _) => return Err(io::Error::from(io::ErrorKind::AlreadyExists).into()),
+        (Some(name), Some(_)) => {
+            warn!("Parent dir missing: {:?}", name);
+            return Err(ObjectStoreError::ObjectNotFound(name.as_os_str().into()).into());
+        }
+    };
+
+    let device = || -> Result<libc::dev_t> {
+        match (matches.value_of("MAJOR"), matches.value_of("MINOR")) {
+            (Some(major), Some(minor)) => Ok(libc::makedev(major.parse()?, minor.parse()?)),
+            _ => Err(ObjectStoreError::OptArgError(String::from(
+                "device nodes need MAJOR and MINOR",
+            ))
+            .into()),
+        }
+    };
+
+    let special = match matches.value_of("TYPE").unwrap() {
+        "p" => SpecialFile::Fifo,
+        "s" => SpecialFile::Socket,
+        "c" => SpecialFile::CharDevice(device()?),
+        "b" => SpecialFile::BlockDevice(device()?),
+        _ => unreachable!("checked by clap"),
+    };
+
+    objectstore.create_special(&SubObject(&parent, name), special, 0o600)
+}
+
+/// The kinds of special files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecialFile {
+    Fifo,
+    Socket,
+    CharDevice(libc::dev_t),
+    BlockDevice(libc::dev_t),
+}
+
+impl SpecialFile {
+    /// Classifies a file mode and device number, 'None' for anything that is not a special
+    /// file.
+    pub fn from_mode(mode: libc::mode_t, rdev: libc::dev_t) -> Option<SpecialFile> {
+        match mode & libc::S_IFMT {
+            libc::S_IFIFO => Some(SpecialFile::Fifo),
+            libc::S_IFSOCK => Some(SpecialFile::Socket),
+            libc::S_IFCHR => Some(SpecialFile::CharDevice(rdev)),
+            libc::S_IFBLK => Some(SpecialFile::BlockDevice(rdev)),
+            _ => None,
+        }
+    }
+
+    /// The file type bits for mknod()
+    fn file_type(&self) -> libc::mode_t {
+        match self {
+            SpecialFile::Fifo => libc::S_IFIFO,
+            SpecialFile::Socket => libc::S_IFSOCK,
+            SpecialFile::CharDevice(_) => libc::S_IFCHR,
+            SpecialFile::BlockDevice(_) => libc::S_IFBLK,
+        }
+    }
+
+    fn rdev(&self) -> libc::dev_t {
+        match self {
+            SpecialFile::CharDevice(rdev) | SpecialFile::BlockDevice(rdev) => *rdev,
+            _ => 0,
+        }
+    }
+}
+
+impl ObjectStore {
+    /// Creates a special file in a private directory.
+    pub(crate) fn create_special(
+        &self,
+        sub_object: &SubObject,
+        special: SpecialFile,
+        permissions: libc::mode_t,
+    ) -> Result<()> {
+        sub_object.0.ensure_dir()?;
+
+        if is_reserved(sub_object.1) {
+            warn!("mknod: illegal file name: {:?}", sub_object.1);
+            return Err(ObjectStoreError::IllegalFileName(sub_object.1.into()).into());
+        }
+        if sub_object.0.sharing_policy() != SharingPolicy::Private {
+            warn!("mknod: {} is not private", sub_object.0);
+            return Err(ObjectStoreError::SpecialFile(sub_object.1.into()).into());
+        }
+        // special files count as files for the rules
+        self.rules_check_create(
+            sub_object,
+            (
+                ObjectType::File,
+                SharingPolicy::Private,
+                Mutability::Mutable,
+            ),
+        )?;
+
+        let path = sub_object.to_pathbuf();
+        trace!("mknod: {:?} {:?}", path.as_os_str(), special);
+        let cpath = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe {
+            libc::mknodat(
+                self.objects.as_raw_fd(),
+                cpath.as_ptr(),
+                special.file_type() | (permissions & 0o7777),
+                special.rdev(),
+            )
+        } == -1
+        {
+            return Err(io::Error::last_os_error().into());
+        }
+        self.snapshot_on_change(sub_object.0)
+    }
+
+    /// Returns the names of the special files in a directory.
+    pub fn special_files(&self, identifier: &Identifier) -> Result<Vec<OsString>> {
+        let dir = self.open_directory(identifier)?;
+        let mut names = Vec::new();
+        for entry in dir.list_self()? {
+            if let Entry {
+                name,
+                file_type: Some(SimpleType::Other),
+                ..
+            } = entry?
+            {
+                names.push(OsStr::from_bytes(name.to_bytes()).into());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Checks that an object can be exported, directories holding special files can not.
+    pub fn check_exportable(&self, identifier: &Identifier) -> Result<()> {
+        if identifier.object_type().is_directory() {
+            if let Some(name) = self.special_files(identifier)?.into_iter().next() {
+                warn!("{} holds the special file {:?}", identifier, name);
+                return Err(ObjectStoreError::SpecialFile(name).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+//! Summary of the state of an objectstore.
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::{Identifier, LockingMethod::*, ObjectStore};
+
+pub(crate) fn opt_status(dir: &OsStr, _matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open_shared(dir.as_ref(), WaitForLock)?;
+    print!("{}", objectstore.status()?);
+    Ok(())
+}
+
+/// Version, root and object counts of an objectstore
+#[derive(Debug)]
+pub struct StoreStatus {
+    pub version: u32,
+    pub root:    Option<Identifier>,
+    /// Number of objects by ObjectType
+    pub objects: BTreeMap<String, usize>,
+}
+
+impl fmt::Display for StoreStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        writeln!(f, "version {}", self.version)?;
+        if let Some(root) = &self.root {
+            writeln!(f, "root {}", root)?;
+        }
+        writeln!(f, "objects {}", self.objects.values().sum::<usize>())?;
+        for (object_type, count) in &self.objects {
+            writeln!(f, "objects {} {}", object_type, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore {
+    /// Collects the status of the objectstore, this iterates over all objects.
+    pub fn status(&self) -> Result<StoreStatus> {
+        let mut objects = BTreeMap::new();
+        for identifier in self.all_objects() {
+            *objects
+                .entry(format!("{:?}", identifier.object_type()))
+                .or_insert(0) += 1;
+        }
+
+        Ok(StoreStatus {
+            version: self.version,
+            root: self.get_root_id().ok(),
+            objects,
+        })
+    }
+}
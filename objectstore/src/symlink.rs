@@ -0,0 +1,149 @@
+//! User symlinks.
+//!
+//! Directories store user symlinks as plain symlinks next to their identifier links.
+//! Identifier links always point to '.uberallfs./ID', user symlinks must never use this
+//! prefix. Since directory objects have no implicit parent, absolute targets and targets
+//! leaving the directory with '..' are only allowed in private directories. Directories with
+//! parent are the exception, '..' may cross from them into their declared parent.
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use openat_ct as openat;
+use openat::{Entry, Metadata, SimpleType};
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectstore::is_reserved;
+use crate::{Identifier, LockingMethod::*, ObjectStore, SubObject};
+
+pub(crate) fn opt_symlink(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = ObjectStore::open(dir.as_ref(), WaitForLock)?;
+
+    let path = matches.value_of_os("PATH").map(PathBuf::from).unwrap();
+    let (parent, remaining) = objectstore.path_lookup(&path, None)?;
+
+    let mut components = remaining.components();
+    let name = match (components.next(), components.next()) {
+        (Some(name), None) => name.as_os_str(),
+        (None, _) => return Err(io::Error::from(io::ErrorKind::AlreadyExists).into()),
+        (Some(name), Some(_)) => {
+            warn!("Parent dir missing: {:?}", name);
+            return Err(ObjectStoreError::ObjectNotFound(name.as_os_str().into()).into());
+        }
+    };
+
+    objectstore.create_symlink(
+        &SubObject(&parent, name),
+        Path::new(matches.value_of_os("TARGET").unwrap()),
+    )
+}
+
+impl ObjectStore {
+    /// Returns the target of a user symlink in a directory, 'None' for identifier links.
+    pub fn read_symlink(&self, sub_object: &SubObject) -> Result<Option<PathBuf>> {
+        sub_object.0.ensure_dir()?;
+        let target = self.objects.read_link(&sub_object.to_pathbuf())?;
+        Ok(if is_reserved(target.as_os_str()) {
+            None
+        } else {
+            Some(target)
+        })
+    }
+
+    /// Returns the underlying metadata of a directory entry itself, symlinks are not followed.
+    pub fn entry_metadata(&self, sub_object: &SubObject) -> io::Result<Metadata> {
+        self.objects.metadata(&sub_object.to_pathbuf())
+    }
+
+    /// Creates a user symlink in a directory.
+    pub(crate) fn create_symlink(&self, sub_object: &SubObject, target: &Path) -> Result<()> {
+        sub_object.0.ensure_dir()?;
+
+        if is_reserved(sub_object.1) {
+            warn!("symlink: illegal file name: {:?}", sub_object.1);
+            return Err(ObjectStoreError::IllegalFileName(sub_object.1.into()).into());
+        }
+        if target.as_os_str().is_empty() || is_reserved(target.as_os_str()) {
+            warn!("symlink: illegal target: {:?}", target);
+            return Err(ObjectStoreError::IllegalFileName(target.as_os_str().into()).into());
+        }
+        if sub_object.0.sharing_policy() != SharingPolicy::Private {
+            self.check_symlink_escape(sub_object.0, target)?;
+        }
+        // symlinks count as files for the rules
+        self.rules_check_create(
+            sub_object,
+            (
+                ObjectType::File,
+                sub_object.0.sharing_policy(),
+                Mutability::Mutable,
+            ),
+        )?;
+
+        let source = sub_object.to_pathbuf();
+        trace!("symlink: {:?} -> {:?}", source.as_os_str(), target);
+        self.objects.symlink(source.as_os_str(), target)?;
+        self.snapshot_on_change(sub_object.0)
+    }
+
+    /// Checks that a directory can be shared with 'sharing_policy'. Private directories may
+    /// hold symlinks escaping them and special files, these can not be shared.
+    pub(crate) fn check_share(
+        &self,
+        identifier: &Identifier,
+        sharing_policy: SharingPolicy,
+    ) -> Result<()> {
+        if !identifier.object_type().is_directory() || sharing_policy == SharingPolicy::Private {
+            return Ok(());
+        }
+        self.check_exportable(identifier)?;
+
+        let dir = self.open_directory(identifier)?;
+        for entry in dir.list_self()? {
+            if let Entry {
+                name,
+                file_type: Some(SimpleType::Symlink),
+                ..
+            } = entry?
+            {
+                let target = dir.read_link(OsStr::from_bytes(name.to_bytes()))?;
+                if !is_reserved(target.as_os_str()) {
+                    self.check_symlink_escape(identifier, &target)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that 'target' stays within 'directory'. Leaving with '..' is only allowed from
+    /// directories with parent towards their declared parent.
+    fn check_symlink_escape(&self, directory: &Identifier, target: &Path) -> Result<()> {
+        let escape = || {
+            warn!("symlink escapes {}: {:?}", directory, target);
+            Err(ObjectStoreError::SymlinkEscape(target.as_os_str().into()).into())
+        };
+
+        if target.is_absolute() {
+            return escape();
+        }
+
+        // number of components below 'current'
+        let mut depth = 0usize;
+        let mut current = directory.clone();
+        for component in target.components() {
+            match component {
+                Component::ParentDir if depth > 0 => depth -= 1,
+                Component::ParentDir => match self.declared_parent(&current)? {
+                    Some(parent) => current = parent,
+                    None => return escape(),
+                },
+                Component::Normal(_) => depth += 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! Versioned objects.
+//!
+//! Versioned objects keep their prior revisions as immutable private snapshots. A snapshot is
+//! taken after every change of a versioned directory and when a versioned file which was
+//! opened for writing gets closed. Snapshots are chained by 'meta' metadata:
+//!
+//!  * head ID:: on the versioned object, the newest snapshot
+//!  * version N:: on the versioned object, the number of the newest snapshot
+//!  * previous ID:: on a snapshot, the snapshot taken before
+//!  * number N:: on a snapshot, its number, these count from 1 and are never reused
+//!  * time SECONDS:: on a snapshot, when it was taken
+//!
+//! Snapshots of directories are shallow, they link to the same objects the directory linked
+//! to when the snapshot was taken.
+//!
+//! The retention policy is stored on the versioned object too. 'retain-count N' keeps the N
+//! newest snapshots, 'retain-days DAYS' keeps snapshots younger than DAYS. Garbage collection
+//! only keeps snapshots that satisfy the policy, without a policy all snapshots are kept.
+use std::ffi::OsStr;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use openat_ct as openat;
+use openat::{Entry, SimpleType};
+use uberall::clap::ArgMatches;
+use uberall::libc;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::object::Object;
+use crate::objectstore::{FileAccess, FilePermissions};
+use crate::revocation::now;
+use crate::{DirectoryPermissions, Handle, Identifier, LockingMethod::*, ObjectStore};
+
+pub(crate) fn opt_versions(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let changes = ["COUNT", "DAYS", "snapshot"]
+        .iter()
+        .any(|arg| matches.is_present(arg));
+    let objectstore = if changes {
+        ObjectStore::open(dir.as_ref(), WaitForLock)?
+    } else {
+        ObjectStore::open_shared(dir.as_ref(), WaitForLock)?
+    };
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+
+    if matches.is_present("COUNT") || matches.is_present("DAYS") {
+        let mut retention = objectstore.retention(&identifier)?;
+        if let Some(count) = matches.value_of("COUNT") {
+            retention.count = Some(count.parse()?);
+        }
+        if let Some(days) = matches.value_of("DAYS") {
+            retention.days = Some(days.parse()?);
+        }
+        objectstore.set_retention(&identifier, retention)?;
+    }
+
+    if matches.is_present("snapshot") {
+        objectstore.snapshot(&identifier)?;
+    }
+
+    if let Some(number) = matches.value_of("NUMBER") {
+        let number = number.parse()?;
+        match objectstore.open_version(&identifier, number)? {
+            Handle::File(mut file) => {
+                std::io::copy(&mut file, &mut std::io::stdout())?;
+            }
+            _ => {
+                let snapshot = objectstore.version(&identifier, number)?;
+                for entry in objectstore.list_directory(&snapshot)? {
+                    println!("{:?}", entry.name());
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    for version in objectstore.versions(&identifier)? {
+        println!("{} {} {}", version.number, version.identifier, version.time);
+    }
+    Ok(())
+}
+
+/// A snapshot of a versioned object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    /// Counts from 1 for the first snapshot ever taken, stays the same when older snapshots
+    /// expire
+    pub number:     usize,
+    pub identifier: Identifier,
+    /// Seconds since epoch when the snapshot was taken
+    pub time:       u64,
+}
+
+/// How long snapshots of a versioned object are kept
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Keep only this many of the newest snapshots
+    pub count: Option<usize>,
+    /// Keep only snapshots younger than this
+    pub days:  Option<u64>,
+}
+
+impl Retention {
+    /// Returns true when the snapshot taken at 'time', being the 'newest'th newest (counting
+    /// from 0), shall be kept.
+    pub fn retains(&self, newest: usize, time: u64, now: u64) -> bool {
+        self.count.map_or(true, |count| newest < count)
+            && self
+                .days
+                .map_or(true, |days| now.saturating_sub(time) < days * 86400)
+    }
+}
+
+impl ObjectStore {
+    /// Takes a snapshot of a versioned object. Returns the identifier of the new snapshot.
+    pub fn snapshot(&self, identifier: &Identifier) -> Result<Identifier> {
+        ensure_versioned(identifier)?;
+
+        let snapshot = Object::build(
+            identifier.object_type(),
+            SharingPolicy::Private,
+            Mutability::Immutable,
+        )
+        .snapshot_of(identifier)
+        .realize(self)?
+        .identifier;
+
+        let mut meta = self.object_meta(identifier)?;
+        let mut snapshot_meta = self.object_meta(&snapshot)?;
+        if let Some(head) = meta.get("head") {
+            snapshot_meta.set("previous", head);
+        }
+        let number = meta
+            .get("version")
+            .map(str::parse::<usize>)
+            .transpose()?
+            .unwrap_or(0)
+            + 1;
+        snapshot_meta.set("number", number);
+        snapshot_meta.set("time", now());
+        self.write_object_meta(&snapshot, &snapshot_meta)?;
+
+        meta.set("head", &snapshot);
+        meta.set("version", number);
+        self.write_object_meta(identifier, &meta)?;
+
+        info!("snapshot {} of {}: {}", number, identifier, snapshot);
+        Ok(snapshot)
+    }
+
+    /// Takes a snapshot of 'identifier' when it is versioned, to be called after it changed.
+    pub(crate) fn snapshot_on_change(&self, identifier: &Identifier) -> Result<()> {
+        if identifier.mutability() == Mutability::Versioned {
+            self.snapshot(identifier)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the existing snapshots of a versioned object, oldest first.
+    pub fn versions(&self, identifier: &Identifier) -> Result<Vec<Version>> {
+        ensure_versioned(identifier)?;
+
+        let mut versions = Vec::new();
+        let mut next = self.object_meta(identifier)?.get("head").map(String::from);
+        while let Some(flipbase64) = next {
+            let snapshot = Identifier::from_filename(Path::new(&flipbase64))?;
+            // older snapshots may have been removed by the garbage collector
+            if self.object_metadata(&snapshot).is_err() {
+                break;
+            }
+            let meta = self.object_meta(&snapshot)?;
+            versions.push(Version {
+                number:     meta
+                    .get("number")
+                    .and_then(|number| number.parse().ok())
+                    .unwrap_or(0),
+                identifier: snapshot,
+                time:       meta
+                    .get("time")
+                    .and_then(|time| time.parse().ok())
+                    .unwrap_or(0),
+            });
+            next = meta.get("previous").map(String::from);
+        }
+
+        versions.reverse();
+        Ok(versions)
+    }
+
+    /// Returns the identifier of snapshot 'number' of a versioned object.
+    pub fn version(&self, identifier: &Identifier, number: usize) -> Result<Identifier> {
+        self.versions(identifier)?
+            .into_iter()
+            .find(|version| version.number == number)
+            .map(|version| version.identifier)
+            .ok_or_else(|| {
+                ObjectStoreError::ObjectNotFound(
+                    format!("{} version {}", identifier, number).into(),
+                )
+                .into()
+            })
+    }
+
+    /// Opens snapshot 'number' of a versioned object for reading.
+    pub fn open_version(&self, identifier: &Identifier, number: usize) -> Result<Handle> {
+        let snapshot = self.version(identifier, number)?;
+        match snapshot.object_type() {
+            ObjectType::Directory => Ok(Handle::Dir(self.open_directory(&snapshot)?)),
+            _ => Ok(Handle::File(self.openat_file(
+                &snapshot.to_pathbuf(),
+                FileAccess::new().readonly().get(),
+                0,
+            )?)),
+        }
+    }
+
+    /// Returns the retention policy of a versioned object.
+    pub fn retention(&self, identifier: &Identifier) -> Result<Retention> {
+        ensure_versioned(identifier)?;
+        let meta = self.object_meta(identifier)?;
+        Ok(Retention {
+            count: meta.get("retain-count").map(str::parse).transpose()?,
+            days:  meta.get("retain-days").map(str::parse).transpose()?,
+        })
+    }
+
+    /// Sets the retention policy of a versioned object.
+    pub(crate) fn set_retention(
+        &self,
+        identifier: &Identifier,
+        retention: Retention,
+    ) -> Result<()> {
+        ensure_versioned(identifier)?;
+        let mut meta = self.object_meta(identifier)?;
+        for (key, value) in [
+            ("retain-count", retention.count.map(|count| count as u64)),
+            ("retain-days", retention.days),
+        ] {
+            match value {
+                Some(value) => meta.set(key, value),
+                None => {
+                    meta.remove(key);
+                }
+            }
+        }
+        self.write_object_meta(identifier, &meta)
+    }
+
+    /// Returns the snapshots of a versioned object which are kept by its retention policy.
+    pub fn retained_versions(&self, identifier: &Identifier) -> Result<Vec<Identifier>> {
+        let retention = self.retention(identifier)?;
+        let now = now();
+        Ok(self
+            .versions(identifier)?
+            .into_iter()
+            .rev()
+            .enumerate()
+            .filter(|(newest, version)| retention.retains(*newest, version.time, now))
+            .map(|(_, version)| version.identifier)
+            .collect())
+    }
+
+    /// Copies the current content of 'from' into the new object 'to'.
+    pub(crate) fn copy_snapshot(&self, from: &Identifier, to: &Identifier) -> Result<()> {
+        match from.object_type() {
+            ObjectType::File => {
+                let mut source =
+                    self.openat_file(&from.to_pathbuf(), FileAccess::new().readonly().get(), 0)?;
+                let tmp = PathBuf::from("tmp").join(to.as_os_str());
+                let mut file = self.openat_file(
+                    &tmp,
+                    FileAccess::new()
+                        .writeonly()
+                        .extra_flags(libc::O_CREAT | libc::O_EXCL)
+                        .get(),
+                    FilePermissions::new().read().get(),
+                )?;
+                std::io::copy(&mut source, &mut file)?;
+                file.flush()?;
+                file.sync_all()?;
+                drop(file);
+                Ok(self.objects.local_rename(&tmp, &to.to_pathbuf())?)
+            }
+            ObjectType::Directory => {
+                self.create_directory(to, DirectoryPermissions::new().full())?;
+                let source = self.open_directory(from)?;
+                let dir = self.open_directory(to)?;
+                for entry in source.list_self()? {
+                    if let Entry {
+                        name,
+                        file_type: Some(SimpleType::Symlink),
+                        ..
+                    } = entry?
+                    {
+                        let name = OsStr::from_bytes(name.to_bytes());
+                        dir.symlink(name, source.read_link(name)?)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(ObjectStoreError::UnsupportedObjectType(from.components()).into()),
+        }
+    }
+}
+
+fn ensure_versioned(identifier: &Identifier) -> Result<()> {
+    if identifier.mutability() == Mutability::Versioned {
+        Ok(())
+    } else {
+        Err(ObjectStoreError::NotVersioned(identifier.as_os_str().into()).into())
+    }
+}
@@ -1,6 +1,7 @@
 use std::ffi::OsStr;
+use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use openat_ct as openat;
@@ -8,9 +9,11 @@ use openat::Metadata;
 use uberall::libc;
 
 use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectstore::FileAccess;
 use crate::{
-    Identifier, LockingMethod::*, ObjectStore, PermissionCheck, PermissionController, SubObject,
-    UserId,
+    Handle, Identifier, LockMode, LockingMethod::*, Object, ObjectStore, PermissionCheck,
+    PermissionController, SpecialFile, SubObject, UserId, Version,
 };
 
 /// Filesystem alike access layer to the objectstore. Does access checks based
@@ -20,20 +23,60 @@ use crate::{
 #[derive(Debug)]
 pub struct VirtualFileSystem {
     objectstore:           Arc<ObjectStore>,
-    permission_controller: PermissionController,
+    permission_controller: Arc<PermissionController>,
+    /// When offline, the errno for operations that need remote data
+    offline:               Option<libc::c_int>,
 }
 
 #[cfg(unix)]
 impl VirtualFileSystem {
     pub fn new(dir: &Path) -> Result<VirtualFileSystem> {
-        let objectstore = Arc::new(ObjectStore::open(dir, WaitForLock)?);
-        let permission_controller = PermissionController::new(objectstore.clone());
+        let objectstore = Arc::new(ObjectStore::open_with(dir, LockMode::Daemon, WaitForLock)?);
+        let permission_controller = Arc::new(PermissionController::new(objectstore.clone())?);
         Ok(Self {
             objectstore,
             permission_controller,
+            offline: None,
         })
     }
 
+    /// Switches to offline mode, operations that need remote data then fail with 'errno'
+    /// instead of waiting for the network. 'None' goes online again.
+    pub fn set_offline(&mut self, errno: Option<libc::c_int>) {
+        info!("offline: {:?}", errno);
+        self.offline = errno;
+    }
+
+    /// Returns the errno remote operations fail with when offline.
+    pub fn offline(&self) -> Option<libc::c_int> {
+        self.offline
+    }
+
+    /// Returns the error for an object which is not available locally. Private objects only
+    /// exist here, others would need to be fetched from the network.
+    fn remote(&self, identifier: &Identifier, err: io::Error) -> io::Error {
+        match (identifier.sharing_policy(), self.offline) {
+            (SharingPolicy::Private, _) => err,
+            (_, Some(errno)) => {
+                debug!("offline, not fetching {}", identifier);
+                io::Error::from_raw_os_error(errno)
+            }
+            // PLANNED: fetch the object through the node
+            (_, None) => err,
+        }
+    }
+
+    /// Returns the underlying objectstore, for administrative access which bypasses the
+    /// permission checks.
+    pub fn objectstore(&self) -> Arc<ObjectStore> {
+        Arc::clone(&self.objectstore)
+    }
+
+    /// Returns the PermissionController, keys get authenticated there.
+    pub fn permission_controller(&self) -> Arc<PermissionController> {
+        Arc::clone(&self.permission_controller)
+    }
+
     /// Request a permission check on an object.
     #[inline]
     fn permission_check<'a>(
@@ -51,6 +94,29 @@ impl VirtualFileSystem {
         Ok(identifier)
     }
 
+    /// resolves the given path to an identifier and the directories leading to it, these
+    /// are the real parents when the path contains '..' or cycles.
+    pub fn path_lookup_with_parents(
+        &self,
+        uid: UserId,
+        path: &Path,
+    ) -> Result<(Identifier, Vec<Identifier>)> {
+        let mut parents = Vec::new();
+        let identifier = self
+            .objectstore
+            .path_lookup(path, Some(&mut parents))
+            .map(|t| t.0)?;
+        self.permission_check(&identifier, Some(uid)).list()?;
+        Ok((identifier, parents))
+    }
+
+    /// resolves the root to mount, see 'ObjectStore::mount_root()'.
+    pub fn mount_root(&self, uid: UserId, root: &OsStr) -> Result<Identifier> {
+        let identifier = self.objectstore.mount_root(root)?;
+        self.permission_check(&identifier, Some(uid)).list()?;
+        Ok(identifier)
+    }
+
     /// the vfs layer does access checks only against the authenticated user id.
     /// There is no concept of real or effective uid's and no groups.
     #[inline]
@@ -86,6 +152,316 @@ impl VirtualFileSystem {
     #[inline]
     pub fn metadata(&self, _uid: UserId, identifier: &Identifier) -> io::Result<Metadata> {
         // TODO: permission checks against keys
-        Ok(self.objectstore.object_metadata(identifier)?)
+        match self.objectstore.object_metadata(identifier) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(self.remote(identifier, err)),
+            result => result,
+        }
+    }
+
+    /// Creates a new private directory 'name' within 'parent'.
+    pub fn mkdir(&self, uid: UserId, parent: &Identifier, name: &OsStr) -> Result<Identifier> {
+        self.permission_check(parent, Some(uid)).add()?;
+
+        let sub_object = SubObject(parent, name);
+        self.objectstore.rules_check_create(
+            &sub_object,
+            (
+                ObjectType::Directory,
+                SharingPolicy::Private,
+                Mutability::Mutable,
+            ),
+        )?;
+
+        let object = Object::build(
+            ObjectType::Directory,
+            SharingPolicy::Private,
+            Mutability::Mutable,
+        )
+        .realize(&self.objectstore)?;
+        self.objectstore
+            .create_link(&object.identifier, sub_object)?;
+
+        Ok(object.identifier)
+    }
+
+    /// Returns true when 'directory' is a drop-box, files created there become anonymous
+    /// immutable objects, see 'create_anonymous()'.
+    pub fn is_dropbox(&self, directory: &Identifier) -> bool {
+        self.permission_controller.is_dropbox(directory)
+    }
+
+    /// Starts adding the file 'name' to the drop-box 'parent'. Returns an unnamed staging file
+    /// which is written and then passed to 'add_anonymous()', anonymous objects can not change
+    /// once they exist.
+    pub fn create_anonymous(&self, uid: UserId, parent: &Identifier, name: &OsStr) -> Result<File> {
+        self.permission_check(parent, Some(uid)).add_anonymous()?;
+
+        let sub_object = SubObject(parent, name);
+        self.objectstore.rules_check_create(
+            &sub_object,
+            (
+                ObjectType::File,
+                SharingPolicy::Anonymous,
+                Mutability::Immutable,
+            ),
+        )?;
+        if self.objectstore.entry_exists(&sub_object)? {
+            return Err(ObjectStoreError::ObjectExists(name.into()).into());
+        }
+
+        Ok(self.objectstore.openat_file(
+            Path::new("tmp"),
+            FileAccess::new()
+                .readwrite()
+                .extra_flags(libc::O_TMPFILE)
+                .get(),
+            0o600,
+        )?)
+    }
+
+    /// Adds the content of 'source' as anonymous immutable file 'name' to 'parent'. Drop-box
+    /// directories accept this from anyone.
+    pub fn add_anonymous(
+        &self,
+        uid: Option<UserId>,
+        parent: &Identifier,
+        name: &OsStr,
+        source: File,
+    ) -> Result<Identifier> {
+        self.permission_check(parent, uid).add_anonymous()?;
+
+        let sub_object = SubObject(parent, name);
+        self.objectstore.rules_check_create(
+            &sub_object,
+            (
+                ObjectType::File,
+                SharingPolicy::Anonymous,
+                Mutability::Immutable,
+            ),
+        )?;
+        self.objectstore
+            .rules_check_close(&sub_object, source.metadata()?.len())?;
+
+        let object = Object::build(
+            ObjectType::File,
+            SharingPolicy::Anonymous,
+            Mutability::Immutable,
+        )
+        .source(source)
+        .realize(&self.objectstore)?;
+        self.objectstore
+            .create_link(&object.identifier, sub_object)?;
+
+        Ok(object.identifier)
+    }
+
+    /// Creates a new private file 'name' within 'parent'.
+    pub fn create(&self, uid: UserId, parent: &Identifier, name: &OsStr) -> Result<Identifier> {
+        self.permission_check(parent, Some(uid)).add()?;
+
+        // files in versioned directories are versioned too
+        let mutability = match parent.mutability() {
+            Mutability::Versioned => Mutability::Versioned,
+            _ => Mutability::Mutable,
+        };
+        let sub_object = SubObject(parent, name);
+        self.objectstore.rules_check_create(
+            &sub_object,
+            (ObjectType::File, SharingPolicy::Private, mutability),
+        )?;
+
+        let object = Object::build(ObjectType::File, SharingPolicy::Private, mutability)
+            .realize(&self.objectstore)?;
+        self.objectstore
+            .create_link(&object.identifier, sub_object)?;
+
+        Ok(object.identifier)
+    }
+
+    /// Opens the content of a file object, 'flags' are the open flags of the request.
+    pub fn open(&self, uid: UserId, identifier: &Identifier, flags: libc::c_int) -> Result<Handle> {
+        let check = self.permission_check(identifier, Some(uid));
+        let access = match flags & libc::O_ACCMODE {
+            libc::O_RDONLY => {
+                check.read()?;
+                FileAccess::new().readonly()
+            }
+            libc::O_WRONLY => {
+                check.write()?;
+                FileAccess::new().writeonly()
+            }
+            _ => {
+                check.read()?;
+                check.write()?;
+                FileAccess::new().readwrite()
+            }
+        };
+        let access = if flags & libc::O_APPEND != 0 {
+            access.append()
+        } else {
+            access
+        };
+        let access = if flags & libc::O_TRUNC != 0 {
+            access.extra_flags(libc::O_TRUNC)
+        } else {
+            access
+        };
+
+        match self.objectstore.open_file(identifier, access) {
+            Err(err)
+                if err.downcast_ref::<io::Error>().map(io::Error::kind)
+                    == Some(io::ErrorKind::NotFound) =>
+            {
+                Err(self
+                    .remote(identifier, io::Error::from(io::ErrorKind::NotFound))
+                    .into())
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the target of the user symlink 'name' in 'parent'.
+    pub fn readlink(&self, uid: UserId, parent: &Identifier, name: &OsStr) -> Result<PathBuf> {
+        self.permission_check(parent, Some(uid)).list()?;
+        self.objectstore
+            .read_symlink(&SubObject(parent, name))?
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL).into())
+    }
+
+    /// Creates the user symlink 'name' in 'parent'.
+    pub fn symlink(
+        &self,
+        uid: UserId,
+        parent: &Identifier,
+        name: &OsStr,
+        target: &Path,
+    ) -> Result<()> {
+        self.permission_check(parent, Some(uid)).add()?;
+        self.objectstore
+            .create_symlink(&SubObject(parent, name), target)
+    }
+
+    /// Creates the special file 'name' in the private directory 'parent'.
+    pub fn mknod(
+        &self,
+        uid: UserId,
+        parent: &Identifier,
+        name: &OsStr,
+        special: SpecialFile,
+        permissions: libc::mode_t,
+    ) -> Result<()> {
+        self.permission_check(parent, Some(uid)).add()?;
+        self.objectstore
+            .create_special(&SubObject(parent, name), special, permissions)
+    }
+
+    /// Returns the metadata of the directory entry 'name' in 'parent' itself.
+    pub fn entry_metadata(
+        &self,
+        _uid: UserId,
+        parent: &Identifier,
+        name: &OsStr,
+    ) -> io::Result<Metadata> {
+        // TODO: permission checks against keys
+        self.objectstore.entry_metadata(&SubObject(parent, name))
+    }
+
+    /// Returns the names of the extended attributes of an object.
+    pub fn listxattr(&self, uid: UserId, identifier: &Identifier) -> Result<Vec<String>> {
+        self.permission_check(identifier, Some(uid)).read()?;
+        self.objectstore.list_xattrs(identifier)
+    }
+
+    /// Returns the value of an extended attribute.
+    pub fn getxattr(&self, uid: UserId, identifier: &Identifier, name: &str) -> Result<Vec<u8>> {
+        self.permission_check(identifier, Some(uid)).read()?;
+        self.objectstore.get_xattr(identifier, name)
+    }
+
+    /// Sets a user extended attribute.
+    pub fn setxattr(
+        &self,
+        uid: UserId,
+        identifier: &Identifier,
+        name: &str,
+        value: &[u8],
+    ) -> Result<()> {
+        self.permission_check(identifier, Some(uid)).setattr()?;
+        self.objectstore.set_xattr(identifier, name, value)
+    }
+
+    /// Removes a user extended attribute.
+    pub fn removexattr(&self, uid: UserId, identifier: &Identifier, name: &str) -> Result<()> {
+        self.permission_check(identifier, Some(uid)).setattr()?;
+        self.objectstore.remove_xattr(identifier, name)
+    }
+
+    /// Lists the snapshots of a versioned object, oldest first.
+    pub fn versions(&self, uid: UserId, identifier: &Identifier) -> Result<Vec<Version>> {
+        self.permission_check(identifier, Some(uid)).read()?;
+        self.objectstore.versions(identifier)
+    }
+
+    /// Returns the snapshot 'number' of a versioned object.
+    pub fn version(
+        &self,
+        uid: UserId,
+        identifier: &Identifier,
+        number: usize,
+    ) -> Result<Identifier> {
+        self.permission_check(identifier, Some(uid)).read()?;
+        self.objectstore.version(identifier, number)
+    }
+
+    /// Checks the final size of file 'name' in 'parent' against the directory rules and
+    /// snapshots versioned files, to be called when a written file gets closed.
+    pub fn close_check(
+        &self,
+        _uid: UserId,
+        parent: &Identifier,
+        name: &OsStr,
+        size: u64,
+    ) -> Result<()> {
+        let sub_object = SubObject(parent, name);
+        self.objectstore.rules_check_close(&sub_object, size)?;
+        self.objectstore
+            .snapshot_on_change(&self.objectstore.sub_object_id(&sub_object)?)
+    }
+
+    /// Renames the entry 'name' in 'parent' to 'new_name' in 'new_parent'. Renames within a
+    /// directory are checked against its rules and may retype the object. An existing
+    /// 'new_name' is replaced when 'replace' is set. 'new_parents' are the directories leading
+    /// to 'new_parent', see 'path_lookup_with_parents()'.
+    #[allow(clippy::too_many_arguments)]
+    pub fn rename(
+        &self,
+        uid: UserId,
+        parent: &Identifier,
+        name: &OsStr,
+        new_parent: &Identifier,
+        new_parents: &[Identifier],
+        new_name: &OsStr,
+        replace: bool,
+    ) -> Result<()> {
+        if parent == new_parent {
+            self.permission_check(parent, Some(uid)).rename()?;
+        } else {
+            self.permission_check(parent, Some(uid)).delete()?;
+            self.permission_check(new_parent, Some(uid)).add()?;
+            // replacing removes the old entry there
+            if replace
+                && self
+                    .objectstore
+                    .entry_exists(&SubObject(new_parent, new_name))?
+            {
+                self.permission_check(new_parent, Some(uid)).delete()?;
+            }
+        }
+        self.objectstore.rename_link(
+            &SubObject(parent, name),
+            &SubObject(new_parent, new_name),
+            new_parents,
+            replace,
+        )
     }
 }
@@ -0,0 +1,210 @@
+//! Extended attributes.
+//!
+//! Attributes in the 'user.' namespace are stored as 'xatr' metadata of the object, one
+//! attribute per line as 'NAME HEXVALUE'. Being metadata they travel with the object.
+//!
+//! The 'uberallfs.' namespace is read-only and generated from the object itself:
+//!
+//!  * uberallfs.id:: the identifier
+//!  * uberallfs.type:: the ObjectType
+//!  * uberallfs.sharing:: the SharingPolicy
+//!  * uberallfs.mutability:: the Mutability
+//!  * uberallfs.delete:: the DeleteMethod
+//!  * uberallfs.shared:: 'yes' when the object may leave this node, else 'no'
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::Path;
+
+use uberall::clap::ArgMatches;
+
+use crate::prelude::*;
+use crate::identifier_kind::*;
+use crate::objectstore::Meta;
+use crate::{Identifier, LockingMethod::*, Object, ObjectStore};
+
+/// Namespace of the attributes users may set
+pub const USER_XATTRS: &str = "user.";
+
+/// The read-only attributes describing the object itself
+pub const UBERALLFS_XATTRS: [&str; 6] = [
+    "uberallfs.id",
+    "uberallfs.type",
+    "uberallfs.sharing",
+    "uberallfs.mutability",
+    "uberallfs.delete",
+    "uberallfs.shared",
+];
+
+pub(crate) fn opt_xattr(dir: &OsStr, matches: &ArgMatches) -> Result<()> {
+    let objectstore = if matches.is_present("SET") || matches.is_present("REMOVE") {
+        ObjectStore::open(dir.as_ref(), WaitForLock)?
+    } else {
+        ObjectStore::open_shared(dir.as_ref(), WaitForLock)?
+    };
+
+    let path = matches.value_of_os("PATH").unwrap();
+    let (identifier, remaining) = objectstore.path_lookup(Path::new(path), None)?;
+    if !remaining.as_os_str().is_empty() {
+        return Err(ObjectStoreError::ObjectNotFound(path.into()).into());
+    }
+
+    if let Some(mut set) = matches.values_of("SET") {
+        let (name, value) = (set.next().unwrap(), set.next().unwrap());
+        objectstore.set_xattr(&identifier, name, value.as_bytes())?;
+    } else if let Some(name) = matches.value_of("REMOVE") {
+        objectstore.remove_xattr(&identifier, name)?;
+    } else if let Some(name) = matches.value_of("GET") {
+        println!(
+            "{}",
+            String::from_utf8_lossy(&objectstore.get_xattr(&identifier, name)?)
+        );
+    } else {
+        for name in objectstore.list_xattrs(&identifier)? {
+            println!(
+                "{}={}",
+                name,
+                String::from_utf8_lossy(&objectstore.get_xattr(&identifier, &name)?)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The user extended attributes of an object
+#[derive(Debug, Default)]
+pub struct Xattrs(BTreeMap<String, Vec<u8>>);
+
+impl Xattrs {
+    /// Parse from the textual representation
+    pub fn parse(text: &str) -> Result<Xattrs> {
+        let mut map = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let malformed =
+                || ObjectStoreError::ObjectStoreFatal(format!("malformed xattr line: {:?}", line));
+            let (name, value) = line.split_once(' ').ok_or_else(malformed)?;
+            if value.len() % 2 != 0 {
+                return Err(malformed().into());
+            }
+            let value = (0..value.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&value[i..i + 2], 16))
+                .collect::<std::result::Result<Vec<u8>, _>>()
+                .map_err(|_| malformed())?;
+            map.insert(name.into(), value);
+        }
+        Ok(Xattrs(map))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.0.get(name).map(Vec::as_slice)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+impl fmt::Display for Xattrs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        for (name, value) in &self.0 {
+            write!(f, "{} ", name)?;
+            for byte in value {
+                write!(f, "{:02x}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore {
+    /// Returns the user extended attributes of an object.
+    pub fn xattrs(&self, identifier: &Identifier) -> Result<Xattrs> {
+        match self.read_metadata(identifier, Meta::Xatr)? {
+            Some(data) => Xattrs::parse(std::str::from_utf8(&data)?),
+            None => Ok(Xattrs::default()),
+        }
+    }
+
+    /// Returns the names of all extended attributes of an object, the read-only ones first.
+    pub fn list_xattrs(&self, identifier: &Identifier) -> Result<Vec<String>> {
+        let mut names: Vec<String> = UBERALLFS_XATTRS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        names.extend(self.xattrs(identifier)?.names().map(String::from));
+        Ok(names)
+    }
+
+    /// Returns the value of the extended attribute 'name'.
+    pub fn get_xattr(&self, identifier: &Identifier, name: &str) -> Result<Vec<u8>> {
+        let value = match name {
+            "uberallfs.id" => Some(identifier.to_string()),
+            "uberallfs.type" => Some(format!("{:?}", identifier.object_type())),
+            "uberallfs.sharing" => Some(format!("{:?}", identifier.sharing_policy())),
+            "uberallfs.mutability" => Some(format!("{:?}", identifier.mutability())),
+            "uberallfs.delete" => {
+                Some(Object::from(identifier.clone()).delete_method().to_string())
+            }
+            "uberallfs.shared" => Some(String::from(
+                if identifier.sharing_policy() == SharingPolicy::Private {
+                    "no"
+                } else {
+                    "yes"
+                },
+            )),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(value.into_bytes()),
+            None => self
+                .xattrs(identifier)?
+                .get(name)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| ObjectStoreError::NoSuchXattr(name.into()).into()),
+        }
+    }
+
+    /// Sets the user extended attribute 'name'.
+    pub(crate) fn set_xattr(
+        &self,
+        identifier: &Identifier,
+        name: &str,
+        value: &[u8],
+    ) -> Result<()> {
+        check_user_xattr(name)?;
+        let mut xattrs = self.xattrs(identifier)?;
+        xattrs.0.insert(name.into(), value.into());
+        self.write_metadata(identifier, Meta::Xatr, xattrs.to_string().as_bytes())
+    }
+
+    /// Removes the user extended attribute 'name'.
+    pub(crate) fn remove_xattr(&self, identifier: &Identifier, name: &str) -> Result<()> {
+        check_user_xattr(name)?;
+        let mut xattrs = self.xattrs(identifier)?;
+        if xattrs.0.remove(name).is_none() {
+            return Err(ObjectStoreError::NoSuchXattr(name.into()).into());
+        }
+        if xattrs.0.is_empty() {
+            self.remove_metadata(identifier, Meta::Xatr)
+        } else {
+            self.write_metadata(identifier, Meta::Xatr, xattrs.to_string().as_bytes())
+        }
+    }
+}
+
+/// Only attributes in the user namespace can be changed.
+fn check_user_xattr(name: &str) -> Result<()> {
+    if name.starts_with(USER_XATTRS)
+        && name.len() > USER_XATTRS.len()
+        && !name.contains(char::is_whitespace)
+    {
+        Ok(())
+    } else if UBERALLFS_XATTRS.contains(&name) {
+        Err(ObjectStoreError::ReadOnlyXattr(name.into()).into())
+    } else {
+        Err(ObjectStoreError::UnsupportedXattr(name.into()).into())
+    }
+}
@@ -13,6 +13,7 @@ fn main() {
         .setting(AppSettings::SubcommandRequired)
         .subcommand(objectstore::optargs())
         .subcommand(fuse::optargs())
+        .subcommands(fuse::porcelain_optargs())
         .get_matches();
 
     uberall::daemon::init_daemonize(&matches);
@@ -21,6 +22,7 @@ fn main() {
     if let Err(err) = match matches.subcommand() {
         ("objectstore", Some(sub_m)) => objectstore::cmd(sub_m),
         ("fuse", Some(sub_m)) => fuse::cmd(sub_m),
+        (name, Some(sub_m)) if fuse::PORCELAIN.contains(&name) => fuse::porcelain(name, sub_m),
         (name, _) => {
             unimplemented!("subcommand '{}'", name)
         }
@@ -106,3 +106,1223 @@ fn mkdir_linked() {
 
     // TODO: assert test1 and test2 are the same objects
 }
+
+#[test]
+fn rules() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+    std::fs::write(
+        tempdir.path().join("videos.rules"),
+        "accept *.mkv *.mkv.part\nmax-size 1K\nretype *.mkv.part *.mkv public immutable\n",
+    )
+    .expect("written rules");
+    std::fs::write(tempdir.path().join("movie"), "movie content\n").expect("written file");
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /videos")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --set videos.rules /videos")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --test movie.mkv /videos")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --test movie.avi /videos")
+        .assert_exitcode(libc::EPERM);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --test movie.mkv --size 2K /videos")
+        .assert_exitcode(libc::EFBIG);
+    uberallfs
+        .call_argstr(
+            "-dd objectstore teststore/ rules --test movie.mkv --from movie.mkv.part /videos",
+        )
+        .assert_success()
+        .assert_stdout_utf8("retype to PublicAcl Immutable");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /videos/subdir")
+        .assert_exitcode(libc::EPERM);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ add-anonymous movie /videos/movie.mkv.part")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /videos/movie.mkv.part /videos/movie.mkv")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls -l /videos")
+        .assert_success()
+        .assert_stdout_utf8(" File PublicAcl Immutable 14 movie.mkv\n");
+}
+
+/// Generates a key in 'keyfile' with the objectstore in 'teststore/', returns its KeyId.
+fn generate_key(uberallfs: &TestCall, keyfile: &str) -> String {
+    let output = uberallfs.call_argstr(&format!(
+        "-dd objectstore teststore/ key --generate {}",
+        keyfile
+    ));
+    output.assert_success();
+    String::from_utf8(output.stdout)
+        .expect("utf8 key")
+        .trim()
+        .into()
+}
+
+#[test]
+fn revocations() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    let admin = generate_key(&uberallfs, "admin.key");
+    let other = generate_key(&uberallfs, "other.key");
+    let somekey = generate_key(&uberallfs, "somekey.key");
+
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --sign-with admin.key {}",
+            somekey
+        ))
+        .assert_failure();
+    // the first admin signs for itself, later ones need an admin
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --admin --sign-with other.key {}",
+            admin
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --admin --sign-with admin.key {}",
+            admin
+        ))
+        .assert_success();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --admin --sign-with other.key {}",
+            other
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --signer {} --signature sig --until 4102444800 {}",
+            admin, somekey
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ revoke --sign-with admin.key {}",
+            somekey
+        ))
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ revocations")
+        .assert_success()
+        .assert_stdout_utf8(&format!("revoke {} {}", somekey, admin));
+
+    // revoked keys can not be used in perm manifests
+    std::fs::write(
+        tempdir.path().join("somekey.perm"),
+        format!("admin {}\nquorum 1\n", somekey),
+    )
+    .expect("written manifest");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose somekey.perm /")
+        .assert_failure();
+}
+
+#[test]
+fn perm_quorum() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    let alice = generate_key(&uberallfs, "alice.key");
+    let bob = generate_key(&uberallfs, "bob.key");
+    let carol = generate_key(&uberallfs, "carol.key");
+    let mallory = generate_key(&uberallfs, "mallory.key");
+    std::fs::write(
+        tempdir.path().join("initial.perm"),
+        format!(
+            "admin {a}\nadmin {b}\nadmin {c}\nquorum 2\nacl read {a} {b} {c}\n",
+            a = alice,
+            b = bob,
+            c = carol
+        ),
+    )
+    .expect("written manifest");
+    std::fs::write(
+        tempdir.path().join("takeover.perm"),
+        format!("admin {m}\nquorum 1\nacl read {m}\n", m = mallory),
+    )
+    .expect("written manifest");
+
+    // the first manifest needs the quorum of its own admins
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose initial.perm /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with alice.key /")
+        .assert_success()
+        .assert_stdout_utf8("signed: 1 of 2");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with bob.key /")
+        .assert_success()
+        .assert_stdout_utf8("signed: 2 of 2");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_success()
+        .assert_stdout_utf8("quorum 2");
+
+    // only admins may abort
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose takeover.perm /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --abort /")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --abort --sign-with mallory.key /")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --abort --sign-with alice.key /")
+        .assert_success();
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose takeover.perm /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with mallory.key /")
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ perm --sign {} --signature sig /",
+            bob
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with bob.key /")
+        .assert_success()
+        .assert_stdout_utf8("signed: 1 of 2");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with carol.key /")
+        .assert_success()
+        .assert_stdout_utf8("signed: 2 of 2");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_success()
+        .assert_stdout_utf8(&format!("admin {}", mallory));
+}
+
+#[test]
+fn perm_replay() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    let alice = generate_key(&uberallfs, "alice.key");
+    let bob = generate_key(&uberallfs, "bob.key");
+    std::fs::write(
+        tempdir.path().join("first.perm"),
+        format!("admin {a}\nquorum 1\nacl read {a}\n", a = alice),
+    )
+    .expect("written manifest");
+    std::fs::write(
+        tempdir.path().join("second.perm"),
+        format!(
+            "admin {a}\nquorum 1\nacl read {a} {b}\n",
+            a = alice,
+            b = bob
+        ),
+    )
+    .expect("written manifest");
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose first.perm /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with alice.key /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --export first.signed /")
+        .assert_success();
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --propose second.perm /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --sign-with alice.key /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --finalize /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --export second.signed /")
+        .assert_success();
+
+    // the old manifest is a replay now, the current one is already known
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --receive first.signed /")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm --receive second.signed /")
+        .assert_success()
+        .assert_stdout_utf8(&format!("acl read {} {}", alice, bob));
+}
+
+#[test]
+fn add_anonymous() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+    std::fs::write(tempdir.path().join("dropbox.rules"), "add-anonymous\n").expect("written rules");
+    std::fs::write(tempdir.path().join("content"), "anonymous content\n").expect("written file");
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dropbox")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --set dropbox.rules /dropbox")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ add-anonymous content /dropbox/first")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ add-anonymous content /dropbox/first")
+        .assert_exitcode(libc::EEXIST);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ add-anonymous content /dropbox/second")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dropbox/subdir")
+        .assert_exitcode(libc::EPERM);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --test renamed --from first /dropbox")
+        .assert_exitcode(libc::EPERM);
+}
+
+#[test]
+fn chtype_chacl() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+    std::fs::write(tempdir.path().join("content"), "shared content\n").expect("written file");
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ add-anonymous content /dir/file")
+        .assert_success();
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype public_immutable /dir/file")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /dir/file")
+        .assert_success()
+        .assert_stdout_utf8("sharing PublicAcl");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype public_mutable /dir/file")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype shared /dir/file")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype public_immutable /dir")
+        .assert_failure();
+
+    // only shared objects have a URL
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show --url /dir/file")
+        .assert_success()
+        .assert_stdout_utf8("uberallfs://[^/]*/");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show --url /dir")
+        .assert_failure();
+
+    let alice = generate_key(&uberallfs, "alice.key");
+    let bob = generate_key(&uberallfs, "bob.key");
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ chacl +read {} --sign-with alice.key /dir/file",
+            bob
+        ))
+        .assert_success()
+        .assert_stdout_utf8("signed: 1 of 1");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm /dir/file")
+        .assert_success()
+        .assert_stdout_utf8(&format!("admin {}", alice));
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ perm /dir/file")
+        .assert_success()
+        .assert_stdout_utf8(&format!("acl read {}", bob));
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ chacl +admin {} --sign-with bob.key /dir/file",
+            bob
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ chacl +fly {} --sign-with alice.key /dir/file",
+            bob
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ chacl -admin {} --sign-with alice.key /dir/file",
+            alice
+        ))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd objectstore teststore/ chacl -read {} --sign-with alice.key /dir/file",
+            bob
+        ))
+        .assert_success()
+        .assert_stdout_utf8("signed: 1 of 1");
+}
+
+#[test]
+fn versions() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir --versioned /docs")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --snapshot /docs")
+        .assert_success()
+        .assert_stdout_utf8("^1 ");
+    // changes are snapshotted automatically
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /docs/subdir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --show 2 /docs")
+        .assert_success()
+        .assert_stdout_utf8("subdir");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --snapshot /docs")
+        .assert_success()
+        .assert_stdout_utf8("\n3 ");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --retain-count 1 /docs")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc")
+        .assert_success();
+    // numbers stay the same when older versions expire
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --show 3 /docs")
+        .assert_success()
+        .assert_stdout_utf8("subdir");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --show 2 /docs")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions --show 1 /docs")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ versions /")
+        .assert_failure();
+}
+
+#[test]
+fn mkdir_with_parent() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /other")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir --with-parent /bound")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /bound/subdir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir --link /bound /other/bound")
+        .assert_failure();
+}
+
+#[test]
+fn symlinks() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink ../elsewhere /dir/link")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink /absolute /dir/link")
+        .assert_failure()
+        .assert_stderr_utf8("exists already");
+    // private directories may point anywhere
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink /absolute /dir/absolute")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink .uberallfs./forged /dir/forged")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir/link/subdir")
+        .assert_failure();
+
+    // symlinks obey the rules of their directory
+    std::fs::write(tempdir.path().join("dirs.rules"), "only directories\n").expect("written rules");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --set dirs.rules /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink target /dir/other")
+        .assert_exitcode(libc::EPERM);
+
+    // symlinks in shared directories stay within them
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /pub --acl default")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink /absolute /pub/absolute")
+        .assert_failure()
+        .assert_stderr_utf8("escapes the directory");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink ../elsewhere /pub/up")
+        .assert_failure()
+        .assert_stderr_utf8("escapes the directory");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink sub/../../elsewhere /pub/hidden")
+        .assert_failure()
+        .assert_stderr_utf8("escapes the directory");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink sub/../inside /pub/inside")
+        .assert_success();
+
+    // directories with parent may go up to their declared parent, sharing them checks that
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /parent")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir --with-parent /parent/bound")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink ../sibling /parent/bound/up")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype public_immutable /parent/bound")
+        .assert_failure()
+        .assert_stderr_utf8("can not be converted");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ symlink ../../elsewhere /parent/bound/out")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ chtype public_immutable /parent/bound")
+        .assert_failure()
+        .assert_stderr_utf8("escapes the directory");
+}
+
+#[test]
+fn special_files() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mknod /dir/fifo p")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mknod /dir/socket s")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mknod /dir/fifo p")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mknod /dir/device c")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir/fifo/subdir")
+        .assert_failure();
+
+    // directories holding special files are not exported
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ send /dir")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ send --private /dir")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /other")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ send --private -r 1 /other")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ send --private -r 1 /")
+        .assert_failure();
+
+    // special files obey the rules of their directory
+    std::fs::write(tempdir.path().join("dirs.rules"), "only directories\n").expect("written rules");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ rules --set dirs.rules /other")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mknod /other/fifo p")
+        .assert_exitcode(libc::EPERM);
+}
+
+#[test]
+fn xattrs() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --set user.comment hello /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --get user.comment /dir")
+        .assert_success()
+        .assert_stdout_utf8("hello");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --get uberallfs.type /dir")
+        .assert_success()
+        .assert_stdout_utf8("Directory");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --set uberallfs.type File /dir")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --set trusted.comment hello /dir")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --remove user.comment /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ xattr --get user.comment /dir")
+        .assert_failure();
+}
+
+#[test]
+fn status() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /dir")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ status")
+        .assert_success()
+        .assert_stdout_utf8("objects Directory 2");
+}
+
+#[test]
+fn porcelain_unmounted() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd show-id teststore/")
+        .assert_failure();
+}
+
+#[test]
+fn insta() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    let alice = generate_key(&uberallfs, "alice.key");
+
+    // shared roots need a creator
+    uberallfs
+        .call_argstr("-dd insta --shared --no-mount shared/")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd insta --shared --key alice.key --no-mount shared/")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore shared/ show /")
+        .assert_success()
+        .assert_stdout_utf8("sharing PublicAcl");
+    uberallfs
+        .call_argstr("-dd objectstore shared/ perm /")
+        .assert_success()
+        .assert_stdout_utf8(&format!("admin {}", alice));
+    // existing objectstores are reused
+    uberallfs
+        .call_argstr("-dd insta --shared --key alice.key --no-mount shared/")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd insta --no-mount private/")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd insta --shared --key alice.key --no-mount private/")
+        .assert_failure();
+
+    let output = uberallfs.call_argstr("-dd objectstore shared/ show --url /");
+    output.assert_success();
+    let url = String::from_utf8(output.stdout).expect("utf8 url");
+    let url = url.trim();
+    uberallfs
+        .call_argstr(&format!("-dd insta --from {} --no-mount shared/", url))
+        .assert_success();
+    uberallfs
+        .call_argstr(&format!(
+            "-dd insta --from {} --shared --no-mount shared/",
+            url
+        ))
+        .assert_failure();
+    // fetching from other nodes is not available yet, nothing gets initialized
+    uberallfs
+        .call_argstr(&format!("-dd insta --from {} --no-mount private/", url))
+        .assert_failure();
+    uberallfs
+        .call_argstr(&format!("-dd insta --from {} --no-mount fresh/", url))
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore fresh/ init")
+        .assert_success();
+}
+
+#[test]
+fn mount_umount() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd --background fuse teststore/ mount")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ umount")
+        .assert_success();
+    // the daemon persisted its inodes on shutdown, the store is visible again
+    assert!(tempdir.path().join("teststore/objects/inodes").exists());
+    uberallfs
+        .call_argstr("-dd fuse teststore/ umount")
+        .assert_failure();
+
+    // mounting again loads them
+    uberallfs
+        .call_argstr("-dd --background fuse teststore/ mount")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ umount --lazy")
+        .assert_success();
+}
+
+#[test]
+fn umount_not_mounted() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ umount")
+        .assert_failure();
+}
+
+#[test]
+fn mount_options_invalid() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ mount -o bogus")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ mount -o rw,attr_timeout=soon")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd fuse teststore/ mount -o allow_other,allow_root")
+        .assert_failure();
+}
+
+#[test]
+fn gc_pins() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /backup")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ pin --set backups /backup")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ pin")
+        .assert_success()
+        .assert_stdout_utf8("backups ");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ pin --set ../escape /backup")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --root /backup")
+        .assert_success()
+        .assert_stdout_utf8("deleted 0");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --root /missing")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ pin --set backups --remove /backup")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ pin --set backups --remove /backup")
+        .assert_failure();
+}
+
+#[test]
+fn gc_parallel() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b/c")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --threads 4 --memory-limit 0 --progress")
+        .assert_success()
+        .assert_stdout_utf8("kept 4");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a/b/c")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --threads many")
+        .assert_failure();
+
+    // a dry run reports the unreachable objects and keeps them
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ unlink /a/b/c")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --dry-run --threads 4")
+        .assert_success()
+        .assert_stdout_utf8("would delete immediate: ");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc --threads 4")
+        .assert_success()
+        .assert_stdout_utf8("deleted 1");
+}
+
+#[test]
+fn gc_concurrent_race() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc-race --writers 4 --rounds 60")
+        .assert_success()
+        .assert_stdout_utf8("ok 244 directories");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /race3/m59")
+        .assert_success();
+}
+
+#[test]
+fn lock_status() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ lock --status")
+        .assert_success()
+        .assert_stdout_utf8("unlocked");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /locked")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /locked")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ lock --status")
+        .assert_success()
+        .assert_stdout_utf8("unlocked");
+
+    // hold the lock in the background
+    let mut holder = EXECUTABLES
+        .command("uberallfs")
+        .current_dir(&tempdir)
+        .args(&["-dd", "objectstore", "teststore/", "lock"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawned lock holder");
+    let status = format!("pid {} exclusive", holder.id());
+    let mut tries = 0;
+    while !String::from_utf8_lossy(
+        &uberallfs
+            .call_argstr("-dd objectstore teststore/ lock --status")
+            .stdout,
+    )
+    .contains(&status)
+    {
+        tries += 1;
+        assert!(tries < 100, "lock holder did not show up");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ lock --status")
+        .assert_success()
+        .assert_stdout_utf8(&status);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ lock")
+        .assert_failure()
+        .assert_stderr_utf8(&format!("locked by {}", status));
+
+    // interrupting the holder releases the lock and removes its record
+    unsafe {
+        libc::kill(holder.id() as libc::pid_t, libc::SIGINT);
+    }
+    assert!(holder.wait().expect("lock holder exited").success());
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ lock --status")
+        .assert_success()
+        .assert_stdout_utf8("unlocked");
+    assert!(
+        std::fs::read_dir(tempdir.path().join("teststore/objects/locks"))
+            .expect("read locks")
+            .next()
+            .is_none()
+    );
+}
+
+#[test]
+fn get_id() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ get-id /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ get-id --abbrev /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ get-id --paths /a/b")
+        .assert_success()
+        .assert_stdout_utf8("/a/b");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ get-id /missing")
+        .assert_failure();
+}
+
+#[test]
+fn show_details() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a")
+        .assert_success()
+        .assert_stdout_utf8("type Directory")
+        .assert_stdout_utf8("entries 1");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show --json /a/b")
+        .assert_success()
+        .assert_stdout_utf8(r#""path":"/a/b""#)
+        .assert_stdout_utf8(r#""entries":0"#);
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show --recursive /")
+        .assert_success()
+        .assert_stdout_utf8("    b ");
+}
+
+#[test]
+fn ls() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /c")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls /")
+        .assert_success()
+        .assert_stdout_utf8("a\nc\n");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls -R /")
+        .assert_success()
+        .assert_stdout_utf8("a\na/b\nc\n");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls -l /a")
+        .assert_success()
+        .assert_stdout_utf8(" Directory Private Mutable ");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls -R --type file /")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls /missing")
+        .assert_failure();
+}
+
+#[test]
+fn link_unlink_mv() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /c")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ln /a/b /c/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ln /a/b /c/.uberallfs.b")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ln /a/b /c/b")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ unlink /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a/b")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /c/b /c/renamed")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /c/renamed /a/moved")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ls -R /")
+        .assert_success()
+        .assert_stdout_utf8("a\na/moved\nc\n");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /a /c")
+        .assert_failure()
+        .assert_stderr_utf8("/c\" exists already");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /c /a/moved")
+        .assert_failure()
+        .assert_stderr_utf8("/a/moved\" exists already");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc")
+        .assert_success()
+        .assert_stdout_utf8("deleted 0");
+}
+
+#[test]
+fn named_roots() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /home")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --set /home --name home")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ unlink /home")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --show")
+        .assert_success()
+        .assert_stdout_utf8("home ");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc")
+        .assert_success()
+        .assert_stdout_utf8("deleted 0");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --set /home --name .bad")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /newroot")
+        .assert_success();
+    // an existing named root is not replaced
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --set /newroot --keep home")
+        .assert_failure()
+        .assert_stderr_utf8("\"home\" exists already");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --set /newroot")
+        .assert_success()
+        .assert_stdout_utf8("kept previous");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc")
+        .assert_success()
+        .assert_stdout_utf8("deleted 0");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ root --remove home")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ gc")
+        .assert_success()
+        .assert_stdout_utf8("deleted 1");
+}
+
+#[test]
+fn path_parents() {
+    let mut uberallfs = TestCall::new(&EXECUTABLES, "uberallfs");
+    let tempdir = TempDir::new().expect("created tempdir");
+    uberallfs.current_dir(&tempdir);
+
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ init")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir -p /a/b")
+        .assert_success();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a/b/..")
+        .assert_success()
+        .assert_stdout_utf8("entries 1");
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /..")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a/missing/../b")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mkdir /a/missing/../x")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a/x")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ ln /a /a/b/loop")
+        .assert_success();
+
+    // the cycle is cut at its first occurrence, going up twice from there ends at the root
+    let get_id = |path: &str| {
+        let output = uberallfs.call_argstr(&format!("-dd objectstore teststore/ get-id {}", path));
+        output.assert_success();
+        String::from_utf8(output.stdout).expect("utf8 identifier")
+    };
+    assert_eq!(get_id("/a/b/loop/b/../.."), get_id("/"));
+    assert_ne!(get_id("/a/b/loop/b/../.."), get_id("/a/b"));
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ mv /a /a/b/moved")
+        .assert_failure();
+    uberallfs
+        .call_argstr("-dd objectstore teststore/ show /a")
+        .assert_success();
+}
@@ -16,7 +16,7 @@ pub use serde;
 pub use syslog;
 pub use thiserror;
 pub use cachedb;
-pub use uberall::UberAll;
+pub use uberall::{SerialNonce, UberAll};
 use prelude::*;
 
 pub fn error_to_exitcode(error: Box<dyn Error>) -> i32 {
@@ -26,8 +26,28 @@ impl UberAll {
         self.rng.lock().gen()
     }
 
+    /// Returns a new initial serial nonce. Serial nonces start at a random value which leaves
+    /// enough headroom for at least 2^32 increments.
+    pub fn serial_nonce(&self) -> SerialNonce {
+        SerialNonce(self.rng_gen::<u128>() % (u128::MAX - u64::MAX as u128))
+    }
+
+    /// Returns the serial nonce following 'nonce'. It is incremented by a random amount, thus
+    /// newer nonces compare greater without leaking how often they got incremented. Returns
+    /// 'None' when the nonce space is exhausted.
+    pub fn serial_nonce_next(&self, nonce: SerialNonce) -> Option<SerialNonce> {
+        nonce
+            .0
+            .checked_add(self.rng_gen::<u32>() as u128 + 1)
+            .map(SerialNonce)
+    }
+
     // PLANNED: provide multiple mutex<queues> of u8 filled with randoms by a thread
     // which get woken up when any queue hits lowwater. trylock these round
     // robin to acquire randoms. keep start index for roundrobin in a atomic
     // counter
 }
+
+/// Monotonically growing random numbers, see 'UberAll::serial_nonce()'
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct SerialNonce(pub u128);